@@ -0,0 +1,318 @@
+use log::{error, info, warn};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often a running program is checked for exit
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Placeholders that only the player can fill in
+const NOW_PLAYING_PLACEHOLDERS: [&str; 4] = ["{title}", "{artist}", "{album}", "{status}"];
+
+/// An external program to run for a `RunCommand` action
+#[derive(Debug, Clone)]
+pub struct CommandSpec {
+    pub program: String,
+    pub args: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    pub cwd: Option<PathBuf>,
+    pub timeout: Duration,
+    pub shell: bool,
+}
+
+impl CommandSpec {
+    /// Check whether the command refers to now-playing placeholders
+    pub fn needs_now_playing(&self) -> bool {
+        let program = self.fills_program().then_some(&self.program);
+        let args = if self.fills_args() { &self.args[..] } else { &[] };
+        program
+            .into_iter()
+            .chain(args)
+            .chain(self.env.values())
+            .any(|text| {
+                NOW_PLAYING_PLACEHOLDERS
+                    .iter()
+                    .any(|placeholder| text.contains(placeholder))
+            })
+    }
+
+    /// Whether placeholders are replaced in the program. A shell command line
+    /// is left as it is, or the shell would run whatever a track title contains.
+    fn fills_program(&self) -> bool {
+        !self.shell
+    }
+
+    /// Whether placeholders are replaced in the arguments: `sh` gets them as
+    /// `$1`, `$2`, ..., but `cmd` parses them as part of the command line
+    fn fills_args(&self) -> bool {
+        !(self.shell && cfg!(windows))
+    }
+
+    /// Replace `{name}` placeholders in the program, arguments and environment.
+    /// Shell commands only get the values as arguments (not on Windows) and
+    /// environment variables.
+    pub fn fill(&self, values: &[(&str, String)]) -> CommandSpec {
+        let fill = |text: &String| {
+            values.iter().fold(text.clone(), |text, (name, value)| {
+                text.replace(&format!("{{{}}}", name), value)
+            })
+        };
+
+        CommandSpec {
+            program: if self.fills_program() {
+                fill(&self.program)
+            } else {
+                self.program.clone()
+            },
+            args: if self.fills_args() {
+                self.args.iter().map(fill).collect()
+            } else {
+                self.args.clone()
+            },
+            env: self
+                .env
+                .iter()
+                .map(|(name, value)| (name.clone(), fill(value)))
+                .collect(),
+            cwd: self.cwd.clone(),
+            timeout: self.timeout,
+            shell: self.shell,
+        }
+    }
+
+    fn command(&self) -> Command {
+        let mut command = if self.shell {
+            // The program is a command line, arguments become $1, $2, ...
+            #[cfg(windows)]
+            {
+                // Delayed expansion, so `!TITLE!` is expanded after the line is parsed
+                let mut command = Command::new("cmd");
+                command.arg("/V:ON").arg("/C").arg(&self.program).args(&self.args);
+                command
+            }
+            #[cfg(not(windows))]
+            {
+                let mut command = Command::new("sh");
+                command.arg("-c").arg(&self.program).arg("sh").args(&self.args);
+                command
+            }
+        } else {
+            let mut command = Command::new(&self.program);
+            command.args(&self.args);
+            command
+        };
+
+        command.envs(&self.env);
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        command
+    }
+}
+
+/// Read a pipe to the end on its own thread, so a chatty program can't block on a full pipe
+fn collect<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut output = String::new();
+        if let Some(mut pipe) = pipe {
+            let mut bytes = Vec::new();
+            let _ = pipe.read_to_end(&mut bytes);
+            output = String::from_utf8_lossy(&bytes).into_owned();
+        }
+        output
+    })
+}
+
+/// Wait for the program to exit, killing it after the timeout
+fn wait(child: &mut Child, timeout: Duration) -> Option<std::process::ExitStatus> {
+    let started = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return Some(status),
+            Ok(None) if started.elapsed() >= timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(e) => {
+                error!("Failed to wait for command: {}", e);
+                return None;
+            }
+        }
+    }
+}
+
+/// Log captured output line by line
+fn log_output(program: &str, stream: &str, output: &str) {
+    for line in output.lines().filter(|line| !line.trim().is_empty()) {
+        info!("[{} {}] {}", program, stream, line);
+    }
+}
+
+/// Runs external programs in the background, at most `limit` at a time
+#[derive(Clone, Default)]
+pub struct CommandRunner {
+    running: Arc<AtomicUsize>,
+}
+
+impl CommandRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start the program without waiting for it, returns false if `limit` programs are running
+    pub fn spawn(&self, spec: CommandSpec, limit: usize) -> bool {
+        // Reserve a slot, so a hung program bound to a repeatedly pressed key
+        // can't pile up processes
+        let reserved = self
+            .running
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |running| {
+                (running < limit).then_some(running + 1)
+            });
+        if reserved.is_err() {
+            warn!(
+                "Not running '{}': {} commands are already running",
+                spec.program, limit
+            );
+            return false;
+        }
+
+        let running = self.running.clone();
+        thread::spawn(move || {
+            run(&spec);
+            running.fetch_sub(1, Ordering::SeqCst);
+        });
+        true
+    }
+}
+
+/// Run the program and log its outcome
+fn run(spec: &CommandSpec) {
+    info!("Running command: {} {:?}", spec.program, spec.args);
+
+    let mut child = match spec.command().spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            error!("Failed to run '{}': {}", spec.program, e);
+            return;
+        }
+    };
+
+    let stdout = collect(child.stdout.take());
+    let stderr = collect(child.stderr.take());
+
+    let status = wait(&mut child, spec.timeout);
+
+    // Children of a killed program may still hold the pipes open,
+    // only wait for the output of programs that exited by themselves
+    if status.is_some() {
+        log_output(&spec.program, "stdout", &stdout.join().unwrap_or_default());
+        log_output(&spec.program, "stderr", &stderr.join().unwrap_or_default());
+    }
+
+    match status {
+        Some(status) if status.success() => info!("Command '{}' finished", spec.program),
+        Some(status) => warn!("Command '{}' failed: {}", spec.program, status),
+        None => warn!(
+            "Command '{}' was killed after {} seconds",
+            spec.program,
+            spec.timeout.as_secs()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(program: &str, args: &[&str], shell: bool) -> CommandSpec {
+        CommandSpec {
+            program: program.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            env: BTreeMap::from([("TITLE".to_string(), "{title}".to_string())]),
+            cwd: None,
+            timeout: Duration::from_secs(5),
+            shell,
+        }
+    }
+
+    fn values() -> Vec<(&'static str, String)> {
+        vec![
+            ("key", "F3".to_string()),
+            ("title", "$(rm -rf ~)".to_string()),
+        ]
+    }
+
+    #[test]
+    fn fills_placeholders() {
+        let filled = spec("notify-{key}", &["{title}", "{album}"], false).fill(&values());
+        assert_eq!(filled.program, "notify-F3");
+        assert_eq!(filled.args, ["$(rm -rf ~)", "{album}"]);
+        assert_eq!(filled.env["TITLE"], "$(rm -rf ~)");
+    }
+
+    #[test]
+    fn shell_command_lines_are_not_filled() {
+        let filled = spec("echo {title} \"$1\" \"$TITLE\"", &["{title}"], true).fill(&values());
+        assert_eq!(filled.program, "echo {title} \"$1\" \"$TITLE\"");
+        assert_eq!(filled.env["TITLE"], "$(rm -rf ~)");
+        if cfg!(windows) {
+            assert_eq!(filled.args, ["{title}"]);
+        } else {
+            assert_eq!(filled.args, ["$(rm -rf ~)"]);
+        }
+    }
+
+    #[test]
+    fn asks_the_player_only_for_its_placeholders() {
+        let mut command = spec("notify-send", &["{key}"], false);
+        command.env.clear();
+        assert!(!command.needs_now_playing());
+        command.args.push("{artist}".to_string());
+        assert!(command.needs_now_playing());
+
+        // Not filled in a shell command line, so the player isn't needed for it
+        let mut command = spec("echo {status}", &[], true);
+        command.env.clear();
+        assert!(!command.needs_now_playing());
+        command.env.insert("STATUS".to_string(), "{status}".to_string());
+        assert!(command.needs_now_playing());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn runs_at_most_limit_commands() {
+        let runner = CommandRunner::new();
+        assert!(runner.spawn(spec("sleep", &["1"], false), 1));
+        assert!(!runner.spawn(spec("sleep", &["1"], false), 1));
+
+        // The slot is free again once the program has exited
+        let started = Instant::now();
+        while runner.running.load(Ordering::SeqCst) > 0 {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            thread::sleep(POLL_INTERVAL);
+        }
+        assert!(runner.spawn(spec("true", &[], false), 1));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn kills_programs_after_the_timeout() {
+        let mut child = spec("sleep", &["10"], false).command().spawn().unwrap();
+        let started = Instant::now();
+        assert_eq!(wait(&mut child, Duration::from_millis(100)), None);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        // Reaped already
+        assert!(child.try_wait().unwrap().is_some());
+    }
+}
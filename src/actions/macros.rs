@@ -0,0 +1,245 @@
+use crate::config::{Config, MediaAction, Step};
+use crate::ui::AppMessage;
+use log::info;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{ActionRunner, Trigger};
+
+/// How often a delay checks whether the macro was cancelled
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Progress of a running macro, reported to the UI
+#[derive(Debug, Clone, PartialEq)]
+pub enum MacroProgress {
+    /// Step `index` (counting from 0) of `total` has started
+    Step {
+        index: usize,
+        total: usize,
+        description: String,
+    },
+    Finished,
+    Cancelled,
+}
+
+/// Cancel flags of the macros that are running, by what started them
+#[derive(Clone, Default)]
+pub struct RunningMacros(Arc<Mutex<HashMap<Trigger, Arc<AtomicBool>>>>);
+
+/// Start the macro of `trigger` on its own thread, or cancel it if it is running
+pub fn start(runner: &ActionRunner, trigger: Trigger, steps: Vec<Step>, config: Config) {
+    let mut running = runner.macros.0.lock().unwrap();
+
+    if let Some(cancel) = running.remove(&trigger) {
+        info!("Cancelling macro for {}", trigger);
+        cancel.store(true, Ordering::SeqCst);
+        return;
+    }
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    running.insert(trigger, cancel.clone());
+    drop(running);
+
+    let runner = runner.clone();
+    thread::spawn(move || {
+        let steps = flatten(steps);
+        info!("Running macro for {} ({} steps)", trigger, steps.len());
+
+        let completed = run_steps(&runner, trigger, &steps, &config, &cancel);
+
+        // Forget the macro unless a new run has already replaced it
+        let mut running = runner.macros.0.lock().unwrap();
+        if running
+            .get(&trigger)
+            .is_some_and(|flag| Arc::ptr_eq(flag, &cancel))
+        {
+            running.remove(&trigger);
+        }
+        drop(running);
+
+        let progress = if completed {
+            info!("Macro for {} finished", trigger);
+            MacroProgress::Finished
+        } else {
+            info!("Macro for {} cancelled", trigger);
+            MacroProgress::Cancelled
+        };
+        let _ = runner
+            .app_sender
+            .send(AppMessage::MacroProgress(trigger, progress));
+    });
+}
+
+/// Replace nested macros by their steps, so one cancel flag covers everything
+fn flatten(steps: Vec<Step>) -> Vec<Step> {
+    steps
+        .into_iter()
+        .flat_map(|step| match step {
+            Step::Action(MediaAction::Macro(nested)) => flatten(nested),
+            step => vec![step],
+        })
+        .collect()
+}
+
+/// Human readable description of a step
+fn describe(step: &Step) -> String {
+    match step {
+        Step::Action(action) => action.name().to_string(),
+        Step::Delay(ms) => format!("Wait {} ms", ms),
+    }
+}
+
+/// Run the steps in order. Returns false if the macro was cancelled.
+fn run_steps(
+    runner: &ActionRunner,
+    trigger: Trigger,
+    steps: &[Step],
+    config: &Config,
+    cancel: &AtomicBool,
+) -> bool {
+    for (index, step) in steps.iter().enumerate() {
+        if cancel.load(Ordering::SeqCst) {
+            return false;
+        }
+
+        let _ = runner.app_sender.send(AppMessage::MacroProgress(
+            trigger,
+            MacroProgress::Step {
+                index,
+                total: steps.len(),
+                description: describe(step),
+            },
+        ));
+
+        match step {
+            Step::Action(action) => runner.execute(trigger, action, config),
+            Step::Delay(ms) => {
+                if !sleep(Duration::from_millis(*ms), cancel) {
+                    return false;
+                }
+            }
+        }
+    }
+
+    !cancel.load(Ordering::SeqCst)
+}
+
+/// Sleep for the duration. Returns false if cancelled meanwhile.
+fn sleep(duration: Duration, cancel: &AtomicBool) -> bool {
+    let started = Instant::now();
+
+    while started.elapsed() < duration {
+        if cancel.load(Ordering::SeqCst) {
+            return false;
+        }
+        thread::sleep(CANCEL_CHECK_INTERVAL.min(duration.saturating_sub(started.elapsed())));
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hotkey_listener::ListenerControl;
+    use crate::media_control::MediaController;
+    use crossbeam_channel::{unbounded, Receiver};
+    use rdev::Key;
+
+    const TRIGGER: Trigger = Trigger::Key(Key::F1);
+
+    fn runner() -> (ActionRunner, Receiver<AppMessage>) {
+        let (sender, receiver) = unbounded();
+        let config = Arc::new(Mutex::new(Config::default()));
+        let runner = ActionRunner::new(
+            MediaController::new(),
+            config,
+            ListenerControl::default(),
+            sender,
+        );
+        (runner, receiver)
+    }
+
+    /// Messages until the macro has ended, with the time each arrived
+    fn messages(receiver: &Receiver<AppMessage>) -> Vec<(AppMessage, Instant)> {
+        let mut messages = Vec::new();
+        loop {
+            let message = receiver
+                .recv_timeout(Duration::from_secs(5))
+                .expect("the macro didn't end");
+            let ended = matches!(
+                message,
+                AppMessage::MacroProgress(_, MacroProgress::Finished | MacroProgress::Cancelled)
+            );
+            messages.push((message, Instant::now()));
+            if ended {
+                return messages;
+            }
+        }
+    }
+
+    #[test]
+    fn nested_macros_are_flattened() {
+        let inner = vec![Step::Delay(1), Step::Action(MediaAction::Stop)];
+        let steps = vec![
+            Step::Action(MediaAction::Next),
+            Step::Action(MediaAction::Macro(vec![
+                Step::Action(MediaAction::Macro(inner)),
+                Step::Delay(2),
+            ])),
+        ];
+        assert_eq!(
+            flatten(steps),
+            [
+                Step::Action(MediaAction::Next),
+                Step::Delay(1),
+                Step::Action(MediaAction::Stop),
+                Step::Delay(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn waits_between_steps() {
+        let (runner, receiver) = runner();
+        let steps = vec![
+            Step::Action(MediaAction::ToggleEnabled),
+            Step::Delay(200),
+            Step::Action(MediaAction::ToggleEnabled),
+        ];
+        let started = Instant::now();
+        start(&runner, TRIGGER, steps, Config::default());
+
+        // Messages are timed when received, the first one may be received late
+        let toggled: Vec<Instant> = messages(&receiver)
+            .into_iter()
+            .filter(|(message, _)| matches!(message, AppMessage::Enabled(_)))
+            .map(|(_, at)| at)
+            .collect();
+        assert_eq!(toggled.len(), 2);
+        assert!(toggled[1] - started >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn same_trigger_cancels_the_running_macro() {
+        let (runner, receiver) = runner();
+        let steps = vec![Step::Delay(5000), Step::Action(MediaAction::ToggleEnabled)];
+        let started = Instant::now();
+        start(&runner, TRIGGER, steps.clone(), Config::default());
+        start(&runner, TRIGGER, steps, Config::default());
+
+        let messages = messages(&receiver);
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(matches!(
+            messages.last().unwrap().0,
+            AppMessage::MacroProgress(TRIGGER, MacroProgress::Cancelled)
+        ));
+        assert!(!messages
+            .iter()
+            .any(|(message, _)| matches!(message, AppMessage::Enabled(_))));
+        assert!(runner.macros.0.lock().unwrap().is_empty());
+    }
+}
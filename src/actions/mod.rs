@@ -0,0 +1,344 @@
+mod command;
+mod macros;
+mod script;
+mod sleep_timer;
+
+use crate::config::{Config, MediaAction};
+use crate::hotkey_listener::ListenerControl;
+use crate::media_control::MediaController;
+use crate::ui::AppMessage;
+use crossbeam_channel::Sender;
+use log::{info, warn};
+use rdev::Key;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use command::{CommandRunner, CommandSpec};
+use macros::RunningMacros;
+use script::ScriptCache;
+
+pub use macros::MacroProgress;
+pub use sleep_timer::{format_duration, SleepTimer, MAX_MINUTES};
+
+/// What started an action
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Trigger {
+    Key(Key),
+    /// Entry of `Config::schedule`, by index
+    Schedule(usize),
+}
+
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Trigger::Key(key) => write!(f, "{:?}", key),
+            Trigger::Schedule(index) => write!(f, "schedule #{}", index + 1),
+        }
+    }
+}
+
+/// Executes configured actions
+#[derive(Clone)]
+pub struct ActionRunner {
+    media_controller: Arc<MediaController>,
+    commands: CommandRunner,
+    macros: RunningMacros,
+    scripts: ScriptCache,
+    sleep_timer: SleepTimer,
+    /// Shared configuration, for actions that change it
+    config: Arc<Mutex<Config>>,
+    listener: ListenerControl,
+    app_sender: Sender<AppMessage>,
+}
+
+impl ActionRunner {
+    pub fn new(
+        media_controller: MediaController,
+        config: Arc<Mutex<Config>>,
+        listener: ListenerControl,
+        app_sender: Sender<AppMessage>,
+    ) -> Self {
+        let media_controller = Arc::new(media_controller);
+        ActionRunner {
+            sleep_timer: SleepTimer::new(media_controller.clone(), app_sender.clone()),
+            media_controller,
+            commands: CommandRunner::new(),
+            macros: RunningMacros::default(),
+            scripts: ScriptCache::default(),
+            config,
+            listener,
+            app_sender,
+        }
+    }
+
+    pub fn sleep_timer(&self) -> &SleepTimer {
+        &self.sleep_timer
+    }
+
+    /// Execute an action started by `trigger`
+    pub fn run(&self, trigger: Trigger, action: &MediaAction, config: &Config) {
+        self.media_controller.select_backend(&config.active().backend);
+        self.execute(trigger, action, config);
+    }
+
+    /// Execute an action on the current thread
+    fn execute(&self, trigger: Trigger, action: &MediaAction, config: &Config) {
+        let media = &self.media_controller;
+
+        match action {
+            MediaAction::PlayPause => media.play_pause(),
+            MediaAction::Next => media.next(),
+            MediaAction::Previous => media.previous(),
+            MediaAction::VolumeUp => media.volume_up(),
+            MediaAction::VolumeDown => media.volume_down(),
+            MediaAction::Stop => media.stop(),
+            MediaAction::AppVolume { app, step } => media.app_volume(app, *step),
+            MediaAction::AppMute { app } => media.app_mute(app),
+            MediaAction::CycleOutputDevice => media.cycle_output_device(&config.output_devices),
+            MediaAction::SetOutputDevice(name) => {
+                media.set_output_device(name, &config.output_devices)
+            }
+            MediaAction::Seek(seconds) => media.seek(*seconds),
+            MediaAction::SpeedUp => media.speed_up(),
+            MediaAction::SpeedDown => media.speed_down(),
+            MediaAction::ChapterNext => media.chapter_next(),
+            MediaAction::ChapterPrevious => media.chapter_previous(),
+            MediaAction::ToggleSubtitles => media.toggle_subtitles(),
+            MediaAction::RunCommand {
+                program,
+                args,
+                env,
+                cwd,
+                timeout,
+                shell,
+            } => {
+                let spec = CommandSpec {
+                    program: program.clone(),
+                    args: args.clone(),
+                    env: env.clone(),
+                    cwd: cwd.clone(),
+                    timeout: Duration::from_secs(*timeout),
+                    shell: *shell,
+                };
+                self.run_command(trigger, action, spec, config);
+                // Programs run in the background, there is no new track to report
+                return;
+            }
+            MediaAction::Script(source) => {
+                // Scripts run on their own thread, errors are reported to the UI
+                script::start(self, trigger, source.clone(), config);
+                return;
+            }
+            MediaAction::SleepTimer(minutes) => {
+                // The configuration isn't checked before it is used
+                if *minutes > MAX_MINUTES {
+                    warn!(
+                        "Sleep timer: {} minutes is too long, adding {}",
+                        minutes, MAX_MINUTES
+                    );
+                }
+                self.sleep_timer.extend((*minutes).min(MAX_MINUTES));
+                return;
+            }
+            MediaAction::CancelSleepTimer => {
+                self.sleep_timer.cancel();
+                return;
+            }
+            MediaAction::SwitchProfile(name) => {
+                self.switch_profile(Some(name));
+                return;
+            }
+            MediaAction::CycleProfile => {
+                self.switch_profile(None);
+                return;
+            }
+            MediaAction::ToggleEnabled => {
+                self.set_enabled(!self.is_enabled());
+                return;
+            }
+            MediaAction::Macro(steps) => {
+                // Macros run on their own thread, so the listener is never blocked
+                macros::start(self, trigger, steps.clone(), config.clone());
+                return;
+            }
+        }
+
+        // Let the UI show what is playing now
+        let _ = self
+            .app_sender
+            .send(AppMessage::NowPlaying(media.now_playing()));
+    }
+
+    /// Make the profile `name` active, or the next one if `None`
+    pub fn switch_profile(&self, name: Option<&str>) {
+        let mut config = self.config.lock().unwrap();
+
+        let name = match name {
+            Some(name) if config.profiles.contains_key(name) => name.to_string(),
+            Some(name) => {
+                warn!("Profile '{}' does not exist", name);
+                return;
+            }
+            None => match config.next_profile(&config.active_profile) {
+                Some(name) => name.clone(),
+                None => return,
+            },
+        };
+
+        if config.active_profile == name {
+            return;
+        }
+
+        info!("Switching to profile '{}'", name);
+        config.active_profile = name.clone();
+        drop(config);
+
+        self.listener.rebind();
+        let _ = self.app_sender.send(AppMessage::ProfileChanged(name));
+    }
+
+    /// Whether the hotkeys are handled, i.e. not paused
+    pub fn is_enabled(&self) -> bool {
+        !self.listener.is_paused()
+    }
+
+    /// Pause or resume the hotkeys
+    pub fn set_enabled(&self, enabled: bool) {
+        if self.listener.set_paused(!enabled) {
+            let _ = self.app_sender.send(AppMessage::Enabled(enabled));
+        }
+    }
+
+    fn run_command(&self, trigger: Trigger, action: &MediaAction, spec: CommandSpec, config: &Config) {
+        info!("Running command for {}", trigger);
+
+        let mut values = vec![
+            ("key", trigger.to_string()),
+            ("action", action.name().to_string()),
+        ];
+
+        // Only ask the player when the command uses its fields
+        if spec.needs_now_playing() {
+            let now_playing = self.media_controller.now_playing().unwrap_or_default();
+            let status = if now_playing.title.is_empty() {
+                "Stopped"
+            } else if now_playing.playing {
+                "Playing"
+            } else {
+                "Paused"
+            };
+            values.push(("status", status.to_string()));
+            values.push(("title", now_playing.title));
+            values.push(("artist", now_playing.artist));
+            values.push(("album", now_playing.album));
+        }
+
+        self.commands
+            .spawn(spec.fill(&values), config.max_running_commands);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Profile;
+    use crossbeam_channel::{unbounded, Receiver};
+
+    fn runner(profiles: &[&str], active: &str) -> (ActionRunner, Receiver<AppMessage>) {
+        let config = Config {
+            profiles: profiles
+                .iter()
+                .map(|name| (name.to_string(), Profile::default()))
+                .collect(),
+            active_profile: active.to_string(),
+            ..Config::default()
+        };
+
+        let (sender, receiver) = unbounded();
+        let runner = ActionRunner::new(
+            MediaController::new(),
+            Arc::new(Mutex::new(config)),
+            ListenerControl::default(),
+            sender,
+        );
+        (runner, receiver)
+    }
+
+    fn active(runner: &ActionRunner) -> String {
+        runner.config.lock().unwrap().active_profile.clone()
+    }
+
+    fn profile_changes(receiver: &Receiver<AppMessage>) -> Vec<String> {
+        receiver
+            .try_iter()
+            .filter_map(|message| match message {
+                AppMessage::ProfileChanged(name) => Some(name),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn cycle_profile_wraps_around() {
+        let (runner, receiver) = runner(&["Default", "Gaming", "Work"], "Gaming");
+        let trigger = Trigger::Key(Key::F1);
+
+        for _ in 0..2 {
+            let config = runner.config.lock().unwrap().clone();
+            runner.run(trigger, &MediaAction::CycleProfile, &config);
+        }
+
+        assert_eq!(active(&runner), "Default");
+        assert_eq!(profile_changes(&receiver), ["Work", "Default"]);
+    }
+
+    #[test]
+    fn switching_rebinds_the_keys() {
+        let (runner, receiver) = runner(&["Default", "Work"], "Default");
+        #[cfg(target_os = "linux")]
+        let rebinds = runner.listener.watch_rebinds();
+
+        runner.switch_profile(Some("Work"));
+        assert_eq!(active(&runner), "Work");
+        assert_eq!(profile_changes(&receiver), ["Work"]);
+        #[cfg(target_os = "linux")]
+        assert!(rebinds.try_recv().is_ok());
+
+        // Already active, nothing changes
+        runner.switch_profile(Some("Work"));
+        assert!(profile_changes(&receiver).is_empty());
+        #[cfg(target_os = "linux")]
+        assert!(rebinds.try_recv().is_err());
+    }
+
+    #[test]
+    fn unknown_profiles_are_not_switched_to() {
+        let (runner, receiver) = runner(&["Default", "Work"], "Default");
+        runner.switch_profile(Some("Gaming"));
+        assert_eq!(active(&runner), "Default");
+        assert!(profile_changes(&receiver).is_empty());
+
+        // The active profile was removed, cycling starts over
+        runner.config.lock().unwrap().active_profile = "Removed".to_string();
+        runner.switch_profile(None);
+        assert_eq!(active(&runner), "Default");
+        assert_eq!(profile_changes(&receiver), ["Default"]);
+    }
+
+    #[test]
+    fn sleep_timers_are_limited() {
+        let (runner, _receiver) = runner(&["Default"], "Default");
+        let config = runner.config.lock().unwrap().clone();
+        runner.run(
+            Trigger::Key(Key::F1),
+            &MediaAction::SleepTimer(u64::MAX),
+            &config,
+        );
+
+        let remaining = runner.sleep_timer().remaining().unwrap();
+        runner.sleep_timer().cancel();
+        assert!(remaining <= Duration::from_secs(MAX_MINUTES * 60));
+        assert!(remaining > Duration::from_secs((MAX_MINUTES - 1) * 60));
+    }
+}
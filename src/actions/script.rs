@@ -0,0 +1,324 @@
+use crate::config::{Config, ScriptSource};
+use crate::media_control::MediaController;
+use crate::ui::AppMessage;
+use log::{error, info, warn};
+use rhai::{Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use super::{ActionRunner, Trigger};
+
+/// Compiled script files with their modification time, recompiled when the file changes
+#[derive(Clone, Default)]
+pub struct ScriptCache {
+    compiled: Arc<Mutex<HashMap<PathBuf, (SystemTime, AST)>>>,
+    running: Arc<Mutex<HashSet<Trigger>>>,
+}
+
+impl ScriptCache {
+    /// Compile the script, reusing the last compilation of an unchanged file
+    fn load(&self, engine: &Engine, source: &ScriptSource) -> Result<AST, String> {
+        let path = match source {
+            ScriptSource::Inline(code) => {
+                return engine
+                    .compile(code)
+                    .map_err(|e| format!("Syntax error: {}", e));
+            }
+            ScriptSource::File(path) if path.is_relative() => Config::config_dir().join(path),
+            ScriptSource::File(path) => path.clone(),
+        };
+
+        let modified = std::fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+        let mut compiled = self.compiled.lock().unwrap();
+        if let Some((compiled_at, ast)) = compiled.get(&path) {
+            if *compiled_at == modified {
+                return Ok(ast.clone());
+            }
+            info!("Script {} changed, reloading", path.display());
+        }
+
+        let code = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let ast = engine
+            .compile(&code)
+            .map_err(|e| format!("Syntax error in {}: {}", path.display(), e))?;
+
+        compiled.insert(path, (modified, ast.clone()));
+        Ok(ast)
+    }
+}
+
+/// Run the script on its own thread, reporting errors to the UI
+pub fn start(runner: &ActionRunner, trigger: Trigger, source: ScriptSource, config: &Config) {
+    let timeout = Duration::from_secs(config.script_timeout);
+
+    // One run per trigger at a time, a slow script must not pile up threads
+    if !runner.scripts.running.lock().unwrap().insert(trigger) {
+        warn!("Script for {} is still running", trigger);
+        return;
+    }
+
+    let runner = runner.clone();
+    thread::spawn(move || {
+        info!("Running script for {}", trigger);
+
+        let result = run(&runner, trigger, &source, timeout);
+        runner.scripts.running.lock().unwrap().remove(&trigger);
+
+        match result {
+            Ok(()) => info!("Script for {} finished", trigger),
+            Err(e) => {
+                error!("Script for {} failed: {}", trigger, e);
+                let _ = runner.app_sender.send(AppMessage::ScriptError(trigger, e));
+            }
+        }
+    });
+}
+
+fn run(
+    runner: &ActionRunner,
+    trigger: Trigger,
+    source: &ScriptSource,
+    timeout: Duration,
+) -> Result<(), String> {
+    let deadline = Instant::now() + timeout;
+    let engine = engine(runner.media_controller.clone(), deadline);
+    let ast = runner.scripts.load(&engine, source)?;
+
+    let mut scope = Scope::new();
+    scope.push_constant("key", trigger.to_string());
+
+    engine
+        .run_ast_with_scope(&mut scope, &ast)
+        .map_err(|e| match *e {
+            EvalAltResult::ErrorTerminated(..) => {
+                format!("Stopped after {} seconds", timeout.as_secs())
+            }
+            e => e.to_string(),
+        })
+}
+
+/// Error that stops the script when it runs out of time
+fn timed_out() -> Box<EvalAltResult> {
+    EvalAltResult::ErrorTerminated(Dynamic::UNIT, rhai::Position::NONE).into()
+}
+
+/// Create a sandboxed engine: no file or process access,
+/// only the player, time, sleep and logging
+fn engine(media: Arc<MediaController>, deadline: Instant) -> Engine {
+    let mut engine = Engine::new();
+
+    engine.disable_symbol("eval");
+    engine.set_max_call_levels(64);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(1024 * 1024);
+    engine.set_max_array_size(10_000);
+    engine.set_max_map_size(10_000);
+
+    // Stop scripts that run too long
+    engine.on_progress(move |_| (Instant::now() >= deadline).then_some(Dynamic::UNIT));
+
+    engine.on_print(|text| info!("[script] {}", text));
+    engine.on_debug(|text, _, position| info!("[script] {:?}: {}", position, text));
+    engine.register_fn("log_info", |text: &str| info!("[script] {}", text));
+    engine.register_fn("log_warn", |text: &str| warn!("[script] {}", text));
+    engine.register_fn("log_error", |text: &str| error!("[script] {}", text));
+
+    // Player
+    let m = media.clone();
+    engine.register_fn("play_pause", move || m.play_pause());
+    let m = media.clone();
+    engine.register_fn("next", move || m.next());
+    let m = media.clone();
+    engine.register_fn("previous", move || m.previous());
+    let m = media.clone();
+    engine.register_fn("stop", move || m.stop());
+    let m = media.clone();
+    engine.register_fn("volume_up", move || m.volume_up());
+    let m = media.clone();
+    engine.register_fn("volume_down", move || m.volume_down());
+    let m = media.clone();
+    engine.register_fn("set_volume", move |percent: i64| {
+        m.set_volume(percent.clamp(0, 100) as u32)
+    });
+    let m = media.clone();
+    engine.register_fn("volume", move || -> Dynamic {
+        m.volume()
+            .map_or(Dynamic::UNIT, |volume| Dynamic::from_int(volume as i64))
+    });
+    let m = media;
+    engine.register_fn("now_playing", move || -> Dynamic {
+        let Some(now_playing) = m.now_playing() else {
+            return Dynamic::UNIT;
+        };
+        let mut map = Map::new();
+        map.insert("title".into(), now_playing.title.into());
+        map.insert("artist".into(), now_playing.artist.into());
+        map.insert("album".into(), now_playing.album.into());
+        map.insert("playing".into(), now_playing.playing.into());
+        map.into()
+    });
+
+    // Time
+    engine.register_fn("time", || -> Map {
+        let now = jiff::Zoned::now();
+        let mut map = Map::new();
+        map.insert("year".into(), (now.year() as i64).into());
+        map.insert("month".into(), (now.month() as i64).into());
+        map.insert("day".into(), (now.day() as i64).into());
+        map.insert("hour".into(), (now.hour() as i64).into());
+        map.insert("minute".into(), (now.minute() as i64).into());
+        map.insert("second".into(), (now.second() as i64).into());
+        // 1 = Monday ... 7 = Sunday
+        map.insert(
+            "weekday".into(),
+            (now.weekday().to_monday_one_offset() as i64).into(),
+        );
+        map.insert("timestamp".into(), now.timestamp().as_second().into());
+        map
+    });
+
+    // Sleeping counts towards the time limit
+    engine.register_fn("sleep", move |ms: i64| -> Result<(), Box<EvalAltResult>> {
+        let wanted = Duration::from_millis(ms.max(0) as u64);
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        thread::sleep(wanted.min(remaining));
+        if wanted > remaining {
+            return Err(timed_out());
+        }
+        Ok(())
+    });
+
+    engine
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hotkey_listener::ListenerControl;
+    use crossbeam_channel::{unbounded, Receiver};
+    use rdev::Key;
+    use std::fs::File;
+
+    const TRIGGER: Trigger = Trigger::Key(Key::F1);
+
+    fn runner() -> (ActionRunner, Receiver<AppMessage>) {
+        let (sender, receiver) = unbounded();
+        let config = Arc::new(Mutex::new(Config::default()));
+        let runner = ActionRunner::new(
+            MediaController::new(),
+            config,
+            ListenerControl::default(),
+            sender,
+        );
+        (runner, receiver)
+    }
+
+    fn inline(code: &str) -> ScriptSource {
+        ScriptSource::Inline(code.to_string())
+    }
+
+    /// The error of the next script that fails
+    fn script_error(receiver: &Receiver<AppMessage>) -> Option<(Trigger, String)> {
+        loop {
+            match receiver.recv_timeout(Duration::from_secs(2)) {
+                Ok(AppMessage::ScriptError(trigger, error)) => return Some((trigger, error)),
+                Ok(_) => continue,
+                Err(_) => return None,
+            }
+        }
+    }
+
+    #[test]
+    fn endless_loops_are_stopped() {
+        let (runner, _receiver) = runner();
+        let started = Instant::now();
+
+        let error = run(&runner, TRIGGER, &inline("loop {}"), Duration::from_secs(1)).unwrap_err();
+
+        assert_eq!(error, "Stopped after 1 seconds");
+        assert!(started.elapsed() < Duration::from_secs(3));
+    }
+
+    #[test]
+    fn errors_are_reported_to_the_ui() {
+        let (runner, receiver) = runner();
+        let config = Config::default();
+
+        start(&runner, TRIGGER, inline("let x = ;"), &config);
+        let (trigger, error) = script_error(&receiver).unwrap();
+        assert_eq!(trigger, TRIGGER);
+        assert!(error.starts_with("Syntax error"), "{}", error);
+
+        let trigger = Trigger::Schedule(0);
+        start(&runner, trigger, inline(r#"throw "no player""#), &config);
+        let (reported, error) = script_error(&receiver).unwrap();
+        assert_eq!(reported, trigger);
+        assert!(error.contains("no player"), "{}", error);
+    }
+
+    #[test]
+    fn eval_is_not_available() {
+        let (runner, _receiver) = runner();
+        let timeout = Duration::from_secs(1);
+        assert!(run(&runner, TRIGGER, &inline("let x = 1;"), timeout).is_ok());
+
+        let error = run(&runner, TRIGGER, &inline(r#"eval("1")"#), timeout).unwrap_err();
+        assert!(error.contains("eval"), "{}", error);
+    }
+
+    #[test]
+    fn changed_files_are_compiled_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("script.rhai");
+        let source = ScriptSource::File(path.clone());
+        let engine = Engine::new();
+        let cache = ScriptCache::default();
+        let result = |ast: AST| engine.eval_ast::<i64>(&ast).unwrap();
+
+        std::fs::write(&path, "40 + 2").unwrap();
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        assert_eq!(result(cache.load(&engine, &source).unwrap()), 42);
+
+        // Same modification time, the compiled script is used
+        std::fs::write(&path, "1 + 1").unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        assert_eq!(result(cache.load(&engine, &source).unwrap()), 42);
+
+        let later = modified + Duration::from_secs(5);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert_eq!(result(cache.load(&engine, &source).unwrap()), 2);
+    }
+
+    #[test]
+    fn one_run_per_trigger_at_a_time() {
+        let (runner, receiver) = runner();
+        let config = Config::default();
+        let script = inline(r#"sleep(300); throw "done""#);
+
+        start(&runner, TRIGGER, script.clone(), &config);
+        start(&runner, TRIGGER, script.clone(), &config);
+        assert!(script_error(&receiver).is_some());
+        assert!(script_error(&receiver).is_none());
+
+        // Can run again once it has ended
+        start(&runner, TRIGGER, script, &config);
+        assert!(script_error(&receiver).is_some());
+    }
+}
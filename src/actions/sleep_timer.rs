@@ -0,0 +1,333 @@
+use crate::media_control::MediaController;
+use crate::ui::AppMessage;
+use crossbeam_channel::Sender;
+use log::{info, warn};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The volume fades out over this last part of the timer
+const FADE_DURATION: Duration = Duration::from_secs(60);
+
+/// How often the timer checks the time and adjusts the volume
+const TICK: Duration = Duration::from_millis(500);
+
+/// Longest time that can be added at once, one day
+pub const MAX_MINUTES: u64 = 24 * 60;
+
+/// What the timer does to the player
+pub trait Playback: Send + Sync + 'static {
+    fn stop(&self);
+    fn volume(&self) -> Option<u32>;
+    fn set_volume(&self, percent: u32);
+}
+
+impl Playback for MediaController {
+    fn stop(&self) {
+        MediaController::stop(self);
+    }
+
+    fn volume(&self) -> Option<u32> {
+        MediaController::volume(self)
+    }
+
+    fn set_volume(&self, percent: u32) {
+        MediaController::set_volume(self, percent);
+    }
+}
+
+#[derive(Default)]
+struct State {
+    deadline: Option<Instant>,
+    /// Volume before the fade started, restored when the timer ends
+    original_volume: Option<u32>,
+    /// Whether the fade has started (the volume may be unknown)
+    fading: bool,
+    /// Whether the timer thread is running
+    running: bool,
+}
+
+/// Stops the player after a while, fading the volume out first.
+/// The player is never called while `state` is locked.
+pub struct SleepTimer<P: Playback = MediaController> {
+    state: Arc<(Mutex<State>, Condvar)>,
+    /// Held while changing the volume, so a fade step can't undo a restore
+    volume: Arc<Mutex<()>>,
+    media_controller: Arc<P>,
+    app_sender: Sender<AppMessage>,
+}
+
+impl<P: Playback> Clone for SleepTimer<P> {
+    fn clone(&self) -> Self {
+        SleepTimer {
+            state: self.state.clone(),
+            volume: self.volume.clone(),
+            media_controller: self.media_controller.clone(),
+            app_sender: self.app_sender.clone(),
+        }
+    }
+}
+
+impl<P: Playback> SleepTimer<P> {
+    pub fn new(media_controller: Arc<P>, app_sender: Sender<AppMessage>) -> Self {
+        SleepTimer {
+            state: Default::default(),
+            volume: Default::default(),
+            media_controller,
+            app_sender,
+        }
+    }
+
+    /// Time left until the player is stopped, if the timer is running
+    pub fn remaining(&self) -> Option<Duration> {
+        let state = self.state.0.lock().unwrap();
+        state
+            .deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Start the timer, or add `minutes` to the running one
+    pub fn extend(&self, minutes: u64) {
+        match minutes.checked_mul(60) {
+            Some(seconds) => self.add(Duration::from_secs(seconds)),
+            None => warn!("Sleep timer: {} minutes is too long", minutes),
+        }
+    }
+
+    fn add(&self, added: Duration) {
+        let (lock, wakeup) = &*self.state;
+        let mut state = lock.lock().unwrap();
+
+        let now = Instant::now();
+        let deadline = match state.deadline {
+            Some(deadline) => deadline.max(now).checked_add(added),
+            None => now.checked_add(added),
+        };
+        let Some(deadline) = deadline else {
+            warn!("Sleep timer: {} is too long", format_duration(added));
+            return;
+        };
+        state.deadline = Some(deadline);
+        info!(
+            "Sleep timer: stopping in {}",
+            format_duration(deadline - now)
+        );
+
+        // The fade starts over once the new deadline comes close
+        let restore = self.stop_fading(&mut state);
+
+        if !state.running {
+            state.running = true;
+            let timer = self.clone();
+            thread::spawn(move || timer.run());
+        }
+        drop(state);
+        wakeup.notify_all();
+
+        self.restore_volume(restore);
+        let _ = self.app_sender.send(AppMessage::SleepTimer(Some(deadline)));
+    }
+
+    /// Cancel the timer and restore the volume. Returns false if it wasn't running.
+    pub fn cancel(&self) -> bool {
+        let (lock, wakeup) = &*self.state;
+        let mut state = lock.lock().unwrap();
+
+        if state.deadline.take().is_none() {
+            return false;
+        }
+        info!("Sleep timer cancelled");
+
+        let restore = self.stop_fading(&mut state);
+        drop(state);
+        wakeup.notify_all();
+
+        self.restore_volume(restore);
+        let _ = self.app_sender.send(AppMessage::SleepTimer(None));
+        true
+    }
+
+    /// Leave the fade, returning the volume to restore
+    fn stop_fading(&self, state: &mut State) -> Option<u32> {
+        state.fading = false;
+        state.original_volume.take()
+    }
+
+    fn restore_volume(&self, volume: Option<u32>) {
+        if let Some(volume) = volume {
+            let _volume = self.volume.lock().unwrap();
+            self.media_controller.set_volume(volume);
+        }
+    }
+
+    /// Timer thread: fades the volume and stops the player at the deadline
+    fn run(&self) {
+        let (lock, wakeup) = &*self.state;
+        let mut last_volume = None;
+
+        loop {
+            let mut state = lock.lock().unwrap();
+
+            let Some(deadline) = state.deadline else {
+                state.running = false;
+                return;
+            };
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+
+            if remaining.is_zero() {
+                state.deadline = None;
+                state.running = false;
+                let restore = self.stop_fading(&mut state);
+                drop(state);
+
+                info!("Sleep timer finished, stopping the player");
+                self.media_controller.stop();
+                self.restore_volume(restore);
+                let _ = self.app_sender.send(AppMessage::SleepTimer(None));
+                return;
+            }
+
+            let start_fade = remaining <= FADE_DURATION && !state.fading;
+            state.fading |= start_fade;
+            let mut original = state.original_volume;
+            drop(state);
+
+            if remaining <= FADE_DURATION {
+                let _volume = self.volume.lock().unwrap();
+                if start_fade {
+                    let volume = self.media_controller.volume();
+                    let mut state = lock.lock().unwrap();
+                    if !state.fading {
+                        // Changed in the meantime
+                        continue;
+                    }
+                    state.original_volume = volume;
+                    drop(state);
+
+                    match volume {
+                        Some(volume) => info!("Sleep timer: fading out from {}%", volume),
+                        None => warn!("Sleep timer: player volume unknown, stopping without fade"),
+                    }
+                    original = volume;
+                    last_volume = volume;
+                }
+
+                if let Some(original) = original {
+                    let volume = (original as f64 * remaining.as_secs_f64()
+                        / FADE_DURATION.as_secs_f64())
+                    .round() as u32;
+                    // A restore may have happened since, it is not undone
+                    if last_volume != Some(volume) && lock.lock().unwrap().fading {
+                        last_volume = Some(volume);
+                        self.media_controller.set_volume(volume);
+                    }
+                }
+            }
+
+            let state = lock.lock().unwrap();
+            if state.deadline != Some(deadline) {
+                // Changed in the meantime, look again right away
+                continue;
+            }
+
+            // Sleep until the next tick, or until the timer is changed
+            let _ = wakeup.wait_timeout(state, TICK.min(remaining)).unwrap();
+        }
+    }
+}
+
+/// Format a duration as `m:ss` or `h:mm:ss`
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+    #[derive(Default)]
+    struct FakePlayer {
+        volume: AtomicU32,
+        stopped: AtomicBool,
+    }
+
+    impl Playback for FakePlayer {
+        fn stop(&self) {
+            self.stopped.store(true, Ordering::SeqCst);
+        }
+
+        fn volume(&self) -> Option<u32> {
+            Some(self.volume.load(Ordering::SeqCst))
+        }
+
+        fn set_volume(&self, percent: u32) {
+            self.volume.store(percent, Ordering::SeqCst);
+        }
+    }
+
+    fn timer() -> (SleepTimer<FakePlayer>, Arc<FakePlayer>) {
+        let player = Arc::new(FakePlayer::default());
+        player.set_volume(80);
+        let (sender, _) = crossbeam_channel::unbounded();
+        (SleepTimer::new(player.clone(), sender), player)
+    }
+
+    /// Wait for the timer thread to get to `done`
+    fn wait_for(done: impl Fn() -> bool) {
+        let started = Instant::now();
+        while !done() {
+            assert!(started.elapsed() < Duration::from_secs(5), "timed out");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn extends_the_running_timer() {
+        let (timer, _) = timer();
+        timer.extend(5);
+        timer.extend(5);
+        let remaining = timer.remaining().unwrap();
+        assert!(remaining > Duration::from_secs(9 * 60) && remaining <= Duration::from_secs(600));
+
+        // Too long to add, the timer stays as it is
+        timer.extend(u64::MAX);
+        timer.extend(u64::MAX / 60);
+        assert!(timer.remaining().unwrap() <= Duration::from_secs(600));
+        timer.cancel();
+    }
+
+    #[test]
+    fn cancel_restores_the_volume() {
+        let (timer, player) = timer();
+        assert!(!timer.cancel());
+
+        // Within the fade from the start
+        timer.add(Duration::from_secs(30));
+        wait_for(|| player.volume.load(Ordering::SeqCst) < 80);
+        let volume = player.volume.load(Ordering::SeqCst);
+        assert!((35..=45).contains(&volume), "{}", volume);
+
+        assert!(timer.cancel());
+        assert_eq!(timer.remaining(), None);
+        assert_eq!(player.volume.load(Ordering::SeqCst), 80);
+        assert!(!player.stopped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn stops_the_player_at_the_deadline() {
+        let (timer, player) = timer();
+        timer.add(Duration::from_millis(300));
+        wait_for(|| player.stopped.load(Ordering::SeqCst));
+
+        wait_for(|| timer.remaining().is_none());
+        assert_eq!(player.volume.load(Ordering::SeqCst), 80);
+    }
+}
@@ -0,0 +1,368 @@
+use crate::actions::{format_duration, MAX_MINUTES};
+use crate::config::{
+    self, BindingSet, Config, Format, LoadError, Override, Resolution, Severity,
+};
+use crate::control::{self, Request, Response};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+/// Without a command the settings window is opened
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Configuration file to use instead of the one in the user's config directory
+    #[arg(long, env = "MHM_CONFIG", value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    /// Change a setting for this run without saving it, e.g. `--set backups=0`
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    pub overrides: Vec<Override>,
+    /// Keep the configuration, the log and the state next to the executable
+    #[arg(long)]
+    pub portable: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Query or change the sleep timer of the running instance
+    SleepTimer {
+        #[command(subcommand)]
+        action: SleepTimerCommand,
+    },
+    /// Pause all hotkeys except the ones bound to `ToggleEnabled`
+    Pause,
+    /// Resume the hotkeys
+    Resume,
+    /// Pause or resume the hotkeys
+    Toggle,
+    /// Show whether the hotkeys are paused
+    Status,
+    /// Work with configuration files
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+    /// Share bindings through files of their own
+    Bindings {
+        #[command(subcommand)]
+        action: BindingsCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BindingsCommand {
+    /// Write the bindings of a profile to a file,
+    /// the format is taken from the file extension
+    Export {
+        file: PathBuf,
+        /// The active profile if not given
+        #[arg(long)]
+        profile: Option<String>,
+        /// Only these keys, e.g. `--keys F1,F2`
+        #[arg(long, value_delimiter = ',', value_parser = parse_key)]
+        keys: Vec<rdev::Key>,
+        /// Overwrite the file if it exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Add the bindings in a file to a profile and save the configuration
+    Import {
+        file: PathBuf,
+        /// The active profile if not given, created if it doesn't exist
+        #[arg(long)]
+        profile: Option<String>,
+        /// What to do with keys that are bound to other actions already
+        #[arg(long, value_enum, default_value_t = OnConflict::Skip)]
+        on_conflict: OnConflict,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum OnConflict {
+    /// Keep the existing binding
+    Skip,
+    /// Use the imported binding
+    Replace,
+    /// Bind the imported action to a free key
+    Rename,
+}
+
+fn parse_key(name: &str) -> Result<rdev::Key, String> {
+    config::parse_key(name).ok_or_else(|| format!("unknown key {}", name))
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Convert a configuration file between JSON, TOML and YAML,
+    /// the formats are taken from the file extensions
+    Convert {
+        input: PathBuf,
+        output: PathBuf,
+        /// Overwrite the output file if it exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Check a configuration file for errors and likely mistakes,
+    /// fails if there are errors
+    Validate {
+        /// The configuration file of the app if not given
+        file: Option<PathBuf>,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+        format: OutputFormat,
+        /// Fail on warnings too
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Print the JSON Schema of configuration files
+    Schema,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    Human,
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SleepTimerCommand {
+    /// Show the time left
+    Status,
+    /// Start the timer or add minutes to it
+    Add {
+        #[arg(value_parser = clap::value_parser!(u64).range(1..=MAX_MINUTES))]
+        minutes: u64,
+    },
+    /// Cancel the timer and restore the volume
+    Cancel,
+}
+
+/// Run a command line command and report the result
+pub fn run(command: Command) -> ExitCode {
+    let result = match command {
+        Command::SleepTimer { action } => sleep_timer(action),
+        Command::Pause => enabled(Request::SetEnabled { enabled: false }),
+        Command::Resume => enabled(Request::SetEnabled { enabled: true }),
+        Command::Toggle => enabled(Request::ToggleEnabled),
+        Command::Status => enabled(Request::EnabledStatus),
+        Command::Config { action } => config(action),
+        Command::Bindings { action } => bindings(action),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Send a request to the running instance
+fn request(request: Request) -> Result<serde_json::Value, String> {
+    match control::send(&request) {
+        Ok(Response::Ok(value)) => Ok(value),
+        Ok(Response::Error(e)) => Err(e),
+        Err(e) => Err(format!("Can't reach the running instance: {}", e)),
+    }
+}
+
+fn sleep_timer(action: SleepTimerCommand) -> Result<(), String> {
+    let result = match action {
+        SleepTimerCommand::Status => request(Request::SleepTimerStatus)?,
+        SleepTimerCommand::Add { minutes } => request(Request::SleepTimerAdd { minutes })?,
+        SleepTimerCommand::Cancel => {
+            request(Request::SleepTimerCancel)?;
+            println!("Sleep timer cancelled");
+            return Ok(());
+        }
+    };
+
+    match result["remaining"].as_u64() {
+        Some(seconds) => println!(
+            "Sleep timer: {} left",
+            format_duration(Duration::from_secs(seconds))
+        ),
+        None => println!("Sleep timer is not running"),
+    }
+    Ok(())
+}
+
+fn config(action: ConfigCommand) -> Result<(), String> {
+    match action {
+        ConfigCommand::Convert {
+            input,
+            output,
+            force,
+        } => convert(&input, &output, force),
+        ConfigCommand::Validate {
+            file,
+            format,
+            strict,
+        } => validate(&file.unwrap_or_else(Config::config_path), format, strict),
+        ConfigCommand::Schema => {
+            let schema = serde_json::to_string_pretty(&Config::schema()).map_err(|e| e.to_string())?;
+            println!("{}", schema);
+            Ok(())
+        }
+    }
+}
+
+fn format_of(path: &Path) -> Result<Format, String> {
+    Format::of(path).ok_or_else(|| {
+        format!(
+            "Unknown format of {}, use .json, .toml or .yaml",
+            path.display()
+        )
+    })
+}
+
+fn convert(input: &Path, output: &Path, force: bool) -> Result<(), String> {
+    let (from, to) = (format_of(input)?, format_of(output)?);
+    if output.exists() && !force {
+        return Err(format!(
+            "{} exists, use --force to overwrite it",
+            output.display()
+        ));
+    }
+
+    let contents = std::fs::read_to_string(input)
+        .map_err(|e| format!("Can't read {}: {}", input.display(), e))?;
+    let config =
+        Config::parse(&contents, from).map_err(|e| format!("{}: {}", input.display(), e))?;
+    let converted = config.to_text(to)?;
+    std::fs::write(output, converted)
+        .map_err(|e| format!("Can't write {}: {}", output.display(), e))?;
+
+    println!("Converted {} to {}", input.display(), output.display());
+    Ok(())
+}
+
+fn validate(path: &Path, output: OutputFormat, strict: bool) -> Result<(), String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Can't read {}: {}", path.display(), e))?;
+    let findings = config::check_file(&contents, format_of(path)?);
+
+    let errors = findings
+        .iter()
+        .filter(|finding| finding.severity == Severity::Error)
+        .count();
+    let warnings = findings.len() - errors;
+    let valid = errors == 0 && (warnings == 0 || !strict);
+
+    match output {
+        OutputFormat::Human => {
+            for finding in &findings {
+                println!("{}: {}", path.display(), finding);
+            }
+            println!(
+                "{}: {} error(s), {} warning(s)",
+                path.display(),
+                errors,
+                warnings
+            );
+        }
+        OutputFormat::Json => {
+            let report = serde_json::json!({
+                "file": path,
+                "valid": valid,
+                "findings": findings,
+            });
+            println!("{}", serde_json::to_string_pretty(&report).unwrap_or_default());
+        }
+    }
+
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("{} did not pass the checks", path.display()))
+    }
+}
+
+/// The configuration of the app, to change it
+fn load_config() -> Result<Config, String> {
+    match Config::load() {
+        Ok(config) => Ok(config),
+        Err(LoadError::Missing) => Ok(Config::default()),
+        Err(LoadError::Invalid(e)) => Err(format!(
+            "{} is invalid, fix it first: {}",
+            Config::config_path().display(),
+            e
+        )),
+        Err(LoadError::Io(e)) => Err(format!(
+            "Can't read {}: {}",
+            Config::config_path().display(),
+            e
+        )),
+    }
+}
+
+fn bindings(action: BindingsCommand) -> Result<(), String> {
+    let mut config = load_config()?;
+    match action {
+        BindingsCommand::Export {
+            file,
+            profile,
+            keys,
+            force,
+        } => {
+            if file.exists() && !force {
+                return Err(format!(
+                    "{} exists, use --force to overwrite it",
+                    file.display()
+                ));
+            }
+            let name = profile.unwrap_or_else(|| config.active_profile.clone());
+            let profile = config
+                .profiles
+                .get(&name)
+                .ok_or_else(|| format!("There is no profile '{}'", name))?;
+            let keys = (!keys.is_empty()).then_some(keys.as_slice());
+            let set = BindingSet::export(&name, profile, keys);
+            set.write(&file)?;
+            println!(
+                "Exported {} binding(s) of '{}' to {}",
+                set.hotkeys.len(),
+                name,
+                file.display()
+            );
+        }
+        BindingsCommand::Import {
+            file,
+            profile,
+            on_conflict,
+        } => {
+            let set = BindingSet::read(&file)?;
+            let name = profile.unwrap_or_else(|| config.active_profile.clone());
+            let profile = config.profiles.entry(name.clone()).or_default();
+
+            let mut free = profile.free_keys(&set).into_iter();
+            let summary = profile.import(&set, |key| match on_conflict {
+                OnConflict::Skip => Resolution::Skip,
+                OnConflict::Replace => Resolution::Replace,
+                OnConflict::Rename => match free.next() {
+                    Some(to) => Resolution::Rename(to),
+                    None => {
+                        eprintln!("No free key left for {:?}, skipping it", key);
+                        Resolution::Skip
+                    }
+                },
+            });
+            config.save().map_err(|e| format!("Failed to save: {}", e))?;
+            println!("Imported into '{}': {}", name, summary);
+        }
+    }
+    Ok(())
+}
+
+fn enabled(message: Request) -> Result<(), String> {
+    let result = request(message)?;
+    match result["enabled"].as_bool() {
+        Some(true) => println!("Hotkeys are active"),
+        Some(false) => println!("Hotkeys are paused"),
+        None => return Err("Unexpected response".to_string()),
+    }
+    Ok(())
+}
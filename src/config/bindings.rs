@@ -0,0 +1,295 @@
+//! Binding sets: the bindings of a profile, or some of them, in a file of
+//! their own, to share them with others.
+
+use super::{key_map_serde, Format, MediaAction, Profile, CONFIG_VERSION, SUPPORTED_KEYS};
+use rdev::Key;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+/// Bindings exported from a profile
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BindingSet {
+    /// Format of the actions, like the version of the configuration file
+    pub version: u32,
+    /// Profile the bindings were exported from
+    #[serde(default)]
+    pub profile: String,
+    #[serde(with = "key_map_serde")]
+    pub hotkeys: HashMap<Key, MediaAction>,
+}
+
+/// What to do with a binding whose key is bound to another action already
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Keep the existing binding
+    Skip,
+    /// Bind the key to the imported action
+    Replace,
+    /// Bind the imported action to another key
+    Rename(Key),
+}
+
+/// What an import changed
+#[derive(Debug, Default, PartialEq)]
+pub struct ImportSummary {
+    pub added: Vec<Key>,
+    pub replaced: Vec<Key>,
+    /// From the key in the file to the key it is bound to now
+    pub renamed: Vec<(Key, Key)>,
+    pub skipped: Vec<Key>,
+}
+
+impl fmt::Display for ImportSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} replaced, {} skipped",
+            self.added.len(),
+            self.replaced.len(),
+            self.skipped.len()
+        )?;
+        for (from, to) in &self.renamed {
+            write!(f, ", {:?} → {:?}", from, to)?;
+        }
+        Ok(())
+    }
+}
+
+/// Keys in keyboard order
+fn sorted<'a>(keys: impl Iterator<Item = &'a Key>) -> Vec<Key> {
+    let mut keys: Vec<Key> = keys.copied().collect();
+    keys.sort_by_key(|key| SUPPORTED_KEYS.iter().position(|supported| supported == key));
+    keys
+}
+
+impl BindingSet {
+    /// The bindings of `keys` in `profile`, all of them if `keys` is `None`
+    pub fn export(name: &str, profile: &Profile, keys: Option<&[Key]>) -> Self {
+        let hotkeys = profile
+            .hotkeys
+            .iter()
+            .filter(|(key, _)| keys.is_none_or(|keys| keys.contains(key)))
+            .map(|(key, action)| (*key, action.clone()))
+            .collect();
+        BindingSet {
+            version: CONFIG_VERSION,
+            profile: name.to_string(),
+            hotkeys,
+        }
+    }
+
+    /// Read a binding set, the format is taken from the extension
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let format =
+            Format::of(path).ok_or_else(|| format!("Unknown format of {}", path.display()))?;
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Can't read {}: {}", path.display(), e))?;
+        Self::parse(&contents, format).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn parse(contents: &str, format: Format) -> Result<Self, String> {
+        let document = format.parse(contents).map_err(|e| e.to_string())?;
+        let version = document.get("version").and_then(Value::as_u64).unwrap_or(0);
+        if version > u64::from(CONFIG_VERSION) {
+            return Err(format!(
+                "written by a newer release (version {}), this one reads up to version {}",
+                version, CONFIG_VERSION
+            ));
+        }
+        serde_path_to_error::deserialize(&document).map_err(|e| {
+            let entry = e.path().to_string();
+            format!("{}: {}", entry, e.into_inner())
+        })
+    }
+
+    /// Write the binding set, the format is taken from the extension
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let format =
+            Format::of(path).ok_or_else(|| format!("Unknown format of {}", path.display()))?;
+        let document = serde_json::to_value(self).map_err(|e| e.to_string())?;
+        let contents = format.write(&document)?;
+        std::fs::write(path, contents).map_err(|e| format!("Can't write {}: {}", path.display(), e))
+    }
+
+    /// Keys of the set, in keyboard order
+    pub fn keys(&self) -> Vec<Key> {
+        sorted(self.hotkeys.keys())
+    }
+}
+
+impl Profile {
+    /// Keys bound to other actions here than in `set`, in keyboard order
+    pub fn conflicts(&self, set: &BindingSet) -> Vec<Key> {
+        sorted(set.hotkeys.iter().filter_map(|(key, action)| {
+            self.hotkeys
+                .get(key)
+                .filter(|existing| *existing != action)
+                .map(|_| key)
+        }))
+    }
+
+    /// Keys that are neither bound here nor in `set`
+    pub fn free_keys(&self, set: &BindingSet) -> Vec<Key> {
+        SUPPORTED_KEYS
+            .into_iter()
+            .filter(|key| !self.hotkeys.contains_key(key) && !set.hotkeys.contains_key(key))
+            .collect()
+    }
+
+    /// Add the bindings of `set`, `resolve` decides what happens to each conflicting key.
+    /// A binding renamed to a key that is taken by then is skipped.
+    pub fn import(
+        &mut self,
+        set: &BindingSet,
+        mut resolve: impl FnMut(Key) -> Resolution,
+    ) -> ImportSummary {
+        let mut summary = ImportSummary::default();
+        let conflicts = self.conflicts(set);
+
+        for key in set.keys() {
+            let action = set.hotkeys[&key].clone();
+            if self.hotkeys.get(&key) == Some(&action) {
+                continue;
+            }
+            if !conflicts.contains(&key) {
+                self.hotkeys.insert(key, action);
+                summary.added.push(key);
+                continue;
+            }
+
+            match resolve(key) {
+                Resolution::Skip => summary.skipped.push(key),
+                Resolution::Replace => {
+                    self.hotkeys.insert(key, action);
+                    summary.replaced.push(key);
+                }
+                Resolution::Rename(to)
+                    if !self.hotkeys.contains_key(&to) && !set.hotkeys.contains_key(&to) =>
+                {
+                    self.hotkeys.insert(to, action);
+                    summary.renamed.push((key, to));
+                }
+                Resolution::Rename(_) => summary.skipped.push(key),
+            }
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(bindings: &[(Key, MediaAction)]) -> Profile {
+        Profile {
+            hotkeys: bindings.iter().cloned().collect(),
+            ..Profile::default()
+        }
+    }
+
+    fn set(bindings: &[(Key, MediaAction)]) -> BindingSet {
+        BindingSet::export("Shared", &profile(bindings), None)
+    }
+
+    #[test]
+    fn exports_only_the_selected_keys() {
+        let profile = profile(&[
+            (Key::F1, MediaAction::Next),
+            (Key::F2, MediaAction::Stop),
+            (Key::F3, MediaAction::PlayPause),
+        ]);
+        let set = BindingSet::export("Default", &profile, Some(&[Key::F1, Key::F3]));
+        assert_eq!(set.keys(), [Key::F1, Key::F3]);
+        assert_eq!(set.profile, "Default");
+    }
+
+    #[test]
+    fn round_trips_through_every_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let set = set(&[
+            (Key::F1, MediaAction::Seek(10.0)),
+            (Key::F2, MediaAction::SwitchProfile("Work".into())),
+        ]);
+        for name in ["set.json", "set.toml", "set.yaml"] {
+            let path = dir.path().join(name);
+            set.write(&path).unwrap();
+            assert_eq!(BindingSet::read(&path).unwrap(), set, "{}", name);
+        }
+    }
+
+    #[test]
+    fn rejects_sets_from_newer_releases() {
+        let newer = format!(
+            "{{ \"version\": {}, \"hotkeys\": {{}} }}",
+            CONFIG_VERSION + 1
+        );
+        let error = BindingSet::parse(&newer, Format::Json).unwrap_err();
+        assert!(error.contains("newer release"), "{}", error);
+
+        let error = BindingSet::parse(
+            "{ \"version\": 1, \"hotkeys\": { \"F99\": \"Next\" } }",
+            Format::Json,
+        )
+        .unwrap_err();
+        assert!(error.contains("F99"), "{}", error);
+    }
+
+    #[test]
+    fn resolves_conflicts() {
+        let mut mine = profile(&[
+            (Key::F1, MediaAction::Next),
+            (Key::F2, MediaAction::Stop),
+            (Key::F3, MediaAction::PlayPause),
+            (Key::F4, MediaAction::VolumeUp),
+        ]);
+        let theirs = set(&[
+            (Key::F1, MediaAction::Previous),
+            (Key::F2, MediaAction::Seek(5.0)),
+            (Key::F3, MediaAction::CycleProfile),
+            (Key::F4, MediaAction::VolumeUp),
+            (Key::F5, MediaAction::ToggleEnabled),
+        ]);
+        assert_eq!(mine.conflicts(&theirs), [Key::F1, Key::F2, Key::F3]);
+        assert_eq!(mine.free_keys(&theirs)[0], Key::F6);
+
+        let summary = mine.import(&theirs, |key| match key {
+            Key::F1 => Resolution::Skip,
+            Key::F2 => Resolution::Replace,
+            _ => Resolution::Rename(Key::F6),
+        });
+
+        assert_eq!(
+            summary,
+            ImportSummary {
+                added: vec![Key::F5],
+                replaced: vec![Key::F2],
+                renamed: vec![(Key::F3, Key::F6)],
+                skipped: vec![Key::F1],
+            }
+        );
+        assert_eq!(mine.hotkeys[&Key::F1], MediaAction::Next);
+        assert_eq!(mine.hotkeys[&Key::F2], MediaAction::Seek(5.0));
+        assert_eq!(mine.hotkeys[&Key::F3], MediaAction::PlayPause);
+        assert_eq!(mine.hotkeys[&Key::F6], MediaAction::CycleProfile);
+        assert_eq!(
+            summary.to_string(),
+            "1 added, 1 replaced, 1 skipped, F3 → F6"
+        );
+    }
+
+    #[test]
+    fn renaming_to_a_taken_key_skips() {
+        let mut mine = profile(&[(Key::F1, MediaAction::Next), (Key::F2, MediaAction::Stop)]);
+        let theirs = set(&[
+            (Key::F1, MediaAction::Previous),
+            (Key::F3, MediaAction::Stop),
+        ]);
+
+        let summary = mine.import(&theirs, |_| Resolution::Rename(Key::F3));
+        assert_eq!(summary.skipped, [Key::F1]);
+        assert_eq!(mine.hotkeys[&Key::F3], MediaAction::Stop);
+    }
+}
@@ -0,0 +1,106 @@
+//! File formats of the configuration. All of them are read into a JSON
+//! document first, so migrations and lenient loading work the same for each.
+
+use super::ParseError;
+use serde_json::Value;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    /// In order of precedence, if there are several configuration files
+    pub const ALL: [Format; 3] = [Format::Json, Format::Toml, Format::Yaml];
+
+    /// Name of the configuration file in this format
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Format::Json => "config.json",
+            Format::Toml => "config.toml",
+            Format::Yaml => "config.yaml",
+        }
+    }
+
+    /// Format of a file by its extension
+    pub fn of(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "json" => Some(Format::Json),
+            "toml" => Some(Format::Toml),
+            "yaml" | "yml" => Some(Format::Yaml),
+            _ => None,
+        }
+    }
+
+    /// Read a document
+    pub fn parse(self, contents: &str) -> Result<Value, ParseError> {
+        match self {
+            Format::Json => {
+                let deserializer = &mut serde_json::Deserializer::from_str(contents);
+                Ok(serde_path_to_error::deserialize(deserializer)?)
+            }
+            Format::Toml => toml::from_str(contents).map_err(|e| {
+                let (line, column) = e
+                    .span()
+                    .map(|span| line_and_column(contents, span.start))
+                    .unwrap_or_default();
+                ParseError {
+                    message: e.message().to_string(),
+                    line,
+                    column,
+                    entry: None,
+                }
+            }),
+            Format::Yaml => serde_yaml::from_str(contents).map_err(|e| {
+                let location = e.location();
+                ParseError {
+                    message: e
+                        .to_string()
+                        .split(" at line ")
+                        .next()
+                        .unwrap_or_default()
+                        .to_string(),
+                    line: location.as_ref().map_or(0, |location| location.line()),
+                    column: location.as_ref().map_or(0, |location| location.column()),
+                    entry: None,
+                }
+            }),
+        }
+    }
+
+    /// Write a document
+    pub fn write(self, document: &Value) -> Result<String, String> {
+        match self {
+            Format::Json => serde_json::to_string_pretty(document).map_err(|e| e.to_string()),
+            // TOML has no null, a missing value means the same
+            Format::Toml => {
+                toml::to_string_pretty(&without_nulls(document.clone())).map_err(|e| e.to_string())
+            }
+            Format::Yaml => serde_yaml::to_string(document).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// 1-based line and column of the byte `offset`
+fn line_and_column(contents: &str, offset: usize) -> (usize, usize) {
+    let before = &contents[..offset.min(contents.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    (line, column)
+}
+
+fn without_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key, without_nulls(value)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(without_nulls).collect()),
+        value => value,
+    }
+}
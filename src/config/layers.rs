@@ -0,0 +1,425 @@
+//! Layers of the configuration, merged in this order:
+//! 1. the system file provided by an administrator, e.g. `/etc/music_hotkey_mapper/config.json`
+//! 2. the user's file
+//! 3. `--set key=value` overrides given at startup
+//!
+//! Tables are merged entry by entry, so a user's bindings are added to the
+//! system ones. Anything else replaces the value below it, and `null` removes
+//! it. Entries listed in the system file's `locked` list keep their system value.
+
+use super::{migrate, remove_entry, Format, ParseError};
+use serde_json::{Map, Value};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// A `--set key=value` override, e.g. `profiles.Default.hotkeys.F5=Next`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Override {
+    path: Vec<String>,
+    value: Value,
+}
+
+impl FromStr for Override {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected KEY=VALUE, got '{}'", s))?;
+        let path = split_path(key)?;
+        if path.is_empty() {
+            return Err(format!("missing key in '{}'", s));
+        }
+        // JSON values, anything else is a string: `backups=0`, `F5=Next`, `F6={"Seek":10}`
+        let value = serde_json::from_str(value).unwrap_or_else(|_| Value::from(value));
+        Ok(Override { path, value })
+    }
+}
+
+/// Everything merged with the user's file
+#[derive(Debug, Default)]
+pub struct Layers {
+    /// User's file given with `--config` or `MHM_CONFIG`
+    pub file: Option<PathBuf>,
+    /// Migrated system file, without its `locked` list
+    system: Map<String, Value>,
+    locked: Vec<Vec<String>>,
+    overrides: Vec<Override>,
+}
+
+impl Layers {
+    pub fn new(file: Option<PathBuf>, overrides: Vec<Override>) -> Self {
+        Layers {
+            file,
+            overrides,
+            ..Layers::default()
+        }
+    }
+
+    /// Add the system file at `path`, if there is one
+    pub fn read_system_file(&mut self, path: &Path) -> Result<(), String> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        let format = Format::of(path).unwrap_or(Format::Json);
+        self.set_system(format.parse(&contents))
+            .map_err(|e| format!("{} is invalid: {}", path.display(), e))
+    }
+
+    fn set_system(&mut self, document: Result<Value, ParseError>) -> Result<(), ParseError> {
+        let mut document = document?;
+        migrate(&mut document)?;
+        let Value::Object(mut system) = document else {
+            unreachable!("migrate only accepts objects");
+        };
+
+        let locked = system
+            .remove("locked")
+            .unwrap_or_else(|| Value::Array(Vec::new()));
+        self.locked = match serde_json::from_value::<Vec<String>>(locked) {
+            Ok(locked) => locked
+                .iter()
+                .map(|key| split_path(key))
+                .collect::<Result<_, _>>()
+                .map_err(|message| ParseError {
+                    message,
+                    line: 0,
+                    column: 0,
+                    entry: Some("locked".to_string()),
+                })?,
+            Err(e) => {
+                return Err(ParseError {
+                    message: e.to_string(),
+                    line: 0,
+                    column: 0,
+                    entry: Some("locked".to_string()),
+                })
+            }
+        };
+        self.system = system;
+        Ok(())
+    }
+
+    /// Whether the user's file is used as it is
+    pub fn is_empty(&self) -> bool {
+        self.system.is_empty() && self.overrides.is_empty()
+    }
+
+    /// The configuration made of the user's migrated `document` and the other layers
+    pub fn apply(&self, document: Value) -> Value {
+        let mut merged = Value::Object(self.system.clone());
+        merge(&mut merged, document);
+        for o in &self.overrides {
+            set_entry(&mut merged, &o.path, o.value.clone());
+        }
+
+        for path in &self.locked {
+            let system = entry(&Value::Object(self.system.clone()), path).cloned();
+            if entry(&merged, path) != system.as_ref() {
+                log::warn!("{} is locked by the system configuration", path.join("."));
+                match system {
+                    Some(value) => set_entry(&mut merged, path, value),
+                    None => {
+                        remove_entry(&mut merged, path);
+                    }
+                }
+            }
+        }
+        merged
+    }
+
+    /// What to write to the user's file for the configuration `document`:
+    /// only what differs from the system file. Overridden entries keep the
+    /// value they have in `saved`, the user's file as it is now.
+    pub fn user_part(&self, document: Value, saved: &Value) -> Value {
+        let mut user = difference(document, &Value::Object(self.system.clone()))
+            .unwrap_or_else(|| Value::Object(Map::new()));
+
+        for path in &self.locked {
+            remove_entry(&mut user, path);
+        }
+        for o in &self.overrides {
+            match entry(saved, &o.path) {
+                Some(value) => set_entry(&mut user, &o.path, value.clone()),
+                None => {
+                    remove_entry(&mut user, &o.path);
+                }
+            }
+        }
+
+        // Without it the file would be migrated as one from the first release
+        if let Value::Object(user) = &mut user {
+            user.shift_insert(0, "version".to_string(), super::CONFIG_VERSION.into());
+        }
+        user
+    }
+}
+
+/// Segments of `key`, separated by dots. Segments with dots of their own,
+/// e.g. profile names, are quoted: `profiles."v1.2".backend`
+fn split_path(key: &str) -> Result<Vec<String>, String> {
+    let mut segments = Vec::new();
+    let mut rest = key;
+    while !rest.is_empty() {
+        let segment = if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted
+                .find('"')
+                .ok_or_else(|| format!("missing closing quote in '{}'", key))?;
+            rest = &quoted[end + 1..];
+            if !rest.is_empty() && !rest.starts_with('.') {
+                return Err(format!("expected a dot after the quotes in '{}'", key));
+            }
+            &quoted[..end]
+        } else {
+            let end = rest.find('.').unwrap_or(rest.len());
+            let segment = &rest[..end];
+            rest = &rest[end..];
+            segment
+        };
+        if !segment.is_empty() {
+            segments.push(segment.to_string());
+        }
+        rest = rest.strip_prefix('.').unwrap_or(rest);
+    }
+    Ok(segments)
+}
+
+/// Merge `overlay` into `base`
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                if value.is_null() {
+                    base.remove(&key);
+                    continue;
+                }
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// What has to be merged into `base` to get `value`, `None` if they are the same
+fn difference(value: Value, base: &Value) -> Option<Value> {
+    match (value, base) {
+        (value, base) if &value == base => None,
+        (Value::Object(value), Value::Object(base)) => {
+            let mut changed = Map::new();
+            for (key, below) in base {
+                if !value.contains_key(key) && !below.is_null() {
+                    changed.insert(key.clone(), Value::Null);
+                }
+            }
+            for (key, value) in value {
+                let change = match base.get(&key) {
+                    Some(below) => difference(value, below),
+                    None => Some(value),
+                };
+                if let Some(change) = change {
+                    changed.insert(key, change);
+                }
+            }
+            Some(Value::Object(changed))
+        }
+        (value, _) => Some(value),
+    }
+}
+
+fn entry<'a>(value: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, key| value.get(key))
+}
+
+/// Set the entry at `path`, creating the tables on the way
+fn set_entry(value: &mut Value, path: &[String], new: Value) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+    let mut table = value;
+    for key in parents {
+        if !table.is_object() {
+            *table = Value::Object(Map::new());
+        }
+        table = table
+            .as_object_mut()
+            .unwrap()
+            .entry(key.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+    if !table.is_object() {
+        *table = Value::Object(Map::new());
+    }
+    table.as_object_mut().unwrap().insert(last.clone(), new);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn layers(system: Value, overrides: &[&str]) -> Layers {
+        let mut layers = Layers::new(None, overrides.iter().map(|o| o.parse().unwrap()).collect());
+        layers.set_system(Ok(system)).unwrap();
+        layers
+    }
+
+    fn system() -> Value {
+        json!({
+            "version": 1,
+            "profiles": {
+                "Default": {
+                    "hotkeys": { "F7": "Previous", "F12": "ToggleEnabled" },
+                    "backend": "System"
+                }
+            },
+            "backups": 10,
+            "locked": ["profiles.Default.hotkeys.F12", "backups"]
+        })
+    }
+
+    #[test]
+    fn user_bindings_overlay_system_bindings() {
+        let layers = layers(system(), &[]);
+        let merged = layers.apply(json!({
+            "version": 1,
+            "profiles": { "Default": { "hotkeys": { "F7": "Next", "F8": "PlayPause" } } }
+        }));
+
+        assert_eq!(
+            merged["profiles"]["Default"]["hotkeys"],
+            json!({ "F7": "Next", "F12": "ToggleEnabled", "F8": "PlayPause" })
+        );
+        assert_eq!(merged["profiles"]["Default"]["backend"], "System");
+        assert!(merged.get("locked").is_none());
+    }
+
+    #[test]
+    fn null_removes_a_system_entry() {
+        let layers = layers(system(), &[]);
+        let merged = layers.apply(json!({
+            "profiles": { "Default": { "hotkeys": { "F7": null } } }
+        }));
+        assert_eq!(
+            merged["profiles"]["Default"]["hotkeys"],
+            json!({ "F12": "ToggleEnabled" })
+        );
+    }
+
+    #[test]
+    fn locked_entries_keep_the_system_value() {
+        let layers = layers(
+            system(),
+            &["backups=0", "profiles.Default.hotkeys.F12=Stop"],
+        );
+        let merged = layers.apply(json!({
+            "profiles": { "Default": { "hotkeys": { "F12": "PlayPause", "F9": "Next" } } },
+            "backups": 1
+        }));
+
+        assert_eq!(merged["backups"], 10);
+        assert_eq!(
+            merged["profiles"]["Default"]["hotkeys"]["F12"],
+            "ToggleEnabled"
+        );
+        assert_eq!(merged["profiles"]["Default"]["hotkeys"]["F9"], "Next");
+    }
+
+    #[test]
+    fn locked_entry_missing_in_system_file_stays_unset() {
+        let layers = layers(
+            json!({ "version": 1, "locked": ["profiles.Default.hotkeys.F1"] }),
+            &[],
+        );
+        let merged = layers.apply(json!({
+            "profiles": { "Default": { "hotkeys": { "F1": "Next", "F2": "Stop" } } }
+        }));
+        assert_eq!(
+            merged["profiles"]["Default"]["hotkeys"],
+            json!({ "F2": "Stop" })
+        );
+    }
+
+    #[test]
+    fn overrides_win_over_the_user_file() {
+        let layers = layers(
+            json!({}),
+            &[
+                "max_running_commands=8",
+                "profiles.Work.hotkeys.F5=Next",
+                "profiles.Work.hotkeys.F6={\"Seek\":10}",
+            ],
+        );
+        let merged = layers.apply(json!({ "max_running_commands": 2 }));
+
+        assert_eq!(merged["max_running_commands"], 8);
+        assert_eq!(
+            merged["profiles"]["Work"]["hotkeys"],
+            json!({ "F5": "Next", "F6": { "Seek": 10 } })
+        );
+    }
+
+    #[test]
+    fn saves_only_what_differs_from_the_system_file() {
+        let layers = layers(system(), &["script_timeout=60"]);
+        let saved = json!({ "version": 1, "script_timeout": 5 });
+        let mut document = layers.apply(saved.clone());
+        document["profiles"]["Default"]["hotkeys"]["F8"] = json!("PlayPause");
+        document["profiles"]["Default"]["hotkeys"]["F12"] = json!("Stop");
+        document["profiles"]["Default"]["hotkeys"]
+            .as_object_mut()
+            .unwrap()
+            .remove("F7");
+
+        let user = layers.user_part(document.clone(), &saved);
+        assert_eq!(
+            user,
+            json!({
+                "version": 1,
+                "profiles": { "Default": { "hotkeys": { "F7": null, "F8": "PlayPause" } } },
+                "script_timeout": 5
+            })
+        );
+
+        // Loading it again gives the same, apart from the locked and overridden entries
+        let mut expected = document;
+        expected["profiles"]["Default"]["hotkeys"]["F12"] = json!("ToggleEnabled");
+        assert_eq!(layers.apply(user), expected);
+    }
+
+    #[test]
+    fn without_layers_the_whole_file_is_saved() {
+        let layers = Layers::default();
+        let document = json!({ "version": 1, "backups": 3, "schedule": [] });
+        assert!(layers.is_empty());
+        assert_eq!(layers.apply(document.clone()), document);
+        assert_eq!(layers.user_part(document.clone(), &json!({})), document);
+    }
+
+    #[test]
+    fn parses_overrides() {
+        let o: Override = "backups=0".parse().unwrap();
+        assert_eq!(
+            (o.path.clone(), o.value),
+            (vec!["backups".to_string()], json!(0))
+        );
+        let o: Override = "active_profile=Work Day".parse().unwrap();
+        assert_eq!(o.value, json!("Work Day"));
+        assert!("backups".parse::<Override>().is_err());
+
+        // Names with dots are quoted
+        let o: Override = r#"profiles."v1.2".hotkeys.F5=Next"#.parse().unwrap();
+        assert_eq!(o.path, ["profiles", "v1.2", "hotkeys", "F5"]);
+        assert!(r#"profiles."v1.2.hotkeys.F5=Next"#.parse::<Override>().is_err());
+        assert!(r#"profiles."v1"2.hotkeys=1"#.parse::<Override>().is_err());
+        assert!("=1".parse::<Override>().is_err());
+    }
+}
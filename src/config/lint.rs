@@ -0,0 +1,629 @@
+//! Checks for configurations that load, but won't work as intended,
+//! e.g. a `SwitchProfile` to a profile that doesn't exist.
+
+use super::{Backend, Config, Format, MediaAction, RuleTarget, ScriptSource, Step};
+use crate::actions::MAX_MINUTES;
+use crate::scheduler::CronExpr;
+use serde::de::{DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Works, but probably not as intended
+    Warning,
+    /// Doesn't work
+    Error,
+}
+
+/// A problem found in a configuration
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Finding {
+    pub severity: Severity,
+    /// Path of the entry, e.g. `profiles.Default.hotkeys.F5`
+    pub entry: String,
+    pub message: String,
+    /// Position in the file, known for syntax errors
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+}
+
+impl Finding {
+    fn new(severity: Severity, entry: impl Into<String>, message: impl Into<String>) -> Self {
+        Finding {
+            severity,
+            entry: entry.into(),
+            message: message.into(),
+            line: None,
+            column: None,
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: ", severity)?;
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            write!(f, "line {}, column {}: ", line, column)?;
+        }
+        if !self.entry.is_empty() {
+            write!(f, "{}: ", self.entry)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Check the contents of a configuration file
+pub fn check_file(contents: &str, format: Format) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    let config = match Config::parse(contents, format) {
+        Ok(config) => config,
+        Err(error) => {
+            findings.push(Finding {
+                line: (error.line > 0).then_some(error.line),
+                column: (error.line > 0).then_some(error.column),
+                ..Finding::new(
+                    Severity::Error,
+                    error.entry.clone().unwrap_or_default(),
+                    error.message.clone(),
+                )
+            });
+            // Go on with what is valid, to report everything at once
+            match Config::parse_lenient(contents, format) {
+                Ok((config, _)) => config,
+                Err(_) => return findings,
+            }
+        }
+    };
+
+    // The other formats reject duplicates while parsing
+    if format == Format::Json {
+        for entry in duplicate_entries(contents) {
+            findings.push(Finding::new(
+                Severity::Warning,
+                entry,
+                "is given more than once, only the last one is used",
+            ));
+        }
+    }
+
+    findings.extend(check(&config));
+    findings
+}
+
+/// Check a loaded configuration
+pub fn check(config: &Config) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    for (name, profile) in &config.profiles {
+        let mut bindings: Vec<_> = profile.hotkeys.iter().collect();
+        bindings.sort_by_key(|(key, _)| format!("{:?}", key));
+        for (key, action) in bindings {
+            let entry = format!("profiles.{}.hotkeys.{:?}", name, key);
+            check_action(
+                config,
+                Some(&profile.backend),
+                action,
+                &entry,
+                &mut findings,
+            );
+        }
+    }
+
+    for (name, profile) in &config.profiles {
+        let entry = format!("profiles.{}.backend", name);
+        check_backend(&profile.backend, &entry, &mut findings);
+    }
+
+    for (index, scheduled) in config.schedule.iter().enumerate() {
+        if let Err(e) = CronExpr::try_from(&scheduled.when) {
+            findings.push(Finding::new(
+                Severity::Error,
+                format!("schedule.{}.when", index),
+                e,
+            ));
+        }
+        // Runs with the backend of whichever profile is active then
+        let entry = format!("schedule.{}.action", index);
+        check_action(config, None, &scheduled.action, &entry, &mut findings);
+    }
+
+    for (index, rule) in config.window_rules.iter().enumerate() {
+        let entry = format!("window_rules.{}", index);
+        if rule.class.is_empty() && rule.title.is_empty() {
+            findings.push(Finding::new(
+                Severity::Warning,
+                &entry,
+                "has neither class nor title, so it never matches",
+            ));
+        }
+        if let RuleTarget::Profile(name) = &rule.target {
+            if !config.profiles.contains_key(name) {
+                findings.push(Finding::new(
+                    Severity::Error,
+                    format!("{}.target", entry),
+                    format!("unknown profile '{}'", name),
+                ));
+            }
+        }
+    }
+
+    findings
+}
+
+/// Check an action run with `backend`, `None` if it isn't known
+fn check_action(
+    config: &Config,
+    backend: Option<&Backend>,
+    action: &MediaAction,
+    entry: &str,
+    findings: &mut Vec<Finding>,
+) {
+    match action {
+        MediaAction::SwitchProfile(name) if !config.profiles.contains_key(name) => {
+            findings.push(Finding::new(
+                Severity::Error,
+                entry,
+                format!("switches to unknown profile '{}'", name),
+            ));
+        }
+        MediaAction::Seek(_)
+        | MediaAction::SpeedUp
+        | MediaAction::SpeedDown
+        | MediaAction::ChapterNext
+        | MediaAction::ChapterPrevious
+        | MediaAction::ToggleSubtitles
+            if backend.is_some_and(|backend| !matches!(backend, Backend::Mpv(_))) =>
+        {
+            findings.push(Finding::new(
+                Severity::Warning,
+                entry,
+                format!(
+                    "{} only works with the mpv backend, this profile uses another player",
+                    action.name()
+                ),
+            ));
+        }
+        MediaAction::SleepTimer(minutes) if *minutes > MAX_MINUTES => {
+            findings.push(Finding::new(
+                Severity::Error,
+                entry,
+                format!(
+                    "sets a sleep timer of {} minutes, at most {} are added at a time",
+                    minutes, MAX_MINUTES
+                ),
+            ));
+        }
+        MediaAction::RunCommand { program, .. } if program.trim().is_empty() => {
+            findings.push(Finding::new(
+                Severity::Error,
+                entry,
+                "has no program to run",
+            ));
+        }
+        MediaAction::Script(source) => {
+            if let Err(e) = compile_script(source) {
+                findings.push(Finding::new(Severity::Error, entry, e));
+            }
+        }
+        MediaAction::Macro(steps) if steps.is_empty() => {
+            findings.push(Finding::new(Severity::Warning, entry, "macro has no steps"));
+        }
+        MediaAction::Macro(steps) => {
+            for (index, step) in steps.iter().enumerate() {
+                if let Step::Action(action) = step {
+                    let entry = format!("{}.Macro.{}", entry, index);
+                    check_action(config, backend, action, &entry, findings);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Whether the script can be read and has no syntax errors
+fn compile_script(source: &ScriptSource) -> Result<(), String> {
+    let code = match source {
+        ScriptSource::Inline(code) => code.clone(),
+        ScriptSource::File(path) => {
+            let path = if path.is_relative() {
+                Config::config_dir().join(path)
+            } else {
+                path.clone()
+            };
+            std::fs::read_to_string(&path)
+                .map_err(|e| format!("script {} can't be read: {}", path.display(), e))?
+        }
+    };
+    rhai::Engine::new()
+        .compile(&code)
+        .map(|_| ())
+        .map_err(|e| format!("script has a syntax error: {}", e))
+}
+
+/// Check where the player of `backend` is reached
+fn check_backend(backend: &Backend, entry: &str, findings: &mut Vec<Finding>) {
+    match backend {
+        Backend::System => {}
+        Backend::Mpd(settings) => {
+            if let Some(socket) = &settings.socket {
+                if !socket.exists() {
+                    findings.push(Finding::new(
+                        Severity::Warning,
+                        format!("{}.Mpd.socket", entry),
+                        format!("{} doesn't exist, is MPD running?", socket.display()),
+                    ));
+                }
+            } else if settings.host.trim().is_empty() {
+                findings.push(Finding::new(
+                    Severity::Error,
+                    format!("{}.Mpd.host", entry),
+                    "has no host to connect to",
+                ));
+            } else if settings.port == 0 {
+                findings.push(Finding::new(
+                    Severity::Error,
+                    format!("{}.Mpd.port", entry),
+                    "0 is not a port MPD can listen on",
+                ));
+            }
+        }
+        Backend::Mpv(settings) => {
+            if let Some(dir) = &settings.socket_dir {
+                if !dir.is_dir() {
+                    findings.push(Finding::new(
+                        Severity::Error,
+                        format!("{}.Mpv.socket_dir", entry),
+                        format!("{} is not a directory", dir.display()),
+                    ));
+                }
+            } else if let Some(socket) = &settings.socket {
+                // Named pipes on Windows can't be checked without connecting
+                if cfg!(unix) && !socket.exists() {
+                    findings.push(Finding::new(
+                        Severity::Warning,
+                        format!("{}.Mpv.socket", entry),
+                        format!(
+                            "{} doesn't exist, is mpv started with --input-ipc-server?",
+                            socket.display()
+                        ),
+                    ));
+                }
+            } else {
+                findings.push(Finding::new(
+                    Severity::Error,
+                    format!("{}.Mpv", entry),
+                    "has neither socket nor socket_dir to connect to",
+                ));
+            }
+        }
+    }
+}
+
+/// Paths of the object entries that are given more than once. Parsers
+/// keep the last one, so the earlier ones would be lost without a word.
+fn duplicate_entries(contents: &str) -> Vec<String> {
+    let found = RefCell::new(Vec::new());
+    let mut deserializer = serde_json::Deserializer::from_str(contents);
+    let _ = Duplicates {
+        path: String::new(),
+        found: &found,
+    }
+    .deserialize(&mut deserializer);
+    found.into_inner()
+}
+
+/// Walks a document, noting the duplicate entries in `found`
+struct Duplicates<'a> {
+    path: String,
+    found: &'a RefCell<Vec<String>>,
+}
+
+impl Duplicates<'_> {
+    fn child(&self, key: &str) -> Self {
+        let path = if self.path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", self.path, key)
+        };
+        Duplicates {
+            path,
+            found: self.found,
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for Duplicates<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Duplicates<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("any value")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let mut seen = std::collections::HashSet::new();
+        while let Some(key) = map.next_key::<String>()? {
+            let child = self.child(&key);
+            if !seen.insert(key) {
+                self.found.borrow_mut().push(child.path.clone());
+            }
+            map.next_value_seed(child)?;
+        }
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let mut index = 0;
+        while seq
+            .next_element_seed(self.child(&index.to_string()))?
+            .is_some()
+        {
+            index += 1;
+        }
+        Ok(())
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_unit<E>(self) -> Result<(), E> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{MpdSettings, MpvSettings, Profile, Schedule, ScheduledAction, WindowRule};
+    use rdev::Key;
+
+    fn messages(findings: &[Finding]) -> Vec<String> {
+        findings.iter().map(|finding| finding.to_string()).collect()
+    }
+
+    #[test]
+    fn default_configuration_has_no_findings() {
+        assert_eq!(check(&Config::default()), []);
+    }
+
+    #[test]
+    fn reports_unknown_profiles() {
+        let mut config = Config::default();
+        let steps = vec![
+            Step::Delay(100),
+            Step::Action(MediaAction::SwitchProfile("Gaming".into())),
+        ];
+        config
+            .active_mut()
+            .hotkeys
+            .insert(Key::F1, MediaAction::SwitchProfile("Work".into()));
+        config
+            .active_mut()
+            .hotkeys
+            .insert(Key::F2, MediaAction::Macro(steps));
+        config.window_rules.push(WindowRule {
+            class: "steam".into(),
+            title: String::new(),
+            target: RuleTarget::Profile("Games".into()),
+        });
+
+        assert_eq!(
+            messages(&check(&config)),
+            [
+                "error: profiles.Default.hotkeys.F1: switches to unknown profile 'Work'",
+                "error: profiles.Default.hotkeys.F2.Macro.1: switches to unknown profile 'Gaming'",
+                "error: window_rules.0.target: unknown profile 'Games'",
+            ]
+        );
+    }
+
+    #[test]
+    fn mpv_actions_need_the_mpv_backend() {
+        let mut config = Config::default();
+        config
+            .active_mut()
+            .hotkeys
+            .insert(Key::F1, MediaAction::Seek(10.0));
+        config.profiles.insert(
+            "Video".into(),
+            Profile {
+                hotkeys: [(Key::F1, MediaAction::Seek(10.0))].into(),
+                backend: Backend::Mpv(MpvSettings::default()),
+            },
+        );
+
+        // Whether the mpv socket exists is checked on its own
+        let findings: Vec<_> = check(&config)
+            .into_iter()
+            .filter(|finding| finding.entry.contains(".hotkeys."))
+            .collect();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].entry, "profiles.Default.hotkeys.F1");
+        assert_eq!(findings[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn reports_scripts_that_dont_compile() {
+        let mut config = Config::default();
+        let hotkeys = &mut config.active_mut().hotkeys;
+        hotkeys.insert(
+            Key::F1,
+            MediaAction::Script(ScriptSource::Inline("let x = ;".into())),
+        );
+        hotkeys.insert(
+            Key::F2,
+            MediaAction::Script(ScriptSource::Inline("let x = 1;".into())),
+        );
+        hotkeys.insert(
+            Key::F3,
+            MediaAction::Script(ScriptSource::File("/nonexistent/script.rhai".into())),
+        );
+
+        let entries: Vec<_> = check(&config)
+            .into_iter()
+            .map(|finding| finding.entry)
+            .collect();
+        assert_eq!(
+            entries,
+            ["profiles.Default.hotkeys.F1", "profiles.Default.hotkeys.F3"]
+        );
+    }
+
+    #[test]
+    fn reports_too_long_sleep_timers() {
+        let mut config = Config::default();
+        let hotkeys = &mut config.active_mut().hotkeys;
+        hotkeys.insert(Key::F1, MediaAction::SleepTimer(MAX_MINUTES));
+        hotkeys.insert(Key::F2, MediaAction::SleepTimer(MAX_MINUTES + 1));
+
+        let findings = check(&config);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].entry, "profiles.Default.hotkeys.F2");
+        assert_eq!(findings[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn reports_invalid_schedules() {
+        let mut config = Config::default();
+        for when in [
+            Schedule::Cron("0 7 * * 1-5".into()),
+            Schedule::Cron("61 7 * * *".into()),
+            Schedule::Daily {
+                time: "25:00".into(),
+                days: Vec::new(),
+            },
+        ] {
+            config.schedule.push(ScheduledAction {
+                when,
+                action: MediaAction::PlayPause,
+            });
+        }
+
+        let findings = check(&config);
+        let entries: Vec<_> = findings.iter().map(|f| f.entry.as_str()).collect();
+        assert_eq!(entries, ["schedule.1.when", "schedule.2.when"]);
+        assert!(findings.iter().all(|f| f.severity == Severity::Error));
+    }
+
+    #[test]
+    fn reports_unreachable_players() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config::default();
+        config.active_mut().backend = Backend::Mpd(MpdSettings {
+            host: " ".into(),
+            ..MpdSettings::default()
+        });
+        let backends = [
+            ("Local", Backend::Mpd(MpdSettings::default())),
+            (
+                "Missing",
+                Backend::Mpv(MpvSettings {
+                    socket: Some(dir.path().join("mpvsocket")),
+                    socket_dir: None,
+                }),
+            ),
+            (
+                "Discovered",
+                Backend::Mpv(MpvSettings {
+                    socket: Some(dir.path().join("mpvsocket")),
+                    socket_dir: Some(dir.path().to_path_buf()),
+                }),
+            ),
+            (
+                "Nowhere",
+                Backend::Mpv(MpvSettings {
+                    socket: None,
+                    socket_dir: Some(dir.path().join("sockets")),
+                }),
+            ),
+        ];
+        for (name, backend) in backends {
+            let profile = Profile {
+                hotkeys: Default::default(),
+                backend,
+            };
+            config.profiles.insert(name.into(), profile);
+        }
+
+        let findings = check(&config);
+        let entries: Vec<_> = findings.iter().map(|f| f.entry.as_str()).collect();
+        let mut expected = vec!["profiles.Default.backend.Mpd.host"];
+        if cfg!(unix) {
+            expected.push("profiles.Missing.backend.Mpv.socket");
+        }
+        expected.push("profiles.Nowhere.backend.Mpv.socket_dir");
+        assert_eq!(entries, expected);
+    }
+
+    #[test]
+    fn checks_files_as_written() {
+        let contents = r#"{
+  "version": 1,
+  "profiles": {
+    "Default": { "hotkeys": { "F1": { "SwitchProfile": "Work" } } }
+  },
+  "schedule": [{ "when": { "Cron": "* * *" }, "action": "Stop" }]
+}"#;
+        assert_eq!(
+            messages(&check_file(contents, Format::Json))
+                .iter()
+                .map(|message| message.split(':').nth(1).unwrap().trim())
+                .collect::<Vec<_>>(),
+            ["profiles.Default.hotkeys.F1", "schedule.0.when"]
+        );
+    }
+
+    #[test]
+    fn reports_duplicates_and_syntax_errors_in_files() {
+        let duplicated = r#"{
+  "version": 1,
+  "profiles": {
+    "Default": { "hotkeys": { "F1": "Next", "F2": "Stop", "F1": "Previous" } }
+  }
+}"#;
+        assert_eq!(
+            messages(&check_file(duplicated, Format::Json)),
+            ["warning: profiles.Default.hotkeys.F1: is given more than once, only the last one is used"]
+        );
+
+        let broken = "{\n  \"version\": 1,\n  \"backups\": \"many\"\n}";
+        let findings = check_file(broken, Format::Json);
+        assert_eq!(findings.len(), 1);
+        assert_eq!((findings[0].line, findings[0].column), (Some(3), Some(19)));
+        assert_eq!(findings[0].entry, "backups");
+    }
+}
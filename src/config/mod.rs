@@ -0,0 +1,1357 @@
+mod bindings;
+mod format;
+mod layers;
+mod lint;
+mod portable;
+mod schema;
+mod store;
+
+use rdev::Key;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, OnceLock};
+
+pub use bindings::{BindingSet, Resolution};
+pub use format::Format;
+pub use layers::{Layers, Override};
+pub use lint::{check_file, Finding, Severity};
+pub use key_map_serde::parse_key;
+pub use store::Backup;
+
+/// Media control actions
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum MediaAction {
+    PlayPause,
+    Next,
+    Previous,
+    VolumeUp,
+    VolumeDown,
+    Stop,
+    /// Change the volume of a single application stream by `step` percent
+    /// (negative values lower it). `app` is matched against the stream's
+    /// application name or binary, case-insensitively.
+    AppVolume { app: String, step: i32 },
+    /// Toggle mute of a single application stream
+    AppMute { app: String },
+    /// Switch to the next output device in the configured cycle order
+    CycleOutputDevice,
+    /// Switch to the output device with the given name
+    SetOutputDevice(String),
+    /// Seek by the given number of seconds (mpv backend only)
+    Seek(f64),
+    /// Increase playback speed (mpv backend only)
+    SpeedUp,
+    /// Decrease playback speed (mpv backend only)
+    SpeedDown,
+    /// Jump to the next chapter (mpv backend only)
+    ChapterNext,
+    /// Jump to the previous chapter (mpv backend only)
+    ChapterPrevious,
+    /// Show or hide subtitles (mpv backend only)
+    ToggleSubtitles,
+    /// Run an external program. Arguments may contain the placeholders
+    /// `{key}`, `{action}`, `{title}`, `{artist}`, `{album}` and `{status}`.
+    RunCommand {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+        #[serde(default)]
+        env: BTreeMap<String, String>,
+        #[serde(default)]
+        cwd: Option<PathBuf>,
+        /// Seconds after which the program is killed
+        #[serde(default = "default_command_timeout")]
+        timeout: u64,
+        /// Run `program` as a shell command line instead of executing it directly
+        #[serde(default)]
+        shell: bool,
+    },
+    /// Run steps one after another. Pressing the key again cancels it.
+    Macro(Vec<Step>),
+    /// Run a Rhai script
+    Script(ScriptSource),
+    /// Start the sleep timer or add the given number of minutes to it.
+    /// The volume fades out over the last minute, then the player is stopped.
+    SleepTimer(u64),
+    /// Cancel the sleep timer and restore the volume
+    CancelSleepTimer,
+    /// Make the profile with the given name active
+    SwitchProfile(String),
+    /// Make the next profile (in alphabetical order) active
+    CycleProfile,
+    /// Pause or resume all other bindings, works while paused
+    ToggleEnabled,
+}
+
+/// Where the code of a `Script` action comes from
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum ScriptSource {
+    /// Script file, relative paths are resolved against the configuration directory.
+    /// Changes to the file are picked up on the next run.
+    File(PathBuf),
+    Inline(String),
+}
+
+/// A step of a macro
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum Step {
+    Action(MediaAction),
+    /// Wait for the given number of milliseconds
+    Delay(u64),
+}
+
+fn default_command_timeout() -> u64 {
+    30
+}
+
+impl MediaAction {
+    /// Name of the action as written in the configuration file
+    pub fn name(&self) -> &'static str {
+        match self {
+            MediaAction::PlayPause => "PlayPause",
+            MediaAction::Next => "Next",
+            MediaAction::Previous => "Previous",
+            MediaAction::VolumeUp => "VolumeUp",
+            MediaAction::VolumeDown => "VolumeDown",
+            MediaAction::Stop => "Stop",
+            MediaAction::AppVolume { .. } => "AppVolume",
+            MediaAction::AppMute { .. } => "AppMute",
+            MediaAction::CycleOutputDevice => "CycleOutputDevice",
+            MediaAction::SetOutputDevice(_) => "SetOutputDevice",
+            MediaAction::Seek(_) => "Seek",
+            MediaAction::SpeedUp => "SpeedUp",
+            MediaAction::SpeedDown => "SpeedDown",
+            MediaAction::ChapterNext => "ChapterNext",
+            MediaAction::ChapterPrevious => "ChapterPrevious",
+            MediaAction::ToggleSubtitles => "ToggleSubtitles",
+            MediaAction::RunCommand { .. } => "RunCommand",
+            MediaAction::Macro(_) => "Macro",
+            MediaAction::Script(_) => "Script",
+            MediaAction::SleepTimer(_) => "SleepTimer",
+            MediaAction::CancelSleepTimer => "CancelSleepTimer",
+            MediaAction::SwitchProfile(_) => "SwitchProfile",
+            MediaAction::CycleProfile => "CycleProfile",
+            MediaAction::ToggleEnabled => "ToggleEnabled",
+        }
+    }
+}
+
+/// Output device switching settings
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct OutputDeviceSettings {
+    /// Device names in the order `CycleOutputDevice` switches through them.
+    /// Empty means all available devices.
+    #[serde(default)]
+    pub cycle: Vec<String>,
+    /// Move playing streams to the new device when switching
+    #[serde(default)]
+    pub move_streams: bool,
+}
+
+/// Player backend that receives the playback actions
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum Backend {
+    /// Media keys on Windows, playerctl (MPRIS) on Linux
+    #[default]
+    System,
+    /// Music Player Daemon
+    Mpd(MpdSettings),
+    /// mpv over its JSON IPC socket
+    Mpv(MpvSettings),
+}
+
+/// Connection settings for the Music Player Daemon
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct MpdSettings {
+    #[serde(default = "default_mpd_host")]
+    pub host: String,
+    #[serde(default = "default_mpd_port")]
+    pub port: u16,
+    /// Unix socket path, used instead of host/port when set
+    #[serde(default)]
+    pub socket: Option<PathBuf>,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+fn default_mpd_host() -> String {
+    "localhost".to_string()
+}
+
+fn default_mpd_port() -> u16 {
+    6600
+}
+
+impl Default for MpdSettings {
+    fn default() -> Self {
+        MpdSettings {
+            host: default_mpd_host(),
+            port: default_mpd_port(),
+            socket: None,
+            password: None,
+        }
+    }
+}
+
+/// Connection settings for mpv's `--input-ipc-server` socket
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct MpvSettings {
+    /// Socket to connect to first
+    #[serde(default)]
+    pub socket: Option<PathBuf>,
+    /// Directory searched for sockets of running mpv instances,
+    /// the most recently started one is used
+    #[serde(default)]
+    pub socket_dir: Option<PathBuf>,
+}
+
+/// Socket mpv is started with in the README, a named pipe on Windows
+pub fn default_mpv_socket() -> PathBuf {
+    if cfg!(windows) {
+        PathBuf::from(r"\\.\pipe\mpvsocket")
+    } else {
+        PathBuf::from("/tmp/mpvsocket")
+    }
+}
+
+impl Default for MpvSettings {
+    fn default() -> Self {
+        MpvSettings {
+            socket: Some(default_mpv_socket()),
+            socket_dir: None,
+        }
+    }
+}
+
+/// An action that runs at set times
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct ScheduledAction {
+    pub when: Schedule,
+    pub action: MediaAction,
+}
+
+/// When a scheduled action runs, in local time
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum Schedule {
+    /// Every day at `time` ("HH:MM"), only on `days` if any are given
+    Daily {
+        time: String,
+        #[serde(default)]
+        days: Vec<Weekday>,
+    },
+    /// Cron expression: minute, hour, day of month, month, day of week
+    Cron(String),
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub enum Weekday {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+impl Weekday {
+    pub const ALL: [Weekday; 7] = [
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+        Weekday::Sat,
+        Weekday::Sun,
+    ];
+}
+
+/// Switches profiles while a matching window is focused
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct WindowRule {
+    /// Matched against both parts of WM_CLASS (instance and class),
+    /// ignoring case. Empty matches every window.
+    #[serde(default)]
+    pub class: String,
+    /// Part of the window title, ignoring case. Empty matches every window.
+    #[serde(default)]
+    pub title: String,
+    pub target: RuleTarget,
+}
+
+/// What happens while a window rule matches
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub enum RuleTarget {
+    /// Make the profile with the given name active
+    Profile(String),
+    /// Ignore all hotkeys
+    Disable,
+}
+
+/// Keys that can be bound in the configuration
+pub const SUPPORTED_KEYS: [Key; 12] = [
+    Key::F1,
+    Key::F2,
+    Key::F3,
+    Key::F4,
+    Key::F5,
+    Key::F6,
+    Key::F7,
+    Key::F8,
+    Key::F9,
+    Key::F10,
+    Key::F11,
+    Key::F12,
+];
+
+/// A named set of bindings with its own player backend
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct Profile {
+    #[serde(with = "key_map_serde")]
+    #[schemars(schema_with = "key_map_serde::schema")]
+    pub hotkeys: HashMap<Key, MediaAction>,
+    #[serde(default)]
+    pub backend: Backend,
+}
+
+/// Name of the profile created for configurations without profiles
+pub const DEFAULT_PROFILE: &str = "Default";
+
+fn default_profile_name() -> String {
+    DEFAULT_PROFILE.to_string()
+}
+
+/// Used while the active profile doesn't exist
+static EMPTY_PROFILE: LazyLock<Profile> = LazyLock::new(Profile::default);
+
+/// Set once at startup, before the configuration is loaded
+static LAYERS: OnceLock<Layers> = OnceLock::new();
+static NO_LAYERS: LazyLock<Layers> = LazyLock::new(Layers::default);
+
+fn layers() -> &'static Layers {
+    LAYERS.get().unwrap_or(&NO_LAYERS)
+}
+
+/// Application configuration
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct Config {
+    /// Format of the file, older files are migrated on load
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    /// Name of the profile whose bindings are in use
+    #[serde(default = "default_profile_name")]
+    pub active_profile: String,
+    #[serde(default)]
+    pub output_devices: OutputDeviceSettings,
+    /// How many `RunCommand` programs may run at the same time
+    #[serde(default = "default_max_running_commands")]
+    pub max_running_commands: usize,
+    /// Seconds after which a `Script` is stopped
+    #[serde(default = "default_script_timeout")]
+    pub script_timeout: u64,
+    /// How many previous versions of the file are kept when saving
+    #[serde(default = "default_backups")]
+    pub backups: usize,
+    /// Actions that run at set times
+    #[serde(default)]
+    pub schedule: Vec<ScheduledAction>,
+    /// Profile switching by the focused window, the first matching rule wins
+    #[serde(default)]
+    pub window_rules: Vec<WindowRule>,
+}
+
+fn default_max_running_commands() -> usize {
+    4
+}
+
+fn default_script_timeout() -> u64 {
+    10
+}
+
+fn default_backups() -> usize {
+    5
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let mut hotkeys = HashMap::new();
+
+        // Default hotkey bindings
+        // F7 - Previous track
+        hotkeys.insert(Key::F7, MediaAction::Previous);
+        // F8 - Play/Pause
+        hotkeys.insert(Key::F8, MediaAction::PlayPause);
+        // F9 - Next track
+        hotkeys.insert(Key::F9, MediaAction::Next);
+        // F10 - Stop
+        hotkeys.insert(Key::F10, MediaAction::Stop);
+        // F11 - Volume Down
+        hotkeys.insert(Key::F11, MediaAction::VolumeDown);
+        // F12 - Volume Up
+        hotkeys.insert(Key::F12, MediaAction::VolumeUp);
+
+        let profile = Profile {
+            hotkeys,
+            backend: Backend::default(),
+        };
+
+        Config {
+            version: CONFIG_VERSION,
+            profiles: BTreeMap::from([(default_profile_name(), profile)]),
+            active_profile: default_profile_name(),
+            output_devices: OutputDeviceSettings::default(),
+            max_running_commands: default_max_running_commands(),
+            script_timeout: default_script_timeout(),
+            backups: default_backups(),
+            schedule: Vec::new(),
+            window_rules: Vec::new(),
+        }
+    }
+}
+
+/// Why the configuration file could not be loaded
+#[derive(Debug)]
+pub enum LoadError {
+    /// There is no configuration file yet
+    Missing,
+    Io(io::Error),
+    /// The file is not a valid configuration
+    Invalid(ParseError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Missing => write!(f, "the configuration file does not exist"),
+            LoadError::Io(e) => write!(f, "{}", e),
+            LoadError::Invalid(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Where and why a configuration file is invalid
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+    /// Path of the offending entry, e.g. `profiles.Default.hotkeys`
+    pub entry: Option<String>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Errors found after a migration have no position in the file
+        if self.line > 0 {
+            write!(f, "line {}, column {}: ", self.line, self.column)?;
+        }
+        write!(f, "{}", self.message)?;
+        if let Some(entry) = &self.entry {
+            write!(f, " (in `{}`)", entry)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<serde_path_to_error::Error<serde_json::Error>> for ParseError {
+    fn from(error: serde_path_to_error::Error<serde_json::Error>) -> Self {
+        let entry = error.path().to_string();
+        let error = error.into_inner();
+        ParseError {
+            // serde_json appends the position, it is reported separately
+            message: error
+                .to_string()
+                .split(" at line ")
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            line: error.line(),
+            column: error.column(),
+            entry: (entry != ".").then_some(entry),
+        }
+    }
+}
+
+/// Entries dropped when a configuration is loaded leniently
+const MAX_SKIPPED: usize = 100;
+
+/// Migrations of the configuration file, `MIGRATIONS[n]` turns version `n` into `n + 1`
+const MIGRATIONS: [fn(&mut serde_json::Map<String, Value>); 1] = [move_bindings_into_profile];
+
+/// Version of the configuration files written by this release
+pub const CONFIG_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// Version 0 had one set of bindings and one backend at the top level,
+/// they become the "Default" profile (version 0 files may have profiles too)
+fn move_bindings_into_profile(config: &mut serde_json::Map<String, Value>) {
+    let hotkeys = config.remove("hotkeys");
+    let backend = config.remove("backend");
+    if hotkeys.is_none() && backend.is_none() {
+        return;
+    }
+
+    let profiles = config
+        .entry("profiles")
+        .or_insert_with(|| Value::Object(Default::default()));
+    let Some(profile) = profiles.as_object_mut().map(|profiles| {
+        profiles
+            .entry(DEFAULT_PROFILE)
+            .or_insert_with(|| serde_json::json!({ "hotkeys": {} }))
+    }) else {
+        // Not an object, reported when the result is loaded
+        return;
+    };
+    let Some(profile) = profile.as_object_mut() else {
+        return;
+    };
+
+    match (hotkeys, profile.get_mut("hotkeys")) {
+        // The top-level bindings win, as they did when both were read
+        (Some(Value::Object(hotkeys)), Some(Value::Object(existing))) => existing.extend(hotkeys),
+        (Some(hotkeys), _) => {
+            profile.insert("hotkeys".to_string(), hotkeys);
+        }
+        (None, _) => {}
+    }
+    if let Some(backend) = backend {
+        profile.insert("backend".to_string(), backend);
+    }
+}
+
+/// Bring a configuration document to `CONFIG_VERSION`, returns the version it had
+fn migrate(document: &mut Value) -> Result<u32, ParseError> {
+    let error = |message: String| ParseError {
+        message,
+        line: 0,
+        column: 0,
+        entry: Some("version".to_string()),
+    };
+
+    let Value::Object(config) = document else {
+        return Err(ParseError {
+            entry: None,
+            ..error("expected a JSON object".to_string())
+        });
+    };
+    let version = match config.get("version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| error(format!("invalid version {}", version)))?,
+    };
+    if version > CONFIG_VERSION {
+        return Err(error(format!(
+            "version {} was written by a newer release, this one reads up to version {}",
+            version, CONFIG_VERSION
+        )));
+    }
+
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(config);
+    }
+    config.insert("version".to_string(), CONFIG_VERSION.into());
+    Ok(version)
+}
+
+impl Config {
+    /// Get the directory of the configuration file, the executable's in portable mode
+    pub fn config_dir() -> PathBuf {
+        if let Some(dir) = portable::dir() {
+            return dir.to_path_buf();
+        }
+        let mut path = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
+        path.push("music_hotkey_mapper");
+        std::fs::create_dir_all(&path).ok();
+        path
+    }
+
+    /// Get the path to the configuration file
+    pub fn config_path() -> PathBuf {
+        match &layers().file {
+            Some(file) => file.clone(),
+            None => find_config_file(&Self::config_dir()),
+        }
+    }
+
+    /// Get the path to the system configuration file, provided by an administrator
+    pub fn system_config_path() -> PathBuf {
+        #[cfg(windows)]
+        let dir = std::env::var_os("ProgramData")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(r"C:\ProgramData"));
+        #[cfg(not(windows))]
+        let dir = PathBuf::from("/etc");
+        find_config_file(&dir.join("music_hotkey_mapper"))
+    }
+
+    /// Run portable if asked for by `--portable` or the files next to the executable
+    pub fn set_portable(forced: bool) {
+        portable::init(forced);
+    }
+
+    /// Whether the configuration is kept next to the executable
+    pub fn is_portable() -> bool {
+        portable::dir().is_some()
+    }
+
+    /// Log file for this run in portable mode
+    pub fn open_log_file() -> Option<io::Result<fs::File>> {
+        portable::dir().map(portable::open_log)
+    }
+
+    /// Use `layers` for loading and saving the configuration from now on
+    pub fn set_layers(layers: Layers) {
+        if LAYERS.set(layers).is_err() {
+            log::warn!("Configuration layers are already set");
+        }
+    }
+
+    /// Format of the configuration file
+    fn format() -> Format {
+        Format::of(&Self::config_path()).unwrap_or(Format::Json)
+    }
+
+    /// Load configuration from file
+    pub fn load() -> Result<Self, LoadError> {
+        let path = Self::config_path();
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(LoadError::Missing),
+            Err(e) => return Err(LoadError::Io(e)),
+        };
+        let (config, version) =
+            Self::parse_versioned(&contents, Self::format()).map_err(LoadError::Invalid)?;
+
+        // Older files are upgraded once, the original is kept next to it
+        if version < CONFIG_VERSION {
+            let backup = store::with_suffix(&path, &format!(".v{}.bak", version));
+            log::info!(
+                "Migrating the configuration from version {} to {}, the old file is kept as {}",
+                version,
+                CONFIG_VERSION,
+                backup.display()
+            );
+            match fs::copy(&path, &backup) {
+                Ok(_) => {
+                    if let Err(e) = config.save() {
+                        log::error!("Failed to save the migrated configuration: {}", e);
+                    }
+                }
+                Err(e) => log::error!("Failed to back up {}: {}", path.display(), e),
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Parse the contents of a configuration file
+    pub fn parse(contents: &str, format: Format) -> Result<Self, ParseError> {
+        Self::parse_versioned(contents, format).map(|(config, _)| config)
+    }
+
+    /// Parse and migrate a configuration file, also returns the version it had
+    fn parse_versioned(contents: &str, format: Format) -> Result<(Self, u32), ParseError> {
+        Self::parse_layered(contents, format, layers())
+    }
+
+    /// Parse and migrate a user's file, merged with `layers`
+    fn parse_layered(
+        contents: &str,
+        format: Format,
+        layers: &Layers,
+    ) -> Result<(Self, u32), ParseError> {
+        let mut document = format.parse(contents)?;
+        let version = migrate(&mut document)?;
+
+        let mut config: Config = if !layers.is_empty() {
+            serde_path_to_error::deserialize(&layers.apply(document))?
+        } else if version == CONFIG_VERSION && format == Format::Json {
+            // Read from the text again, so errors point at their line
+            let deserializer = &mut serde_json::Deserializer::from_str(contents);
+            serde_path_to_error::deserialize(deserializer)?
+        } else {
+            serde_path_to_error::deserialize(&document)?
+        };
+        config.ensure_active_profile();
+        Ok((config, version))
+    }
+
+    /// Parse the contents of a configuration file, leaving out the invalid entries.
+    /// Returns the configuration and a description of each entry left out.
+    /// Fails only if the file can't be read as a table, or is from a newer release.
+    pub fn parse_lenient(
+        contents: &str,
+        format: Format,
+    ) -> Result<(Self, Vec<String>), ParseError> {
+        let mut value = format.parse(contents)?;
+        migrate(&mut value)?;
+        let mut value = layers().apply(value);
+        let mut skipped = Vec::new();
+
+        // Drop single entries of the lists, so the rest of the list survives
+        if let Some(root) = value.as_object_mut() {
+            if let Some(Value::Object(profiles)) = root.get_mut("profiles") {
+                for (name, profile) in profiles.iter_mut() {
+                    if let Some(hotkeys) = profile.get_mut("hotkeys") {
+                        let path = format!("profiles.{}.hotkeys", name);
+                        retain_valid_bindings(hotkeys, &path, &mut skipped);
+                    }
+                }
+            }
+            retain_valid::<ScheduledAction>(root, "schedule", &mut skipped);
+            retain_valid::<WindowRule>(root, "window_rules", &mut skipped);
+        }
+
+        // Anything else that is invalid is removed, falling back to its default
+        for _ in 0..MAX_SKIPPED {
+            let error = match serde_path_to_error::deserialize::<_, Config>(&value) {
+                Ok(mut config) => {
+                    config.ensure_active_profile();
+                    return Ok((config, skipped));
+                }
+                Err(error) => error,
+            };
+            let path: Vec<String> = error
+                .path()
+                .iter()
+                .map_while(|segment| match segment {
+                    serde_path_to_error::Segment::Map { key } => Some(key.clone()),
+                    serde_path_to_error::Segment::Enum { variant } => Some(variant.clone()),
+                    serde_path_to_error::Segment::Seq { index } => Some(index.to_string()),
+                    serde_path_to_error::Segment::Unknown => None,
+                })
+                .collect();
+            if path.is_empty() || !remove_entry(&mut value, &path) {
+                break;
+            }
+            skipped.push(format!("`{}`: {}", path.join("."), error.inner()));
+        }
+
+        Ok((Config::default(), vec!["the whole file, using the default configuration".to_string()]))
+    }
+
+    /// Keep an invalid configuration file for the user to fix, and load what
+    /// is valid in it. Returns the configuration and a message for the UI.
+    pub fn recover(error: &ParseError) -> (Self, String) {
+        let path = Self::config_path();
+        let contents = fs::read_to_string(&path).unwrap_or_default();
+
+        let timestamp = jiff::Zoned::now().strftime("%Y%m%d-%H%M%S").to_string();
+        let broken = store::with_suffix(&path, &format!(".broken-{}", timestamp));
+        let mut message = format!("The configuration file is invalid: {}.", error);
+        match fs::rename(&path, &broken) {
+            Ok(()) => message.push_str(&format!(" It was kept as {}.", broken.display())),
+            Err(e) => {
+                // Not saving over it then, so nothing is lost
+                log::error!("Failed to move {}: {}", path.display(), e);
+                message.push_str(" Changes are not saved until it is fixed.");
+                let config = Self::parse_lenient(&contents, Self::format())
+                    .map(|(config, _)| config)
+                    .unwrap_or_default();
+                return (config, message);
+            }
+        }
+
+        let config = match Self::parse_lenient(&contents, Self::format()) {
+            Ok((config, skipped)) => {
+                if !skipped.is_empty() {
+                    message.push_str(" Left out:");
+                }
+                for entry in skipped {
+                    message.push_str(&format!("\n• {}", entry));
+                }
+                config
+            }
+            Err(_) => {
+                message.push_str(" Using the default configuration.");
+                Config::default()
+            }
+        };
+
+        if let Err(e) = config.save() {
+            log::error!("Failed to save the recovered configuration: {}", e);
+        }
+        (config, message)
+    }
+
+    /// Contents of a configuration file in `format`
+    pub fn to_text(&self, format: Format) -> Result<String, String> {
+        let document = serde_json::to_value(self).map_err(|e| e.to_string())?;
+        format.write(&document)
+    }
+
+    /// JSON Schema of the configuration file
+    pub fn schema() -> Value {
+        schema::generate()
+    }
+
+    /// Problems that keep the configuration from working as intended
+    pub fn check(&self) -> Vec<Finding> {
+        lint::check(self)
+    }
+
+    /// Whether both would be saved the same
+    pub fn same_as(&self, other: &Config) -> bool {
+        serde_json::to_value(self).ok() == serde_json::to_value(other).ok()
+    }
+
+    /// Keep `name` active if there is such a profile, e.g. one switched to at runtime
+    pub fn keep_active_profile(&mut self, name: &str) {
+        if self.profiles.contains_key(name) {
+            self.active_profile = name.to_string();
+        }
+    }
+
+    /// Make sure the active profile exists
+    fn ensure_active_profile(&mut self) {
+        if self.profiles.is_empty() {
+            self.profiles.insert(default_profile_name(), Profile::default());
+        }
+
+        if !self.profiles.contains_key(&self.active_profile) {
+            self.active_profile = self.profiles.keys().next().cloned().unwrap_or_default();
+        }
+    }
+
+    /// The profile whose bindings are in use
+    pub fn active(&self) -> &Profile {
+        self.profiles.get(&self.active_profile).unwrap_or(&EMPTY_PROFILE)
+    }
+
+    /// The profile whose bindings are in use, created if it doesn't exist
+    pub fn active_mut(&mut self) -> &mut Profile {
+        self.profiles.entry(self.active_profile.clone()).or_default()
+    }
+
+    /// The settings an action runs with, without the bindings, the schedule
+    /// and the window rules, so a key press doesn't copy all of them
+    pub fn action_settings(&self) -> Config {
+        let profile = Profile {
+            hotkeys: HashMap::new(),
+            backend: self.active().backend.clone(),
+        };
+        Config {
+            version: self.version,
+            profiles: BTreeMap::from([(self.active_profile.clone(), profile)]),
+            active_profile: self.active_profile.clone(),
+            output_devices: self.output_devices.clone(),
+            max_running_commands: self.max_running_commands,
+            script_timeout: self.script_timeout,
+            backups: self.backups,
+            schedule: Vec::new(),
+            window_rules: Vec::new(),
+        }
+    }
+
+    /// Name of the profile after `name`, wrapping around
+    pub fn next_profile(&self, name: &str) -> Option<&String> {
+        self.profiles
+            .keys()
+            .skip_while(|profile| *profile != name)
+            .nth(1)
+            .or_else(|| self.profiles.keys().next())
+    }
+
+    /// The configuration of a first run, made of the other layers and an empty
+    /// user's file, and the user's file to create for it
+    fn first_run(layers: &Layers) -> Result<(Self, Value), ParseError> {
+        let empty = serde_json::json!({ "version": CONFIG_VERSION });
+        let (config, _) = Self::parse_layered(&empty.to_string(), Format::Json, layers)?;
+        Ok((config, empty))
+    }
+
+    /// Create the configuration file on the first run. With a system file or
+    /// `--set`, the user's file starts empty, so copies of the defaults don't
+    /// shadow the system settings.
+    pub fn create() -> Self {
+        let layers = layers();
+        if layers.is_empty() {
+            let config = Config::default();
+            if let Err(e) = config.save() {
+                log::error!("Failed to save default configuration: {}", e);
+            }
+            return config;
+        }
+
+        match Self::first_run(layers) {
+            Ok((config, document)) => {
+                if let Err(e) = Self::write_document(&Self::config_path(), document, config.backups) {
+                    log::error!("Failed to save the configuration: {}", e);
+                }
+                config
+            }
+            Err(e) => {
+                // Not saved, the system file or `--set` is to be fixed
+                log::error!("The system configuration and --set can't be applied: {}", e);
+                Config::default()
+            }
+        }
+    }
+
+    /// Save configuration to file, the previous file becomes the newest backup
+    pub fn save(&self) -> Result<(), io::Error> {
+        self.save_to(&Self::config_path(), layers())
+    }
+
+    fn save_to(&self, path: &Path, layers: &Layers) -> Result<(), io::Error> {
+        let mut document = serde_json::to_value(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        // Only the user's own settings go to the file
+        if !layers.is_empty() {
+            let mut saved = fs::read_to_string(path)
+                .ok()
+                .and_then(|contents| Format::of(path).unwrap_or(Format::Json).parse(&contents).ok())
+                .unwrap_or_else(|| Value::Object(Default::default()));
+            if migrate(&mut saved).is_err() {
+                saved = Value::Object(Default::default());
+            }
+            document = layers.user_part(document, &saved);
+        }
+
+        Self::write_document(path, document, self.backups)
+    }
+
+    /// Write `document` to the configuration file at `path`, with a reference
+    /// to the schema written next to it, so editors can complete it
+    fn write_document(path: &Path, mut document: Value, backups: usize) -> Result<(), io::Error> {
+        match schema::write_next_to(path) {
+            Ok(reference) => {
+                if let Value::Object(document) = &mut document {
+                    document.shift_insert(0, "$schema".to_string(), Value::String(reference));
+                }
+            }
+            Err(e) => log::warn!("Failed to write the configuration schema: {}", e),
+        }
+
+        let contents = Format::of(path)
+            .unwrap_or(Format::Json)
+            .write(&document)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        store::write(path, contents.as_bytes(), backups)
+    }
+
+    /// Previous versions of the configuration file, newest first
+    pub fn backups() -> Vec<Backup> {
+        store::backups(&Self::config_path())
+    }
+
+    /// Make a backup the configuration file again. The replaced
+    /// configuration becomes the newest backup, so this can be undone.
+    pub fn restore(backup: &Backup) -> Result<Self, String> {
+        let contents = fs::read_to_string(&backup.path).map_err(|e| e.to_string())?;
+        let config = Self::parse(&contents, Self::format()).map_err(|e| e.to_string())?;
+        config.save().map_err(|e| e.to_string())?;
+        Ok(config)
+    }
+}
+
+/// The configuration file in `dir`. If there are several, JSON is used
+/// before TOML before YAML.
+fn find_config_file(dir: &Path) -> PathBuf {
+    let mut existing = Format::ALL
+        .iter()
+        .flat_map(|format| match format {
+            Format::Yaml => vec!["config.yaml", "config.yml"],
+            format => vec![format.file_name()],
+        })
+        .map(|name| dir.join(name))
+        .filter(|path| path.exists());
+
+    let Some(path) = existing.next() else {
+        return dir.join(Format::Json.file_name());
+    };
+    for ignored in existing {
+        log::warn!("Using {}, {} is ignored", path.display(), ignored.display());
+    }
+    path
+}
+
+/// Remove the bindings with an unknown key or an invalid action
+fn retain_valid_bindings(hotkeys: &mut Value, path: &str, skipped: &mut Vec<String>) {
+    let Value::Object(bindings) = hotkeys else {
+        return;
+    };
+    bindings.retain(|key, action| {
+        let problem = match MediaAction::deserialize(&*action) {
+            _ if key_map_serde::parse_key(key).is_none() => format!("unknown key {}", key),
+            Ok(_) => return true,
+            Err(e) => e.to_string(),
+        };
+        skipped.push(format!("`{}.{}`: {}", path, key, problem));
+        false
+    });
+}
+
+/// Remove the items of the list `root[field]` that are not a valid `T`
+fn retain_valid<T: serde::de::DeserializeOwned>(
+    root: &mut serde_json::Map<String, Value>,
+    field: &str,
+    skipped: &mut Vec<String>,
+) {
+    let Some(Value::Array(items)) = root.get_mut(field) else {
+        return;
+    };
+    let mut index = 0;
+    items.retain(|item| {
+        let result = T::deserialize(item);
+        if let Err(e) = &result {
+            skipped.push(format!("`{}.{}`: {}", field, index, e));
+        }
+        index += 1;
+        result.is_ok()
+    });
+}
+
+/// Remove the value at `path` from `value`, returns false if there is none
+fn remove_entry(value: &mut Value, path: &[String]) -> bool {
+    let Some((last, parents)) = path.split_last() else {
+        return false;
+    };
+    let mut parent = value;
+    for segment in parents {
+        let child = match parent {
+            Value::Object(map) => map.get_mut(segment),
+            Value::Array(items) => segment.parse().ok().and_then(|index: usize| items.get_mut(index)),
+            _ => None,
+        };
+        match child {
+            Some(child) => parent = child,
+            None => return false,
+        }
+    }
+    match parent {
+        Value::Object(map) => map.remove(last).is_some(),
+        Value::Array(items) => match last.parse::<usize>() {
+            Ok(index) if index < items.len() => {
+                items.remove(index);
+                true
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+// Module for serialization of HashMap<Key, MediaAction>
+mod key_map_serde {
+    use super::*;
+    use serde::de::{Deserialize, Deserializer};
+    use serde::ser::Serializer;
+    use std::collections::HashMap;
+
+    pub fn serialize<S>(
+        map: &HashMap<Key, MediaAction>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        use serde::ser::SerializeMap;
+
+        // Written in keyboard order, so saving doesn't shuffle the file
+        let mut entries: Vec<_> = map.iter().collect();
+        entries.sort_by_key(|(key, _)| {
+            SUPPORTED_KEYS
+                .iter()
+                .position(|supported| supported == *key)
+                .unwrap_or(usize::MAX)
+        });
+
+        let mut s = serializer.serialize_map(Some(map.len()))?;
+        for (key, value) in entries {
+            let key_str = format!("{:?}", key);
+            s.serialize_entry(&key_str, value)?;
+        }
+        s.end()
+    }
+
+    pub fn deserialize<'de, D>(
+        deserializer: D,
+    ) -> Result<HashMap<Key, MediaAction>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let map: HashMap<String, MediaAction> = HashMap::deserialize(deserializer)?;
+        let mut result = HashMap::new();
+
+        for (key_str, action) in map {
+            let key = parse_key(&key_str).ok_or_else(|| {
+                serde::de::Error::custom(format!("Invalid key: {}", key_str))
+            })?;
+            result.insert(key, action);
+        }
+
+        Ok(result)
+    }
+
+    /// Only the supported key names are accepted
+    pub fn schema(generator: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        let keys: Vec<String> = SUPPORTED_KEYS.iter().map(|key| format!("{:?}", key)).collect();
+        let action = generator.subschema_for::<MediaAction>();
+        schemars::json_schema!({
+            "type": "object",
+            "propertyNames": { "enum": keys },
+            "additionalProperties": action,
+        })
+    }
+
+    pub fn parse_key(s: &str) -> Option<Key> {
+        // Simple function to parse string to Key
+        // Can be extended in a real project
+        match s {
+            "F1" => Some(Key::F1),
+            "F2" => Some(Key::F2),
+            "F3" => Some(Key::F3),
+            "F4" => Some(Key::F4),
+            "F5" => Some(Key::F5),
+            "F6" => Some(Key::F6),
+            "F7" => Some(Key::F7),
+            "F8" => Some(Key::F8),
+            "F9" => Some(Key::F9),
+            "F10" => Some(Key::F10),
+            "F11" => Some(Key::F11),
+            "F12" => Some(Key::F12),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BROKEN: &str = r#"{
+  "version": 1,
+  "profiles": {
+    "Default": {
+      "hotkeys": {
+        "F1": "PlayPause",
+        "F13": "Next",
+        "F2": { "Seek": "ten" },
+        "F3": "VolumeUp"
+      }
+    }
+  },
+  "schedule": [
+    { "when": { "Cron": "0 23 * * *" }, "action": "Stop" },
+    { "when": { "Daily": {} }, "action": "Stop" }
+  ],
+  "max_running_commands": "four"
+}"#;
+
+    #[test]
+    fn reports_position_and_entry() {
+        let error = Config::parse(BROKEN, Format::Json).unwrap_err();
+        assert_eq!(error.entry.as_deref(), Some("profiles.Default.hotkeys.F2.Seek"));
+        assert_eq!((error.line, error.column), (8, 29));
+        assert!(error.to_string().starts_with("line 8, column 29: invalid type"));
+    }
+
+    #[test]
+    fn lenient_load_skips_only_invalid_entries() {
+        let (config, skipped) = Config::parse_lenient(BROKEN, Format::Json).unwrap();
+
+        let hotkeys = &config.profiles["Default"].hotkeys;
+        assert_eq!(hotkeys.len(), 2);
+        assert_eq!(hotkeys[&Key::F1], MediaAction::PlayPause);
+        assert_eq!(hotkeys[&Key::F3], MediaAction::VolumeUp);
+        assert_eq!(config.schedule.len(), 1);
+        assert_eq!(config.max_running_commands, default_max_running_commands());
+
+        let entries: Vec<_> = skipped
+            .iter()
+            .map(|entry| entry.split(':').next().unwrap())
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                "`profiles.Default.hotkeys.F13`",
+                "`profiles.Default.hotkeys.F2`",
+                "`schedule.1`",
+                "`max_running_commands`",
+            ]
+        );
+    }
+
+    /// Every `<name>.json` in tests/golden/config must load and save as
+    /// `<name>.expected.json`. `UPDATE_GOLDEN=1 cargo test` rewrites the expected files.
+    #[test]
+    fn migrates_golden_files() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden/config");
+        let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+        let mut checked = 0;
+
+        for entry in fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            let Some(stem) = name.strip_suffix(".json") else {
+                continue;
+            };
+            if stem.ends_with(".expected") {
+                continue;
+            }
+
+            let config = Config::parse(&fs::read_to_string(&path).unwrap(), Format::Json)
+                .unwrap_or_else(|e| panic!("{}: {}", name, e));
+            assert_eq!(config.version, CONFIG_VERSION, "{}", name);
+
+            let expected_path = dir.join(format!("{}.expected.json", stem));
+            if update {
+                fs::write(&expected_path, config.to_text(Format::Json).unwrap() + "\n").unwrap();
+            }
+            let expected: Value =
+                serde_json::from_str(&fs::read_to_string(&expected_path).unwrap()).unwrap();
+            assert_eq!(serde_json::to_value(&config).unwrap(), expected, "{}", name);
+
+            // Migrated files load as they are
+            let again = Config::parse(&config.to_text(Format::Json).unwrap(), Format::Json).unwrap();
+            assert!(again.same_as(&config), "{}", name);
+            checked += 1;
+        }
+
+        assert!(checked >= 4);
+    }
+
+    #[test]
+    fn golden_files_survive_other_formats() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden/config");
+        for entry in fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if !path.to_string_lossy().ends_with(".expected.json") {
+                continue;
+            }
+            let contents = fs::read_to_string(&path).unwrap();
+            let config = Config::parse(&contents, Format::Json).unwrap();
+
+            for format in [Format::Toml, Format::Yaml] {
+                let text = config.to_text(format).unwrap();
+                let again = Config::parse(&text, format).unwrap_or_else(|e| {
+                    panic!("{} as {:?}: {}\n{}", path.display(), format, e, text)
+                });
+                assert!(again.same_as(&config), "{} as {:?}", path.display(), format);
+            }
+        }
+    }
+
+    #[test]
+    fn reports_positions_in_toml_and_yaml() {
+        let error = Config::parse("version = 1\nbackups = [\n", Format::Toml).unwrap_err();
+        assert_eq!(error.line, 3);
+
+        let error = Config::parse("version: 1\nprofiles: [\n", Format::Yaml).unwrap_err();
+        assert!(error.line >= 2, "{}", error);
+
+        // Not a syntax error, so the position is not known
+        let error =
+            Config::parse("version = 1\nbackups = \"five\"\n", Format::Toml).unwrap_err();
+        assert_eq!(error.entry.as_deref(), Some("backups"));
+    }
+
+    #[test]
+    fn json_takes_precedence_over_other_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(find_config_file(dir.path()), dir.path().join("config.json"));
+
+        fs::write(dir.path().join("config.yml"), "").unwrap();
+        assert_eq!(find_config_file(dir.path()), dir.path().join("config.yml"));
+        fs::write(dir.path().join("config.toml"), "").unwrap();
+        assert_eq!(find_config_file(dir.path()), dir.path().join("config.toml"));
+        fs::write(dir.path().join("config.json"), "").unwrap();
+        assert_eq!(find_config_file(dir.path()), dir.path().join("config.json"));
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let newer = format!("{{ \"version\": {} }}", CONFIG_VERSION + 1);
+        let error = Config::parse(&newer, Format::Json).unwrap_err();
+        assert_eq!(error.entry.as_deref(), Some("version"));
+        assert!(error.to_string().contains("newer release"));
+        assert!(Config::parse_lenient(&newer, Format::Json).is_err());
+    }
+
+    #[test]
+    fn lenient_load_needs_a_json_object() {
+        let error = Config::parse_lenient("{ \"profiles\": {", Format::Json).unwrap_err();
+        assert_eq!(error.line, 1);
+        assert!(Config::parse_lenient("42", Format::Json).is_err());
+    }
+
+    #[test]
+    fn next_profile_wraps_around() {
+        let mut config = Config::default();
+        for name in ["Gaming", "Work"] {
+            config.profiles.insert(name.to_string(), Profile::default());
+        }
+
+        assert_eq!(config.next_profile("Default").unwrap(), "Gaming");
+        assert_eq!(config.next_profile("Gaming").unwrap(), "Work");
+        assert_eq!(config.next_profile("Work").unwrap(), "Default");
+        // A removed profile continues with the first one
+        assert_eq!(config.next_profile("Removed").unwrap(), "Default");
+
+        config.profiles.clear();
+        assert_eq!(config.next_profile("Default"), None);
+    }
+
+    #[test]
+    fn first_run_uses_the_system_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let system = dir.path().join("config.json");
+        fs::write(
+            &system,
+            r#"{ "version": 1, "backups": 10, "profiles": { "Work": { "hotkeys": { "F5": "Next" } } } }"#,
+        )
+        .unwrap();
+        let mut layers = Layers::new(None, vec!["script_timeout=3".parse().unwrap()]);
+        layers.read_system_file(&system).unwrap();
+
+        let (config, document) = Config::first_run(&layers).unwrap();
+        assert_eq!(config.backups, 10);
+        assert_eq!(config.script_timeout, 3);
+        assert_eq!(config.active_profile, "Work");
+        assert_eq!(config.profiles["Work"].hotkeys[&Key::F5], MediaAction::Next);
+
+        // Nothing is copied to the user's file
+        assert_eq!(document, serde_json::json!({ "version": CONFIG_VERSION }));
+    }
+
+    #[test]
+    fn saved_files_refer_to_the_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        Config::default().save_to(&path, &Layers::default()).unwrap();
+
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["$schema"], "./config.schema.json");
+        let schema = fs::read_to_string(dir.path().join("config.schema.json")).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&schema).unwrap(), schema::generate());
+        // The file is still read, the entry is not a setting
+        assert!(Config::parse(&fs::read_to_string(&path).unwrap(), Format::Json).is_ok());
+    }
+
+    #[test]
+    fn action_settings_keep_only_what_actions_use() {
+        let mut config = Config::default();
+        config.active_mut().hotkeys.insert(Key::F1, MediaAction::Next);
+        config.active_mut().backend = Backend::Mpv(Default::default());
+        config.profiles.insert("Work".to_string(), Profile::default());
+        config.script_timeout = 3;
+
+        let settings = config.action_settings();
+        assert_eq!(settings.active_profile, config.active_profile);
+        assert_eq!(settings.active().backend, config.active().backend);
+        assert!(settings.active().hotkeys.is_empty());
+        assert_eq!(settings.profiles.len(), 1);
+        assert_eq!(settings.script_timeout, 3);
+    }
+}
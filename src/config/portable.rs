@@ -0,0 +1,87 @@
+//! Portable mode: the configuration, the log and the state are kept next to
+//! the executable instead of the user's config directory, e.g. on a USB stick.
+//! It is on when `portable.flag` or a configuration file sits next to the
+//! executable, or with `--portable`.
+
+use super::{find_config_file, store};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Empty file that turns portable mode on
+pub const FLAG_FILE: &str = "portable.flag";
+
+/// Log written in portable mode, the one of the previous run is kept as `.old`
+const LOG_FILE: &str = "music_hotkey_mapper.log";
+
+/// Set once at startup, detected on first use otherwise
+static DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+fn exe_dir() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    exe.parent().map(Path::to_path_buf)
+}
+
+/// Whether the files in `dir` ask for portable mode
+fn is_marked(dir: &Path) -> bool {
+    dir.join(FLAG_FILE).exists() || find_config_file(dir).exists()
+}
+
+/// `dir` if it is used in portable mode
+fn detect(dir: Option<PathBuf>, forced: bool) -> Option<PathBuf> {
+    dir.filter(|dir| forced || is_marked(dir))
+}
+
+/// Decide whether to run portable, `forced` by `--portable`
+pub(super) fn init(forced: bool) {
+    if DIR.set(detect(exe_dir(), forced)).is_err() {
+        log::warn!("Portable mode is already decided");
+    }
+}
+
+/// Directory of the executable in portable mode
+pub(super) fn dir() -> Option<&'static Path> {
+    DIR.get_or_init(|| detect(exe_dir(), false)).as_deref()
+}
+
+/// Start a new log in `dir`
+pub(super) fn open_log(dir: &Path) -> io::Result<File> {
+    let path = dir.join(LOG_FILE);
+    if path.exists() {
+        fs::rename(&path, store::with_suffix(&path, ".old"))?;
+    }
+    File::create(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flag_or_config_file_turns_portable_mode_on() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_path_buf();
+        assert_eq!(detect(Some(path.clone()), false), None);
+        assert_eq!(detect(Some(path.clone()), true), Some(path.clone()));
+
+        fs::write(path.join("config.toml"), "version = 1\n").unwrap();
+        assert_eq!(detect(Some(path.clone()), false), Some(path.clone()));
+
+        fs::remove_file(path.join("config.toml")).unwrap();
+        fs::write(path.join(FLAG_FILE), "").unwrap();
+        assert_eq!(detect(Some(path.clone()), false), Some(path));
+        assert_eq!(detect(None, true), None);
+    }
+
+    #[test]
+    fn keeps_the_previous_log() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(LOG_FILE), "first run").unwrap();
+        drop(open_log(dir.path()).unwrap());
+
+        let old = dir.path().join(format!("{}.old", LOG_FILE));
+        assert_eq!(fs::read_to_string(old).unwrap(), "first run");
+        assert_eq!(fs::read_to_string(dir.path().join(LOG_FILE)).unwrap(), "");
+    }
+}
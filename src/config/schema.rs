@@ -0,0 +1,83 @@
+//! JSON Schema of the configuration file, for completion and validation in
+//! editors. It is generated from the serde types, so it can't get out of date.
+
+use super::Config;
+use serde_json::{json, Value};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Name of the schema file written next to the configuration file
+const FILE_NAME: &str = "config.schema.json";
+
+/// The schema of the configuration file
+pub fn generate() -> Value {
+    let mut schema = schemars::schema_for!(Config).to_value();
+
+    // Entries that are read, but are not settings
+    if let Some(Value::Object(properties)) = schema.get_mut("properties") {
+        properties.shift_insert(
+            0,
+            "$schema".to_string(),
+            json!({
+                "description": "Schema of this file, written next to it by the app",
+                "type": "string"
+            }),
+        );
+        properties.insert(
+            "locked".to_string(),
+            json!({
+                "description": "Only in the system file: entries users can't change, e.g. \"profiles.Default.hotkeys.F12\"",
+                "type": "array",
+                "items": { "type": "string" }
+            }),
+        );
+    }
+    schema
+}
+
+/// Write the schema next to the configuration file at `config` if it has
+/// changed, returns the reference to it for the `$schema` entry
+pub(super) fn write_next_to(config: &Path) -> io::Result<String> {
+    let path = config.with_file_name(FILE_NAME);
+    let contents = serde_json::to_string_pretty(&generate())? + "\n";
+    if fs::read_to_string(&path).ok().as_deref() != Some(contents.as_str()) {
+        fs::write(&path, contents)?;
+    }
+    Ok(format!("./{}", FILE_NAME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// The schema in the repository is what editors of the example
+    /// configuration use. `UPDATE_GOLDEN=1 cargo test` rewrites it.
+    #[test]
+    fn schema_file_is_up_to_date() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(FILE_NAME);
+        let generated = serde_json::to_string_pretty(&generate()).unwrap() + "\n";
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::write(&path, &generated).unwrap();
+        }
+        let committed = fs::read_to_string(&path).unwrap_or_default();
+        assert!(
+            committed == generated,
+            "{} is out of date, run `UPDATE_GOLDEN=1 cargo test`",
+            FILE_NAME
+        );
+    }
+
+    #[test]
+    fn describes_keys_and_actions() {
+        let schema = generate();
+        let hotkeys = &schema["$defs"]["Profile"]["properties"]["hotkeys"];
+        assert_eq!(hotkeys["propertyNames"]["enum"][0], "F1");
+        assert_eq!(
+            hotkeys["additionalProperties"]["$ref"],
+            "#/$defs/MediaAction"
+        );
+        assert!(schema["properties"]["$schema"].is_object());
+    }
+}
@@ -0,0 +1,241 @@
+//! Writing the configuration file so that it is never left half written,
+//! keeping the previous versions as `config.json.1` (newest) ... `config.json.N`.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// A previous version of the configuration file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backup {
+    /// 1 is the newest
+    pub number: usize,
+    pub path: PathBuf,
+    pub modified: Option<SystemTime>,
+}
+
+/// `path` with `suffix` appended to the file name
+pub(super) fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+fn backup_path(path: &Path, number: usize) -> PathBuf {
+    with_suffix(path, &format!(".{}", number))
+}
+
+/// Held while the file is written. The app and command line tools
+/// lock the same file, so their saves don't interleave. The lock file
+/// is removed again when the lock is released.
+struct Lock {
+    file: File,
+    path: PathBuf,
+}
+
+impl Lock {
+    fn open(path: &Path) -> io::Result<File> {
+        let mut options = OpenOptions::new();
+        options.create(true).truncate(false).write(true);
+        // Deleted once nobody has it open, waiting saves included
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::OpenOptionsExt;
+            const FILE_FLAG_DELETE_ON_CLOSE: u32 = 0x0400_0000;
+            options.custom_flags(FILE_FLAG_DELETE_ON_CLOSE);
+        }
+        options.open(path)
+    }
+
+    /// Whether `file` is still the one at `path`, i.e. it was not
+    /// removed by the save that held the lock before
+    #[cfg(unix)]
+    fn is_current(file: &File, path: &Path) -> io::Result<bool> {
+        use std::os::unix::fs::MetadataExt;
+        let locked = file.metadata()?;
+        Ok(match fs::metadata(path) {
+            Ok(current) => (current.dev(), current.ino()) == (locked.dev(), locked.ino()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+            Err(e) => return Err(e),
+        })
+    }
+
+    /// Windows removes the file itself, after the last save
+    #[cfg(not(unix))]
+    fn is_current(_file: &File, _path: &Path) -> io::Result<bool> {
+        Ok(true)
+    }
+
+    fn acquire(config: &Path) -> io::Result<Self> {
+        let path = with_suffix(config, ".lock");
+        loop {
+            let file = Lock::open(&path)?;
+            file.lock()?;
+            if Lock::is_current(&file, &path)? {
+                return Ok(Lock { file, path });
+            }
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        // Removed while still locked, so the next save opens a new file
+        #[cfg(unix)]
+        let _ = fs::remove_file(&self.path);
+        let _ = self.file.unlock();
+    }
+}
+
+/// Replace the contents of `path`, keeping up to `backups` previous versions.
+/// The new contents are written to a temporary file that replaces the old
+/// file in one step, so a crash leaves either the old or the new file.
+pub fn write(path: &Path, contents: &[u8], backups: usize) -> io::Result<()> {
+    let _lock = Lock::acquire(path)?;
+
+    // Saving the same again would only push out an older backup
+    if fs::read(path).is_ok_and(|current| current == contents) {
+        return Ok(());
+    }
+
+    let temp = with_suffix(path, ".tmp");
+    let mut file = File::create(&temp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+
+    if backups > 0 && path.exists() {
+        for number in (1..backups).rev() {
+            let older = backup_path(path, number);
+            if older.exists() {
+                fs::rename(&older, backup_path(path, number + 1))?;
+            }
+        }
+        fs::copy(path, backup_path(path, 1))?;
+    }
+
+    // Left over from a time more backups were kept
+    let mut number = backups + 1;
+    while fs::remove_file(backup_path(path, number)).is_ok() {
+        number += 1;
+    }
+
+    fs::rename(&temp, path)?;
+
+    // Make the rename itself durable
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        File::open(dir)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+/// Backups of `path`, newest first
+pub fn backups(path: &Path) -> Vec<Backup> {
+    (1..)
+        .map(|number| (number, backup_path(path, number)))
+        .take_while(|(_, path)| path.exists())
+        .map(|(number, path)| Backup {
+            number,
+            modified: fs::metadata(&path).and_then(|m| m.modified()).ok(),
+            path,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn keeps_rolling_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+
+        for version in 1..=5 {
+            write(&path, format!("v{}", version).as_bytes(), 3).unwrap();
+        }
+
+        assert_eq!(read(&path), "v5");
+        let backups = backups(&path);
+        let contents: Vec<_> = backups.iter().map(|backup| read(&backup.path)).collect();
+        assert_eq!(contents, ["v4", "v3", "v2"]);
+        assert_eq!(backups[0].number, 1);
+        assert!(!dir.path().join("config.json.tmp").exists());
+        assert!(!dir.path().join("config.json.lock").exists());
+    }
+
+    #[test]
+    fn unchanged_contents_keep_the_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+
+        write(&path, b"v1", 3).unwrap();
+        write(&path, b"v2", 3).unwrap();
+        write(&path, b"v2", 3).unwrap();
+
+        let contents: Vec<_> = backups(&path)
+            .iter()
+            .map(|backup| read(&backup.path))
+            .collect();
+        assert_eq!(contents, ["v1"]);
+    }
+
+    #[test]
+    fn turning_backups_off_removes_them() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+
+        write(&path, b"v1", 2).unwrap();
+        write(&path, b"v2", 2).unwrap();
+        write(&path, b"v3", 0).unwrap();
+
+        assert_eq!(read(&path), "v3");
+        assert!(backups(&path).is_empty());
+    }
+
+    #[test]
+    fn concurrent_saves_dont_interleave() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Arc::new(dir.path().join("config.json"));
+
+        let writers: Vec<_> = (0..8)
+            .map(|writer| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    for round in 0..20 {
+                        let contents = format!("{}-{}", writer, round).repeat(1000);
+                        write(&path, contents.as_bytes(), 4).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        assert!(!dir.path().join("config.json.lock").exists());
+
+        // Every file holds exactly one save
+        let mut files = vec![path.to_path_buf()];
+        files.extend(backups(&path).into_iter().map(|backup| backup.path));
+        assert_eq!(files.len(), 5);
+        for file in files {
+            let contents = read(&file);
+            let save = &contents[..contents.len() / 1000];
+            assert_eq!(
+                contents,
+                save.repeat(1000),
+                "{} holds mixed saves",
+                file.display()
+            );
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Applies changes made to the configuration file outside the app,
+//! e.g. in a text editor or by a dotfile manager.
+
+use crate::config::{Config, Format};
+use crate::hotkey_listener::ListenerControl;
+use crate::ui::AppMessage;
+use crossbeam_channel::Sender;
+use log::{error, info, warn};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+/// Editors write a file in several steps (truncate and write, or write a
+/// temporary file and rename it), the file is read once they are quiet
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Watches the configuration file and applies it when it changes
+pub struct ConfigWatcher {
+    path: PathBuf,
+    config: Arc<Mutex<Config>>,
+    listener: ListenerControl,
+    app_sender: Sender<AppMessage>,
+}
+
+impl ConfigWatcher {
+    pub fn new(
+        path: PathBuf,
+        config: Arc<Mutex<Config>>,
+        listener: ListenerControl,
+        app_sender: Sender<AppMessage>,
+    ) -> Self {
+        ConfigWatcher {
+            path,
+            config,
+            listener,
+            app_sender,
+        }
+    }
+
+    /// Watch the file (blocking call)
+    pub fn start(self) {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = match notify::recommended_watcher(sender) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                error!("Failed to watch the configuration file: {}", e);
+                return;
+            }
+        };
+
+        // The directory is watched, a file replaced by a rename is a new file
+        let Some(dir) = self.path.parent() else {
+            return;
+        };
+        if let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+            error!("Failed to watch {}: {}", dir.display(), e);
+            return;
+        }
+        info!("Watching {} for changes", self.path.display());
+
+        loop {
+            // Wait for a change of the file, then for the writes to settle
+            loop {
+                match events.recv() {
+                    Ok(Ok(event)) if self.concerns(&event) => break,
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => warn!("Configuration watcher error: {}", e),
+                    Err(_) => return,
+                }
+            }
+            while events.recv_timeout(DEBOUNCE).is_ok() {}
+
+            self.reload();
+        }
+    }
+
+    /// Whether `event` changed the configuration file
+    fn concerns(&self, event: &notify::Event) -> bool {
+        !matches!(event.kind, EventKind::Access(_))
+            && event
+                .paths
+                .iter()
+                .any(|path| path.file_name() == self.path.file_name())
+    }
+
+    /// Read the file and apply it if it is valid and differs from the current configuration
+    fn reload(&self) {
+        let contents = match std::fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                // Deleted, or still being replaced
+                info!("Configuration file not readable: {}", e);
+                return;
+            }
+        };
+
+        let format = Format::of(&self.path).unwrap_or(Format::Json);
+        let mut new_config = match Config::parse(&contents, format) {
+            Ok(config) => config,
+            Err(e) => {
+                warn!("Ignoring invalid configuration file: {}", e);
+                let _ = self.app_sender.send(AppMessage::ConfigError(format!(
+                    "{} is invalid, still using the previous configuration: {}",
+                    self.path.display(),
+                    e
+                )));
+                return;
+            }
+        };
+
+        let mut config = self.config.lock().unwrap();
+        // A profile switched to by a hotkey or a window rule stays active
+        new_config.keep_active_profile(&config.active_profile);
+        // Our own saves come back here too
+        if config.same_as(&new_config) {
+            return;
+        }
+        info!("Configuration file changed, applying it");
+        *config = new_config.clone();
+        drop(config);
+
+        self.listener.rebind();
+        let _ = self.app_sender.send(AppMessage::ConfigUpdated(new_config));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{MediaAction, Profile};
+    use crossbeam_channel::{unbounded, Receiver};
+    use rdev::Key;
+
+    fn start(
+        dir: &tempfile::TempDir,
+        config: &Config,
+    ) -> (Arc<Mutex<Config>>, Receiver<AppMessage>) {
+        let shared = Arc::new(Mutex::new(config.clone()));
+        let (sender, receiver) = unbounded();
+        let watcher = ConfigWatcher::new(
+            dir.path().join("config.json"),
+            shared.clone(),
+            ListenerControl::default(),
+            sender,
+        );
+        std::thread::spawn(move || watcher.start());
+        // Let the watch be set up before the file changes
+        std::thread::sleep(Duration::from_millis(200));
+        (shared, receiver)
+    }
+
+    fn next_message(receiver: &Receiver<AppMessage>) -> AppMessage {
+        receiver
+            .recv_timeout(Duration::from_secs(5))
+            .expect("no message from the watcher")
+    }
+
+    #[test]
+    fn applies_file_replaced_by_rename() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::default();
+        std::fs::write(dir.path().join("config.json"), config.to_text(Format::Json).unwrap()).unwrap();
+        let (shared, receiver) = start(&dir, &config);
+
+        // What editors like vim do: write a new file, rename it over the old one
+        let mut changed = config.clone();
+        changed
+            .active_mut()
+            .hotkeys
+            .insert(Key::F1, MediaAction::Next);
+        let temp = dir.path().join("config.json.tmp");
+        std::fs::write(&temp, changed.to_text(Format::Json).unwrap()).unwrap();
+        std::fs::rename(&temp, dir.path().join("config.json")).unwrap();
+
+        match next_message(&receiver) {
+            AppMessage::ConfigUpdated(updated) => assert!(updated.same_as(&changed)),
+            message => panic!("unexpected message {:?}", message),
+        }
+        assert!(shared.lock().unwrap().same_as(&changed));
+        // One message for all the steps of the save
+        assert!(receiver.recv_timeout(Duration::from_millis(500)).is_err());
+    }
+
+    #[test]
+    fn keeps_the_profile_switched_to() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config::default();
+        config.profiles.insert("Work".to_string(), Profile::default());
+        std::fs::write(dir.path().join("config.json"), config.to_text(Format::Json).unwrap()).unwrap();
+        let (shared, receiver) = start(&dir, &config);
+        shared.lock().unwrap().active_profile = "Work".to_string();
+
+        // Touched: nothing changes, the switch isn't undone
+        std::fs::write(dir.path().join("config.json"), config.to_text(Format::Json).unwrap()).unwrap();
+        assert!(receiver.recv_timeout(Duration::from_secs(1)).is_err());
+        assert_eq!(shared.lock().unwrap().active_profile, "Work");
+
+        // Edited: applied in the profile that is active now
+        config.backups = 1;
+        std::fs::write(dir.path().join("config.json"), config.to_text(Format::Json).unwrap()).unwrap();
+        match next_message(&receiver) {
+            AppMessage::ConfigUpdated(updated) => assert_eq!(updated.active_profile, "Work"),
+            message => panic!("unexpected message {:?}", message),
+        }
+        assert_eq!(shared.lock().unwrap().backups, 1);
+        assert_eq!(shared.lock().unwrap().active_profile, "Work");
+
+        // Unless the profile is gone
+        config.profiles.remove("Work");
+        std::fs::write(dir.path().join("config.json"), config.to_text(Format::Json).unwrap()).unwrap();
+        next_message(&receiver);
+        assert_eq!(shared.lock().unwrap().active_profile, "Default");
+    }
+
+    #[test]
+    fn keeps_previous_config_if_invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::default();
+        std::fs::write(dir.path().join("config.json"), config.to_text(Format::Json).unwrap()).unwrap();
+        let (shared, receiver) = start(&dir, &config);
+
+        std::fs::write(dir.path().join("config.json"), "{ \"profiles\": ").unwrap();
+
+        assert!(matches!(
+            next_message(&receiver),
+            AppMessage::ConfigError(_)
+        ));
+        assert!(shared.lock().unwrap().same_as(&config));
+    }
+
+    #[test]
+    fn ignores_unchanged_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::default();
+        std::fs::write(dir.path().join("config.json"), config.to_text(Format::Json).unwrap()).unwrap();
+        let (_shared, receiver) = start(&dir, &config);
+
+        // Saved by the app itself, or touched
+        std::fs::write(dir.path().join("config.json"), config.to_text(Format::Json).unwrap()).unwrap();
+        std::fs::write(dir.path().join("other.json"), "{}").unwrap();
+
+        assert!(receiver.recv_timeout(Duration::from_secs(1)).is_err());
+    }
+}
@@ -0,0 +1,365 @@
+//! Control socket: lets the command line talk to the running instance.
+//!
+//! Each connection sends one JSON request per line and gets one JSON
+//! response line back. On Unix this is a socket in the runtime directory,
+//! on Windows a TCP port on localhost written to `control.port` in the
+//! configuration directory.
+
+use crate::actions::{ActionRunner, MAX_MINUTES};
+use crate::config::Config;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+
+/// Requests understood by the running instance
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum Request {
+    SleepTimerStatus,
+    SleepTimerAdd { minutes: u64 },
+    SleepTimerCancel,
+    /// Whether the hotkeys are paused
+    EnabledStatus,
+    SetEnabled { enabled: bool },
+    ToggleEnabled,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Response {
+    Ok(serde_json::Value),
+    Error(String),
+}
+
+/// State the requests are handled with
+pub struct Handler {
+    pub action_runner: ActionRunner,
+}
+
+impl Handler {
+    fn handle(&self, request: Request) -> Response {
+        let sleep_timer = self.action_runner.sleep_timer();
+
+        match request {
+            Request::SleepTimerStatus => Response::Ok(remaining_json(sleep_timer.remaining())),
+            Request::SleepTimerAdd { minutes } if minutes > MAX_MINUTES => Response::Error(format!(
+                "At most {} minutes can be added at once",
+                MAX_MINUTES
+            )),
+            Request::SleepTimerAdd { minutes } => {
+                sleep_timer.extend(minutes);
+                Response::Ok(remaining_json(sleep_timer.remaining()))
+            }
+            Request::SleepTimerCancel => {
+                if sleep_timer.cancel() {
+                    Response::Ok(serde_json::Value::Null)
+                } else {
+                    Response::Error("Sleep timer is not running".to_string())
+                }
+            }
+            Request::EnabledStatus => Response::Ok(self.enabled_json()),
+            Request::SetEnabled { enabled } => {
+                self.action_runner.set_enabled(enabled);
+                Response::Ok(self.enabled_json())
+            }
+            Request::ToggleEnabled => {
+                self.action_runner.set_enabled(!self.action_runner.is_enabled());
+                Response::Ok(self.enabled_json())
+            }
+        }
+    }
+
+    fn enabled_json(&self) -> serde_json::Value {
+        serde_json::json!({ "enabled": self.action_runner.is_enabled() })
+    }
+}
+
+/// Seconds left on the sleep timer, `null` if it isn't running
+fn remaining_json(remaining: Option<std::time::Duration>) -> serde_json::Value {
+    serde_json::json!({ "remaining": remaining.map(|remaining| remaining.as_secs()) })
+}
+
+/// Answer the requests of one client
+fn handle_client(
+    reader: impl io::Read,
+    mut writer: impl Write,
+    handler: &Handler,
+) -> io::Result<()> {
+    for line in BufReader::new(reader).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                info!("Control request: {:?}", request);
+                handler.handle(request)
+            }
+            Err(e) => Response::Error(format!("Invalid request: {}", e)),
+        };
+
+        let mut json = serde_json::to_string(&response)?;
+        json.push('\n');
+        writer.write_all(json.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Send a request to the running instance
+pub fn send(request: &Request) -> io::Result<Response> {
+    let stream = connect()?;
+    let mut writer = stream.try_clone()?;
+
+    let mut json = serde_json::to_string(request)?;
+    json.push('\n');
+    writer.write_all(json.as_bytes())?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    serde_json::from_str(&line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(unix)]
+fn socket_path() -> PathBuf {
+    match dirs::runtime_dir() {
+        Some(dir) => dir.join("music_hotkey_mapper.sock"),
+        None => Config::config_dir().join("control.sock"),
+    }
+}
+
+#[cfg(unix)]
+fn connect() -> io::Result<std::os::unix::net::UnixStream> {
+    std::os::unix::net::UnixStream::connect(socket_path())
+}
+
+/// Start accepting control connections in the background
+#[cfg(unix)]
+pub fn start(handler: Handler) {
+    use std::os::unix::net::UnixListener;
+
+    let path = socket_path();
+
+    // A socket that nobody answers on is left over from a crash
+    if path.exists() {
+        if connect().is_ok() {
+            warn!("Another instance is running, control socket disabled");
+            return;
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Failed to create control socket {}: {}", path.display(), e);
+            return;
+        }
+    };
+    info!("Control socket listening on {}", path.display());
+
+    serve(listener, handler);
+}
+
+#[cfg(windows)]
+fn port_path() -> PathBuf {
+    Config::config_dir().join("control.port")
+}
+
+#[cfg(windows)]
+fn connect() -> io::Result<std::net::TcpStream> {
+    let port: u16 = std::fs::read_to_string(port_path())?
+        .trim()
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    std::net::TcpStream::connect(("127.0.0.1", port))
+}
+
+/// Start accepting control connections in the background
+#[cfg(windows)]
+pub fn start(handler: Handler) {
+    use std::net::TcpListener;
+
+    if connect().is_ok() {
+        warn!("Another instance is running, control socket disabled");
+        return;
+    }
+
+    let listener = match TcpListener::bind(("127.0.0.1", 0)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Failed to create control socket: {}", e);
+            return;
+        }
+    };
+    let port = match listener.local_addr() {
+        Ok(address) => address.port(),
+        Err(e) => {
+            error!("Failed to create control socket: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = std::fs::write(port_path(), port.to_string()) {
+        error!("Failed to write {}: {}", port_path().display(), e);
+        return;
+    }
+    info!("Control socket listening on 127.0.0.1:{}", port);
+
+    serve(listener, handler);
+}
+
+/// Spawn a thread answering each client
+fn serve(listener: impl ControlListener, handler: Handler) {
+    let handler = Arc::new(handler);
+    thread::spawn(move || loop {
+        let stream = match listener.accept_client() {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Control connection failed: {}", e);
+                continue;
+            }
+        };
+
+        let handler = handler.clone();
+        thread::spawn(move || {
+            let result = stream
+                .try_clone_stream()
+                .and_then(|writer| handle_client(stream, writer, &handler));
+            if let Err(e) = result {
+                warn!("Control connection closed: {}", e);
+            }
+        });
+    });
+}
+
+/// A listening socket of the platform
+trait ControlListener: Send + 'static {
+    type Stream: ClientStream;
+
+    fn accept_client(&self) -> io::Result<Self::Stream>;
+}
+
+/// A connection that can be split into a reading and a writing half
+trait ClientStream: io::Read + Write + Send + Sized + 'static {
+    fn try_clone_stream(&self) -> io::Result<Self>;
+}
+
+#[cfg(unix)]
+impl ControlListener for std::os::unix::net::UnixListener {
+    type Stream = std::os::unix::net::UnixStream;
+
+    fn accept_client(&self) -> io::Result<Self::Stream> {
+        self.accept().map(|(stream, _)| stream)
+    }
+}
+
+#[cfg(unix)]
+impl ClientStream for std::os::unix::net::UnixStream {
+    fn try_clone_stream(&self) -> io::Result<Self> {
+        self.try_clone()
+    }
+}
+
+#[cfg(windows)]
+impl ControlListener for std::net::TcpListener {
+    type Stream = std::net::TcpStream;
+
+    fn accept_client(&self) -> io::Result<Self::Stream> {
+        self.accept().map(|(stream, _)| stream)
+    }
+}
+
+#[cfg(windows)]
+impl ClientStream for std::net::TcpStream {
+    fn try_clone_stream(&self) -> io::Result<Self> {
+        self.try_clone()
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::hotkey_listener::ListenerControl;
+    use crate::media_control::MediaController;
+    use std::os::unix::net::UnixStream;
+    use std::sync::Mutex;
+
+    fn handler() -> Handler {
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        Handler {
+            action_runner: ActionRunner::new(
+                MediaController::new(),
+                Arc::new(Mutex::new(Config::default())),
+                ListenerControl::default(),
+                sender,
+            ),
+        }
+    }
+
+    /// Send each request line on one connection, returns the response lines
+    fn exchange(handler: Handler, requests: &[&str]) -> Vec<String> {
+        let (client, server) = UnixStream::pair().unwrap();
+        let answering = thread::spawn(move || {
+            let writer = server.try_clone().unwrap();
+            handle_client(server, writer, &handler).unwrap();
+        });
+
+        let mut writer = client.try_clone().unwrap();
+        let mut reader = BufReader::new(client);
+        let responses = requests
+            .iter()
+            .map(|request| {
+                writeln!(writer, "{}", request).unwrap();
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                line.trim_end().to_string()
+            })
+            .collect();
+
+        writer.shutdown(std::net::Shutdown::Both).unwrap();
+        answering.join().unwrap();
+        responses
+    }
+
+    #[test]
+    fn pauses_and_resumes_the_hotkeys() {
+        let responses = exchange(
+            handler(),
+            &[
+                r#"{"method":"enabled_status"}"#,
+                r#"{"method":"toggle_enabled"}"#,
+                r#"{"method":"enabled_status"}"#,
+                r#"{"method":"set_enabled","enabled":false}"#,
+                r#"{"method":"set_enabled","enabled":true}"#,
+            ],
+        );
+        assert_eq!(
+            responses,
+            [
+                r#"{"Ok":{"enabled":true}}"#,
+                r#"{"Ok":{"enabled":false}}"#,
+                r#"{"Ok":{"enabled":false}}"#,
+                r#"{"Ok":{"enabled":false}}"#,
+                r#"{"Ok":{"enabled":true}}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_invalid_requests() {
+        let responses = exchange(
+            handler(),
+            &[
+                r#"{"method":"reboot"}"#,
+                r#"{"method":"sleep_timer_add","minutes":100000}"#,
+            ],
+        );
+        assert!(responses[0].starts_with(r#"{"Error":"Invalid request"#));
+        assert_eq!(
+            responses[1],
+            r#"{"Error":"At most 1440 minutes can be added at once"}"#
+        );
+    }
+}
@@ -0,0 +1,259 @@
+//! Shortcuts of the desktop and other applications that use the same keys.
+//! rdev only observes the keys, so they still reach the other shortcut.
+
+use crate::config::parse_key;
+use log::warn;
+use rdev::Key;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Command;
+use x11rb::connection::Connection;
+use x11rb::errors::ReplyError;
+use x11rb::protocol::xproto::{ConnectionExt, GrabMode, ModMask};
+use x11rb::protocol::ErrorKind;
+
+/// GNOME schemas with global shortcuts
+const GNOME_SCHEMAS: [&str; 4] = [
+    "org.gnome.settings-daemon.plugins.media-keys",
+    "org.gnome.desktop.wm.keybindings",
+    "org.gnome.shell.keybindings",
+    "org.gnome.mutter.keybindings",
+];
+
+const GNOME_CUSTOM_SCHEMA: &str = "org.gnome.settings-daemon.plugins.media-keys.custom-keybinding";
+
+/// What else uses each of `keys`
+pub fn find(keys: &[Key]) -> HashMap<Key, String> {
+    let mut found = HashMap::new();
+
+    match grabbed_keys(keys) {
+        Ok(grabbed) => {
+            for key in grabbed {
+                found.insert(key, "a key grab of another application".to_string());
+            }
+        }
+        Err(e) => warn!("Failed to check the X11 key grabs: {}", e),
+    }
+
+    // The desktop's own description is more helpful than the grab
+    let shortcuts = gnome_shortcuts().into_iter().chain(kde_shortcuts());
+    for (key, description) in shortcuts {
+        if keys.contains(&key) {
+            found.insert(key, description);
+        }
+    }
+    found
+}
+
+/// X11 keysym of `key`
+fn keysym(key: &Key) -> Option<u32> {
+    match key {
+        Key::F1 => Some(0xFFBE), // XK_F1
+        Key::F2 => Some(0xFFBF),
+        Key::F3 => Some(0xFFC0),
+        Key::F4 => Some(0xFFC1),
+        Key::F5 => Some(0xFFC2),
+        Key::F6 => Some(0xFFC3),
+        Key::F7 => Some(0xFFC4),
+        Key::F8 => Some(0xFFC5),
+        Key::F9 => Some(0xFFC6),
+        Key::F10 => Some(0xFFC7),
+        Key::F11 => Some(0xFFC8),
+        Key::F12 => Some(0xFFC9),
+        _ => None,
+    }
+}
+
+/// Keys another client has grabbed without modifiers: grabbing them
+/// fails with `BadAccess`. A successful grab is released right away.
+fn grabbed_keys(keys: &[Key]) -> Result<Vec<Key>, String> {
+    let (connection, screen) =
+        x11rb::connect(None).map_err(|e| format!("Failed to connect to X11: {}", e))?;
+    let root = connection.setup().roots[screen].root;
+    let min_keycode = connection.setup().min_keycode;
+    let max_keycode = connection.setup().max_keycode;
+
+    let mapping = connection
+        .get_keyboard_mapping(min_keycode, max_keycode - min_keycode + 1)
+        .map_err(|e| e.to_string())?
+        .reply()
+        .map_err(|e| e.to_string())?;
+    let per_keycode = usize::from(mapping.keysyms_per_keycode.max(1));
+
+    let mut grabbed = Vec::new();
+    for key in keys {
+        let Some(keysym) = keysym(key) else {
+            continue;
+        };
+        let Some(index) = mapping
+            .keysyms
+            .chunks(per_keycode)
+            .position(|keysyms| keysyms.contains(&keysym))
+        else {
+            continue;
+        };
+        let keycode = min_keycode + index as u8;
+
+        let result = connection
+            .grab_key(
+                false,
+                root,
+                ModMask::from(0u16),
+                keycode,
+                GrabMode::ASYNC,
+                GrabMode::ASYNC,
+            )
+            .map_err(|e| e.to_string())?
+            .check();
+        match result {
+            Ok(()) => {
+                connection
+                    .ungrab_key(keycode, root, ModMask::from(0u16))
+                    .map_err(|e| e.to_string())?;
+            }
+            Err(ReplyError::X11Error(e)) if e.error_kind == ErrorKind::Access => grabbed.push(*key),
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    connection.flush().map_err(|e| e.to_string())?;
+    Ok(grabbed)
+}
+
+/// Output of `gsettings`, `None` if it isn't installed or the schema is unknown
+fn gsettings(args: &[&str]) -> Option<String> {
+    let output = Command::new("gsettings").args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Strings quoted in a GVariant, e.g. `['F7', '<Super>F7']`
+fn quoted(value: &str) -> Vec<&str> {
+    value.split('\'').skip(1).step_by(2).collect()
+}
+
+/// Shortcuts in the output of `gsettings list-recursively`, e.g.
+/// `org.gnome.settings-daemon.plugins.media-keys next ['F9']`
+fn parse_gsettings(output: &str) -> Vec<(Key, String)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(3, ' ');
+            let (_schema, name, value) = (parts.next()?, parts.next()?, parts.next()?);
+            let key = quoted(value).into_iter().find_map(parse_key)?;
+            Some((key, format!("GNOME shortcut '{}'", name)))
+        })
+        .collect()
+}
+
+fn gnome_shortcuts() -> Vec<(Key, String)> {
+    let mut shortcuts: Vec<(Key, String)> = GNOME_SCHEMAS
+        .iter()
+        .filter_map(|schema| gsettings(&["list-recursively", schema]))
+        .flat_map(|output| parse_gsettings(&output))
+        .collect();
+
+    // Custom shortcuts live in relocatable schemas, one path each
+    let custom = gsettings(&["get", GNOME_SCHEMAS[0], "custom-keybindings"]).unwrap_or_default();
+    for path in quoted(&custom) {
+        let schema = format!("{}:{}", GNOME_CUSTOM_SCHEMA, path);
+        let binding = gsettings(&["get", &schema, "binding"]).unwrap_or_default();
+        let Some(key) = quoted(&binding).into_iter().find_map(parse_key) else {
+            continue;
+        };
+        let name = gsettings(&["get", &schema, "name"]).unwrap_or_default();
+        let name = quoted(&name).first().copied().unwrap_or(path).to_string();
+        shortcuts.push((key, format!("GNOME shortcut '{}'", name)));
+    }
+    shortcuts
+}
+
+/// Shortcuts in `kglobalshortcutsrc`, whose entries are
+/// `action=current shortcuts,default shortcuts,description`
+/// with the shortcuts separated by tabs
+fn parse_kglobalshortcutsrc(contents: &str) -> Vec<(Key, String)> {
+    let mut component = "";
+    let mut shortcuts = Vec::new();
+    for line in contents.lines() {
+        if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            component = section;
+            continue;
+        }
+        let Some((action, value)) = line.split_once('=') else {
+            continue;
+        };
+        if action == "_k_friendly_name" {
+            continue;
+        }
+        let mut fields = value.split(',');
+        let current = fields.next().unwrap_or("");
+        let description = fields.nth(1).filter(|d| !d.is_empty()).unwrap_or(action);
+        if let Some(key) = current.split('\t').find_map(parse_key) {
+            shortcuts.push((
+                key,
+                format!("KDE shortcut '{}' of {}", description, component),
+            ));
+        }
+    }
+    shortcuts
+}
+
+fn kde_shortcuts() -> Vec<(Key, String)> {
+    let path = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("kglobalshortcutsrc");
+    std::fs::read_to_string(path)
+        .map(|contents| parse_kglobalshortcutsrc(&contents))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_gnome_shortcuts_without_modifiers() {
+        let output = "\
+org.gnome.settings-daemon.plugins.media-keys next ['F9']
+org.gnome.settings-daemon.plugins.media-keys play ['<Super>F8']
+org.gnome.desktop.wm.keybindings toggle-fullscreen ['F11', '<Alt>Return']
+org.gnome.desktop.wm.keybindings close @as []
+org.gnome.settings-daemon.plugins.media-keys volume-up 'F12'
+";
+        assert_eq!(
+            parse_gsettings(output),
+            [
+                (Key::F9, "GNOME shortcut 'next'".to_string()),
+                (Key::F11, "GNOME shortcut 'toggle-fullscreen'".to_string()),
+                (Key::F12, "GNOME shortcut 'volume-up'".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_kde_shortcuts_without_modifiers() {
+        let contents = "\
+[kwin]
+_k_friendly_name=KWin
+Expose=Ctrl+F9,Ctrl+F9,Toggle Present Windows (Current desktop)
+Window Maximize=Meta+PgUp\tF10,Meta+PgUp,Maximize Window
+
+[mediacontrol]
+nextmedia=Media Next\tF7,Media Next,Media playback next
+";
+        assert_eq!(
+            parse_kglobalshortcutsrc(contents),
+            [
+                (
+                    Key::F10,
+                    "KDE shortcut 'Maximize Window' of kwin".to_string()
+                ),
+                (
+                    Key::F7,
+                    "KDE shortcut 'Media playback next' of mediacontrol".to_string()
+                ),
+            ]
+        );
+    }
+}
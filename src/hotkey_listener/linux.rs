@@ -1,74 +1,147 @@
-use crate::config::{Config, MediaAction};
-use crate::media_control::MediaController;
+use crate::config::Config;
+use crate::actions::{ActionRunner, Trigger};
 use crate::ui::AppMessage;
-use crossbeam_channel::Sender;
-use log::{error, info};
-use rdev::{listen, Event, EventType};
+use crossbeam_channel::{Receiver, Sender};
+use log::{error, info, warn};
+use rdev::{listen, Event, EventType, Key};
 use std::sync::{Arc, Mutex};
+use std::thread;
+
+use super::{conflicts, BindingStatus, ListenerControl};
 
 pub struct LinuxHotkeyListener {
     config: Arc<Mutex<Config>>,
-    media_controller: MediaController,
+    action_runner: ActionRunner,
     app_sender: Sender<AppMessage>,
+    control: ListenerControl,
 }
 
 impl LinuxHotkeyListener {
     pub fn new(
         config: Arc<Mutex<Config>>,
-        media_controller: MediaController,
+        action_runner: ActionRunner,
         app_sender: Sender<AppMessage>,
+        control: ListenerControl,
     ) -> Self {
         info!("Linux hotkey listener created (using rdev)");
         LinuxHotkeyListener {
             config,
-            media_controller,
+            action_runner,
             app_sender,
+            control,
         }
     }
 
-    pub fn start(&self) {
+    pub fn start(self) {
         info!("Starting Linux hotkey listener with rdev");
 
-        // Clone Arc references for the closure
-        let config = self.config.clone();
-        let app_sender = self.app_sender.clone();
+        // rdev::listen takes ownership of the closure, so move our state into it
+        let LinuxHotkeyListener {
+            config,
+            action_runner,
+            app_sender,
+            control,
+        } = self;
 
-        // We need to create a new MediaController because rdev::listen takes ownership
-        // of the closure and MediaController is not Clone
-        let media_controller = MediaController::new();
+        // Conflicts are checked again whenever the bindings change
+        let (recheck, rechecks) = crossbeam_channel::unbounded();
+        *control.recheck.lock().unwrap() = Some(recheck);
+        {
+            let config = config.clone();
+            let control = control.clone();
+            let app_sender = app_sender.clone();
+            thread::spawn(move || Self::check_conflicts(&config, &control, &app_sender, rechecks));
+        }
 
+        let listener_sender = app_sender.clone();
+        let listener_config = config.clone();
         if let Err(error) = listen(move |event: Event| {
-            Self::handle_event(&event, &config, &media_controller, &app_sender);
+            Self::handle_event(&event, &listener_config, &action_runner, &listener_sender, &control);
         }) {
             error!("Error listening to keyboard events: {:?}", error);
+            let reason = format!("keyboard events can't be read: {:?}", error);
+            let statuses = config
+                .lock()
+                .unwrap()
+                .active()
+                .hotkeys
+                .keys()
+                .map(|key| (*key, BindingStatus::Failed(reason.clone())))
+                .collect();
+            let _ = app_sender.send(AppMessage::BindingStatus(statuses));
+        }
+    }
+
+    /// Report what else uses the keys of the bindings, again after each change
+    fn check_conflicts(
+        config: &Arc<Mutex<Config>>,
+        control: &ListenerControl,
+        app_sender: &Sender<AppMessage>,
+        rechecks: Receiver<()>,
+    ) {
+        loop {
+            let keys: Vec<Key> = config
+                .lock()
+                .unwrap()
+                .active()
+                .hotkeys
+                .iter()
+                .filter(|(_, action)| control.handles(action))
+                .map(|(key, _)| *key)
+                .collect();
+
+            let conflicts = conflicts::find(&keys);
+            let statuses = keys
+                .into_iter()
+                .map(|key| match conflicts.get(&key) {
+                    Some(other) => {
+                        warn!("{:?} is also used: {}", key, other);
+                        (key, BindingStatus::Shadowed(other.clone()))
+                    }
+                    None => (key, BindingStatus::Registered),
+                })
+                .collect();
+            let _ = app_sender.send(AppMessage::BindingStatus(statuses));
+
+            if rechecks.recv().is_err() {
+                return;
+            }
+            // Changes made at once are checked once
+            while rechecks.try_recv().is_ok() {}
         }
     }
 
     fn handle_event(
         event: &Event,
         config: &Arc<Mutex<Config>>,
-        media_controller: &MediaController,
+        action_runner: &ActionRunner,
         app_sender: &Sender<AppMessage>,
+        control: &ListenerControl,
     ) {
         if let EventType::KeyPress(key) = event.event_type {
-            let config = config.lock().unwrap();
+            // Every key press of the system arrives here, only bound keys copy
+            // anything. The lock is released before executing, actions may take a while
+            let (action, settings) = {
+                let config = config.lock().unwrap();
+                // rdev only observes key presses, paused hotkeys are simply ignored
+                match config
+                    .active()
+                    .hotkeys
+                    .get(&key)
+                    .filter(|action| control.handles(action))
+                {
+                    Some(action) => (action.clone(), config.action_settings()),
+                    None => return,
+                }
+            };
 
-            if let Some(action) = config.hotkeys.get(&key) {
-                info!("Hotkey pressed: {:?} -> {:?}", key, action);
+            info!("Hotkey pressed: {:?} -> {:?}", key, action);
 
-                // Send message to UI
-                let _ = app_sender.send(AppMessage::HotkeyPressed(key, *action));
+            // Send message to UI
+            let _ = app_sender.send(AppMessage::HotkeyPressed(key, action.clone()));
 
-                // Execute action
-                match action {
-                    MediaAction::PlayPause => media_controller.play_pause(),
-                    MediaAction::Next => media_controller.next(),
-                    MediaAction::Previous => media_controller.previous(),
-                    MediaAction::VolumeUp => media_controller.volume_up(),
-                    MediaAction::VolumeDown => media_controller.volume_down(),
-                    MediaAction::Stop => media_controller.stop(),
-                }
-            }
+            // Execute action
+            action_runner.run(Trigger::Key(key), &action, &settings);
         }
     }
 }
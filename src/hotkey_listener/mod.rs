@@ -4,14 +4,109 @@ mod windows;
 #[cfg(target_os = "linux")]
 mod linux;
 
+#[cfg(target_os = "linux")]
+mod conflicts;
+
 use crate::config::{Config, MediaAction};
-use crate::media_control::MediaController;
+use crate::actions::ActionRunner;
 use crate::ui::AppMessage;
 use crossbeam_channel::Sender;
+use std::collections::HashMap;
 use log::info;
 use rdev::Key;
+#[cfg(target_os = "windows")]
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 
+/// Whether the key of a binding in the active profile reaches the app
+#[derive(Debug, Clone, PartialEq)]
+pub enum BindingStatus {
+    Registered,
+    /// The key could not be registered, with the reason
+    Failed(String),
+    /// Another shortcut uses the key too, e.g. one of the desktop
+    Shadowed(String),
+}
+
+/// Status of each binding that is handled right now
+pub type BindingStatuses = HashMap<Key, BindingStatus>;
+
+/// Lets other threads tell the running listener that the bindings have changed
+#[derive(Clone, Default)]
+pub struct ListenerControl {
+    /// Thread running the message loop, 0 until it has started
+    #[cfg(target_os = "windows")]
+    thread_id: Arc<AtomicU32>,
+    /// Tells the thread checking for conflicts, `None` until it has started
+    #[cfg(target_os = "linux")]
+    recheck: Arc<Mutex<Option<Sender<()>>>>,
+    /// Hotkeys are ignored while a window rule disables them
+    suspended: Arc<AtomicBool>,
+    /// Paused by the user, only `ToggleEnabled` still works
+    paused: Arc<AtomicBool>,
+}
+
+impl ListenerControl {
+    /// Ignore all hotkeys, or stop ignoring them
+    pub fn set_suspended(&self, suspended: bool) {
+        if self.suspended.swap(suspended, Ordering::SeqCst) != suspended {
+            info!("Hotkeys {}", if suspended { "suspended" } else { "resumed" });
+            self.rebind();
+        }
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.suspended.load(Ordering::SeqCst)
+    }
+
+    /// Pause or resume the hotkeys, returns false if nothing changed
+    pub fn set_paused(&self, paused: bool) -> bool {
+        if self.paused.swap(paused, Ordering::SeqCst) == paused {
+            return false;
+        }
+        info!("Hotkeys {}", if paused { "paused" } else { "enabled" });
+        self.rebind();
+        true
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Whether a key bound to `action` is handled right now
+    pub fn handles(&self, action: &MediaAction) -> bool {
+        if self.is_suspended() {
+            return false;
+        }
+        !self.is_paused() || *action == MediaAction::ToggleEnabled
+    }
+
+    /// Be told of every rebind instead of the conflict check
+    #[cfg(all(test, target_os = "linux"))]
+    pub fn watch_rebinds(&self) -> crossbeam_channel::Receiver<()> {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        *self.recheck.lock().unwrap() = Some(sender);
+        receiver
+    }
+
+    /// Pick up the keys of the active profile
+    pub fn rebind(&self) {
+        #[cfg(target_os = "windows")]
+        windows::request_rebind(&self.thread_id);
+
+        // rdev sees every key press on Linux and the bindings are
+        // looked up for each one, there is nothing to register
+        #[cfg(target_os = "linux")]
+        {
+            info!("Hotkey bindings changed");
+            if let Some(recheck) = &*self.recheck.lock().unwrap() {
+                let _ = recheck.send(());
+            }
+        }
+    }
+}
+
 pub struct HotkeyListener {
     #[cfg(target_os = "windows")]
     inner: windows::WindowsHotkeyListener,
@@ -23,27 +118,34 @@ pub struct HotkeyListener {
 impl HotkeyListener {
     pub fn new(
         config: Arc<Mutex<Config>>,
-        media_controller: MediaController,
+        action_runner: ActionRunner,
         app_sender: Sender<AppMessage>,
+        control: ListenerControl,
     ) -> Self {
         info!("Creating hotkey listener");
 
         #[cfg(target_os = "windows")]
-        let inner = windows::WindowsHotkeyListener::new(config, media_controller, app_sender);
+        let inner = windows::WindowsHotkeyListener::new(
+            config,
+            action_runner,
+            app_sender,
+            control,
+        );
 
         #[cfg(target_os = "linux")]
-        let inner = linux::LinuxHotkeyListener::new(config, media_controller, app_sender);
+        let inner = linux::LinuxHotkeyListener::new(config, action_runner, app_sender, control);
 
         HotkeyListener { inner }
     }
 
     /// Start listening for hotkeys (blocking call)
-    pub fn start(&self) {
+    pub fn start(self) {
         self.inner.start();
     }
 }
 
 /// Convert rdev::Key to a unique hotkey ID for Windows RegisterHotKey
+#[cfg(target_os = "windows")]
 pub fn key_to_hotkey_id(key: &Key) -> Option<i32> {
     match key {
         Key::F1 => Some(1),
@@ -83,6 +185,7 @@ pub fn key_to_vk(key: &Key) -> Option<u32> {
 }
 
 /// Convert hotkey ID back to rdev::Key
+#[cfg(target_os = "windows")]
 pub fn hotkey_id_to_key(id: i32) -> Option<Key> {
     match id {
         1 => Some(Key::F1),
@@ -100,3 +203,36 @@ pub fn hotkey_id_to_key(id: i32) -> Option<Key> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paused_keys_only_toggle() {
+        let control = ListenerControl::default();
+        assert!(control.handles(&MediaAction::Next));
+
+        assert!(control.set_paused(true));
+        assert!(!control.handles(&MediaAction::Next));
+        assert!(control.handles(&MediaAction::ToggleEnabled));
+
+        // Nothing changes, nothing to rebind
+        assert!(!control.set_paused(true));
+        assert!(control.set_paused(false));
+        assert!(!control.set_paused(false));
+        assert!(control.handles(&MediaAction::Next));
+    }
+
+    #[test]
+    fn suspended_keys_are_all_ignored() {
+        let control = ListenerControl::default();
+        control.set_suspended(true);
+        assert!(!control.handles(&MediaAction::Next));
+        assert!(!control.handles(&MediaAction::ToggleEnabled));
+
+        control.set_paused(true);
+        control.set_suspended(false);
+        assert!(control.handles(&MediaAction::ToggleEnabled));
+    }
+}
@@ -1,49 +1,78 @@
-use crate::config::{Config, MediaAction};
-use crate::media_control::MediaController;
+use crate::config::Config;
+use crate::actions::{ActionRunner, Trigger};
 use crate::ui::AppMessage;
 use crossbeam_channel::Sender;
 use log::{error, info, warn};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::{Arc, Mutex};
 
-use windows::Win32::Foundation::HWND;
+use windows::Win32::Foundation::{HWND, LPARAM, WPARAM};
+use windows::Win32::System::Threading::GetCurrentThreadId;
 use windows::Win32::UI::Input::KeyboardAndMouse::{
     RegisterHotKey, UnregisterHotKey, HOT_KEY_MODIFIERS,
 };
 use windows::Win32::UI::WindowsAndMessaging::{
-    GetMessageW, MSG, WM_HOTKEY,
+    GetMessageW, PostThreadMessageW, MSG, WM_APP, WM_HOTKEY,
 };
 
-use super::{hotkey_id_to_key, key_to_hotkey_id, key_to_vk};
+use super::{hotkey_id_to_key, key_to_hotkey_id, key_to_vk, BindingStatus, ListenerControl};
+
+/// Posted to the listener thread when the bindings have changed
+const WM_REBIND: u32 = WM_APP + 1;
+
+/// Ask the message loop on `thread_id` to register the hotkeys again
+pub fn request_rebind(thread_id: &AtomicU32) {
+    let thread_id = thread_id.load(Ordering::SeqCst);
+    if thread_id == 0 {
+        // Not started yet, the current bindings are registered on start
+        return;
+    }
+
+    unsafe {
+        if let Err(e) = PostThreadMessageW(thread_id, WM_REBIND, WPARAM(0), LPARAM(0)) {
+            error!("Failed to notify the hotkey listener: {}", e);
+        }
+    }
+}
 
 pub struct WindowsHotkeyListener {
     config: Arc<Mutex<Config>>,
-    media_controller: MediaController,
+    action_runner: ActionRunner,
     app_sender: Sender<AppMessage>,
+    control: ListenerControl,
 }
 
 impl WindowsHotkeyListener {
     pub fn new(
         config: Arc<Mutex<Config>>,
-        media_controller: MediaController,
+        action_runner: ActionRunner,
         app_sender: Sender<AppMessage>,
+        control: ListenerControl,
     ) -> Self {
         info!("Windows hotkey listener created");
         WindowsHotkeyListener {
             config,
-            media_controller,
+            action_runner,
             app_sender,
+            control,
         }
     }
 
-    pub fn start(&self) {
+    pub fn start(self) {
         info!("Starting Windows hotkey listener with RegisterHotKey");
 
         // Register all configured hotkeys
-        let registered_ids = self.register_hotkeys();
+        let mut registered_ids = self.register_hotkeys();
+
+        // Hotkeys belong to this thread, other threads ask it to register them again.
+        // Registering has created the message queue, so messages can be posted now.
+        self.control
+            .thread_id
+            .store(unsafe { GetCurrentThreadId() }, Ordering::SeqCst);
 
         if registered_ids.is_empty() {
-            error!("No hotkeys were registered successfully");
-            return;
+            warn!("No hotkeys were registered");
         }
 
         info!("Registered {} hotkeys, starting message loop", registered_ids.len());
@@ -57,21 +86,41 @@ impl WindowsHotkeyListener {
                 if msg.message == WM_HOTKEY {
                     let hotkey_id = msg.wParam.0 as i32;
                     self.handle_hotkey(hotkey_id);
+                } else if msg.message == WM_REBIND {
+                    info!("Hotkey bindings changed, registering again");
+                    Self::unregister_hotkeys(&registered_ids);
+                    registered_ids = self.register_hotkeys();
                 }
             }
 
             // Unregister hotkeys on exit
-            for id in &registered_ids {
+            Self::unregister_hotkeys(&registered_ids);
+        }
+    }
+
+    fn unregister_hotkeys(ids: &[i32]) {
+        for id in ids {
+            unsafe {
                 let _ = UnregisterHotKey(HWND::default(), *id);
             }
         }
     }
 
+    /// Register the keys of the active profile, the UI is told how it went
     fn register_hotkeys(&self) -> Vec<i32> {
         let config = self.config.lock().unwrap();
         let mut registered = Vec::new();
+        let mut statuses = HashMap::new();
 
-        for (key, _action) in &config.hotkeys {
+        // Paused hotkeys are released, so other applications receive the keys
+        if self.control.is_paused() {
+            info!("Hotkeys are paused, only registering the keys that resume them");
+        }
+
+        for (key, action) in &config.active().hotkeys {
+            if !self.control.handles(action) {
+                continue;
+            }
             if let (Some(id), Some(vk)) = (key_to_hotkey_id(key), key_to_vk(key)) {
                 unsafe {
                     // Register hotkey without any modifiers (MOD_NOREPEAT = 0x4000 to avoid repeat)
@@ -85,39 +134,50 @@ impl WindowsHotkeyListener {
                     if result.is_ok() {
                         info!("Registered hotkey: {:?} (id={}, vk=0x{:X})", key, id, vk);
                         registered.push(id);
+                        statuses.insert(*key, BindingStatus::Registered);
                     } else {
                         warn!(
                             "Failed to register hotkey {:?}: key might be in use by another application",
                             key
                         );
+                        let reason = "the key might be in use by another application".to_string();
+                        statuses.insert(*key, BindingStatus::Failed(reason));
                     }
                 }
+            } else {
+                let reason = "the key can't be registered on Windows".to_string();
+                statuses.insert(*key, BindingStatus::Failed(reason));
             }
         }
 
+        let _ = self.app_sender.send(AppMessage::BindingStatus(statuses));
         registered
     }
 
     fn handle_hotkey(&self, hotkey_id: i32) {
         if let Some(key) = hotkey_id_to_key(hotkey_id) {
-            let config = self.config.lock().unwrap();
-
-            if let Some(action) = config.hotkeys.get(&key) {
-                info!("Hotkey pressed: {:?} -> {:?}", key, action);
-
-                // Send message to UI
-                let _ = self.app_sender.send(AppMessage::HotkeyPressed(key, *action));
-
-                // Execute action
-                match action {
-                    MediaAction::PlayPause => self.media_controller.play_pause(),
-                    MediaAction::Next => self.media_controller.next(),
-                    MediaAction::Previous => self.media_controller.previous(),
-                    MediaAction::VolumeUp => self.media_controller.volume_up(),
-                    MediaAction::VolumeDown => self.media_controller.volume_down(),
-                    MediaAction::Stop => self.media_controller.stop(),
+            // Release the lock before executing, actions may take a while
+            let (action, settings) = {
+                let config = self.config.lock().unwrap();
+                // A message may still arrive right after pausing
+                match config
+                    .active()
+                    .hotkeys
+                    .get(&key)
+                    .filter(|action| self.control.handles(action))
+                {
+                    Some(action) => (action.clone(), config.action_settings()),
+                    None => return,
                 }
-            }
+            };
+
+            info!("Hotkey pressed: {:?} -> {:?}", key, action);
+
+            // Send message to UI
+            let _ = self.app_sender.send(AppMessage::HotkeyPressed(key, action.clone()));
+
+            // Execute action
+            self.action_runner.run(Trigger::Key(key), &action, &settings);
         }
     }
 }
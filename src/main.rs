@@ -1,68 +1,189 @@
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use log::{error, info};
+use std::process::ExitCode;
 use std::sync::{Arc, Mutex};
 use std::thread;
 
+mod actions;
+mod cli;
 mod config;
+mod config_watcher;
+mod control;
 mod hotkey_listener;
 mod media_control;
+mod scheduler;
 mod ui;
+mod window_watcher;
 
-use config::Config;
-use hotkey_listener::HotkeyListener;
+use actions::{ActionRunner, Trigger};
+use cli::Cli;
+use config::{Config, Layers, LoadError};
+use config_watcher::ConfigWatcher;
+use hotkey_listener::{HotkeyListener, ListenerControl};
 use media_control::MediaController;
+use scheduler::{Scheduler, SystemClock};
 use ui::{AppMessage, UiMessage};
+use window_watcher::WindowWatcher;
 
-fn main() {
-    // Initialize logging
-    env_logger::init();
+/// Log to stderr, or to a file next to the executable in portable mode
+fn init_logging() {
+    let mut builder = env_logger::Builder::from_default_env();
+    match Config::open_log_file() {
+        Some(Ok(file)) => {
+            // Nobody reads the file while it runs, so it gets more than errors
+            if std::env::var_os(env_logger::DEFAULT_FILTER_ENV).is_none() {
+                builder.filter_level(log::LevelFilter::Info);
+            }
+            builder.target(env_logger::Target::Pipe(Box::new(file)));
+        }
+        Some(Err(e)) => eprintln!("Failed to create the log file: {}", e),
+        None => {}
+    }
+    builder.init();
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    Config::set_portable(cli.portable);
 
+    // Commands for the running instance
+    if let Some(command) = cli.command {
+        // They would be dropped without a word, commands save the user's file as it is
+        if !cli.overrides.is_empty() {
+            Cli::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "--set only applies to the app, not to commands",
+                )
+                .exit();
+        }
+        env_logger::init();
+        // Commands work on the user's file as it is
+        Config::set_layers(Layers::new(cli.config, Vec::new()));
+        return cli::run(command);
+    }
+
+    init_logging();
     info!("Starting Music HotKey Mapper");
+    if Config::is_portable() {
+        info!("Portable mode, using {}", Config::config_dir().display());
+    }
 
-    // Load configuration
+    // The system file and the command line are merged with the user's file
+    let mut config_error = None;
+    let mut layers = Layers::new(cli.config, cli.overrides);
+    if let Err(e) = layers.read_system_file(&Config::system_config_path()) {
+        error!("{}", e);
+        config_error = Some(format!("{}. It is not applied.", e));
+    }
+    Config::set_layers(layers);
+
+    // Load configuration, an invalid file is never overwritten
     let config = match Config::load() {
         Ok(cfg) => {
             info!("Configuration loaded successfully");
             cfg
         }
-        Err(e) => {
-            error!("Failed to load configuration: {}", e);
+        Err(LoadError::Missing) => {
             info!("Creating default configuration");
-            let cfg = Config::default();
-            if let Err(e) = cfg.save() {
-                error!("Failed to save default configuration: {}", e);
-            }
+            Config::create()
+        }
+        Err(LoadError::Invalid(e)) => {
+            error!("Configuration is invalid: {}", e);
+            let (cfg, message) = Config::recover(&e);
+            config_error = Some(message);
             cfg
         }
+        Err(LoadError::Io(e)) => {
+            // Not saved here, the file may be readable again on the next start
+            error!("Failed to read configuration: {}", e);
+            config_error = Some(format!(
+                "Failed to read the configuration file: {}. Using the default configuration.",
+                e
+            ));
+            Config::default()
+        }
     };
 
     // Create channels for communication between UI and main thread
     let (ui_sender, ui_receiver): (Sender<UiMessage>, Receiver<UiMessage>) = unbounded();
     let (app_sender, app_receiver): (Sender<AppMessage>, Receiver<AppMessage>) = unbounded();
+    if let Some(message) = config_error {
+        let _ = app_sender.send(AppMessage::ConfigError(message));
+    }
 
     // Clone configuration for different threads
     let config_for_ui = config.clone();
     let config_shared = Arc::new(Mutex::new(config));
 
+    // Create media controller and the runner that executes actions with it
+    let media_controller = MediaController::new();
+    let listener_control = ListenerControl::default();
+    let action_runner = ActionRunner::new(
+        media_controller,
+        config_shared.clone(),
+        listener_control.clone(),
+        app_sender.clone(),
+    );
+
+    // Let the command line reach this instance
+    control::start(control::Handler {
+        action_runner: action_runner.clone(),
+    });
+
     // Start thread for keyboard event processing
     let config_for_listener = config_shared.clone();
     let app_sender_for_listener = app_sender.clone();
+    let action_runner_for_listener = action_runner.clone();
+    let listener_control_for_listener = listener_control.clone();
 
     thread::spawn(move || {
         info!("Starting keyboard listener thread");
 
-        // Create media controller
-        let media_controller = MediaController::new();
-
         // Create and start hotkey listener (platform-specific implementation)
         let listener = HotkeyListener::new(
             config_for_listener,
-            media_controller,
+            action_runner_for_listener,
             app_sender_for_listener,
+            listener_control_for_listener,
         );
         listener.start();
     });
 
+    // Start thread for scheduled actions
+    let scheduler = Scheduler::new(SystemClock, config_shared.clone());
+    let action_runner_for_scheduler = action_runner.clone();
+    thread::spawn(move || {
+        info!("Starting scheduler thread");
+        scheduler.run(action_runner_for_scheduler);
+    });
+
+    // Start thread applying the window rules
+    let window_watcher = WindowWatcher::new(
+        config_shared.clone(),
+        action_runner.clone(),
+        listener_control.clone(),
+        app_sender.clone(),
+    );
+    thread::spawn(move || {
+        info!("Starting window watcher thread");
+        window_watcher.start();
+    });
+
+    // Start thread applying changes made to the configuration file
+    let config_watcher = ConfigWatcher::new(
+        Config::config_path(),
+        config_shared.clone(),
+        listener_control.clone(),
+        app_sender.clone(),
+    );
+    thread::spawn(move || {
+        info!("Starting configuration watcher thread");
+        config_watcher.start();
+    });
+
     // Start thread for processing messages from UI
     let config_for_messages = config_shared.clone();
     thread::spawn(move || {
@@ -70,12 +191,28 @@ fn main() {
 
         loop {
             match ui_receiver.recv() {
-                Ok(UiMessage::SaveConfig(new_config)) => {
+                Ok(UiMessage::ApplyConfig(new_config)) => {
                     info!("Received new configuration from UI");
                     let mut config = config_for_messages.lock().unwrap();
                     *config = new_config;
+                    drop(config);
+                    listener_control.rebind();
                     info!("Configuration updated in listener thread");
                 }
+                Ok(UiMessage::TestAction(key, action)) => {
+                    info!("Testing action from UI: {:?} -> {:?}", key, action);
+                    let config = config_for_messages.lock().unwrap().clone();
+                    action_runner.run(Trigger::Key(key), &action, &config);
+                }
+                Ok(UiMessage::SwitchProfile(name)) => {
+                    action_runner.switch_profile(Some(&name));
+                }
+                Ok(UiMessage::SetEnabled(enabled)) => {
+                    action_runner.set_enabled(enabled);
+                }
+                Ok(UiMessage::CancelSleepTimer) => {
+                    action_runner.sleep_timer().cancel();
+                }
                 Ok(UiMessage::Exit) => {
                     info!("Received exit message from UI");
                     std::process::exit(0);
@@ -92,6 +229,8 @@ fn main() {
     info!("Starting UI");
     if let Err(e) = ui::run_ui(config_for_ui, ui_sender, app_receiver) {
         error!("Failed to run UI: {}", e);
-        std::process::exit(1);
+        return ExitCode::FAILURE;
     }
+
+    ExitCode::SUCCESS
 }
@@ -1,6 +1,8 @@
 use log::{info, error};
 use std::process::Command;
 
+use super::{pulseaudio, NowPlaying};
+
 pub struct LinuxMediaController;
 
 impl LinuxMediaController {
@@ -13,10 +15,16 @@ impl LinuxMediaController {
 
     /// Execute playerctl command
     fn execute_playerctl(&self, command: &str) {
+        self.execute_playerctl_args(&[command]);
+    }
+
+    /// Execute playerctl command with arguments
+    fn execute_playerctl_args(&self, args: &[&str]) {
+        let command = args.join(" ");
         // Use -a flag to send command to all available players
         match Command::new("playerctl")
             .arg("-a")
-            .arg(command)
+            .args(args)
             .output()
         {
             Ok(output) => {
@@ -60,7 +68,7 @@ impl LinuxMediaController {
         // Increase volume by 5%
         self.execute_playerctl("volume");
         match Command::new("playerctl")
-            .args(&["volume", "0.05+"])
+            .args(["volume", "0.05+"])
             .output()
         {
             Ok(output) => {
@@ -83,7 +91,7 @@ impl LinuxMediaController {
         info!("Linux: Sending Volume Down");
         // Decrease volume by 5%
         match Command::new("playerctl")
-            .args(&["volume", "0.05-"])
+            .args(["volume", "0.05-"])
             .output()
         {
             Ok(output) => {
@@ -108,4 +116,77 @@ impl LinuxMediaController {
         // cannot resume playback after stop
         self.execute_playerctl("pause");
     }
+
+    pub fn set_volume(&self, percent: u32) {
+        info!("Linux: Setting volume to {}%", percent);
+        // playerctl uses 0.0 - 1.0
+        let volume = format!("{:.2}", percent as f64 / 100.0);
+        self.execute_playerctl_args(&["volume", &volume]);
+    }
+
+    pub fn volume(&self) -> Option<u32> {
+        match Command::new("playerctl").arg("volume").output() {
+            Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .map(|volume| (volume * 100.0).round() as u32),
+            Ok(_) => {
+                info!("No active player to query the volume of");
+                None
+            }
+            Err(e) => {
+                error!("Failed to execute playerctl: {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn now_playing(&self) -> Option<NowPlaying> {
+        let output = match Command::new("playerctl")
+            .args(["metadata", "--format", "{{status}}\t{{artist}}\t{{title}}\t{{album}}"])
+            .output()
+        {
+            Ok(output) if output.status.success() => output,
+            Ok(_) => {
+                info!("No active player to query");
+                return None;
+            }
+            Err(e) => {
+                error!("Failed to execute playerctl: {}", e);
+                return None;
+            }
+        };
+
+        let output = String::from_utf8_lossy(&output.stdout);
+        let mut fields = output.trim_end_matches('\n').split('\t');
+        let status = fields.next().unwrap_or_default();
+
+        Some(NowPlaying {
+            playing: status == "Playing",
+            artist: fields.next().unwrap_or_default().to_string(),
+            title: fields.next().unwrap_or_default().to_string(),
+            album: fields.next().unwrap_or_default().to_string(),
+        })
+    }
+
+    pub fn app_volume(&self, app: &str, step: i32) {
+        info!("Linux: Changing volume of '{}' by {:+}%", app, step);
+        pulseaudio::change_app_volume(app, step);
+    }
+
+    pub fn app_mute(&self, app: &str) {
+        info!("Linux: Toggling mute of '{}'", app);
+        pulseaudio::toggle_app_mute(app);
+    }
+
+    pub fn cycle_output_device(&self, order: &[String], move_streams: bool) {
+        info!("Linux: Switching to next output device");
+        pulseaudio::cycle_default_sink(order, move_streams);
+    }
+
+    pub fn set_output_device(&self, name: &str, move_streams: bool) {
+        info!("Linux: Switching output device to '{}'", name);
+        pulseaudio::set_default_sink(name, move_streams);
+    }
 }
@@ -4,7 +4,52 @@ mod windows;
 #[cfg(target_os = "linux")]
 mod linux;
 
-use log::info;
+#[cfg(target_os = "linux")]
+mod pulseaudio;
+
+mod mpd;
+mod mpv;
+
+use crate::config::{Backend, OutputDeviceSettings};
+use log::{info, warn};
+use std::sync::Mutex;
+
+/// Track that is currently loaded in the player
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NowPlaying {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub playing: bool,
+}
+
+/// An application that currently plays audio
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppStream {
+    /// Application name as reported by the sound server
+    pub name: String,
+    /// Executable name, if known
+    pub binary: String,
+    /// Title of the stream (e.g. the browser tab)
+    pub media_name: String,
+}
+
+/// An audio output device (sink)
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputDevice {
+    /// Internal device name used in the configuration
+    pub name: String,
+    /// Human readable description
+    pub description: String,
+}
+
+/// Player that receives playback actions
+enum Player {
+    /// Platform-specific controller
+    System,
+    Mpd(mpd::MpdController),
+    Mpv(mpv::MpvController),
+}
 
 /// Controller for media player management
 pub struct MediaController {
@@ -13,6 +58,8 @@ pub struct MediaController {
 
     #[cfg(target_os = "linux")]
     inner: linux::LinuxMediaController,
+
+    player: Mutex<Player>,
 }
 
 impl MediaController {
@@ -23,6 +70,7 @@ impl MediaController {
         {
             MediaController {
                 inner: windows::WindowsMediaController::new(),
+                player: Mutex::new(Player::System),
             }
         }
 
@@ -30,6 +78,7 @@ impl MediaController {
         {
             MediaController {
                 inner: linux::LinuxMediaController::new(),
+                player: Mutex::new(Player::System),
             }
         }
 
@@ -39,34 +88,201 @@ impl MediaController {
         }
     }
 
+    /// Switch the player backend if the configured one has changed
+    pub fn select_backend(&self, backend: &Backend) {
+        let mut player = self.player.lock().unwrap();
+
+        match (backend, &*player) {
+            (Backend::System, Player::System) => {}
+            (Backend::Mpd(settings), Player::Mpd(mpd)) if mpd.settings() == settings => {}
+            (Backend::Mpv(settings), Player::Mpv(mpv)) if mpv.settings() == settings => {}
+            (Backend::System, _) => {
+                info!("MediaController: Switching to system backend");
+                *player = Player::System;
+            }
+            (Backend::Mpd(settings), _) => {
+                info!("MediaController: Switching to MPD backend");
+                *player = Player::Mpd(mpd::MpdController::new(settings.clone()));
+            }
+            (Backend::Mpv(settings), _) => {
+                info!("MediaController: Switching to mpv backend");
+                *player = Player::Mpv(mpv::MpvController::new(settings.clone()));
+            }
+        }
+    }
+
     pub fn play_pause(&self) {
         info!("MediaController: Play/Pause");
-        self.inner.play_pause();
+        match &*self.player.lock().unwrap() {
+            Player::System => self.inner.play_pause(),
+            Player::Mpd(mpd) => mpd.play_pause(),
+            Player::Mpv(mpv) => mpv.play_pause(),
+        }
     }
 
     pub fn next(&self) {
         info!("MediaController: Next");
-        self.inner.next();
+        match &*self.player.lock().unwrap() {
+            Player::System => self.inner.next(),
+            Player::Mpd(mpd) => mpd.next(),
+            Player::Mpv(mpv) => mpv.next(),
+        }
     }
 
     pub fn previous(&self) {
         info!("MediaController: Previous");
-        self.inner.previous();
+        match &*self.player.lock().unwrap() {
+            Player::System => self.inner.previous(),
+            Player::Mpd(mpd) => mpd.previous(),
+            Player::Mpv(mpv) => mpv.previous(),
+        }
     }
 
     pub fn volume_up(&self) {
         info!("MediaController: Volume Up");
-        self.inner.volume_up();
+        match &*self.player.lock().unwrap() {
+            Player::System => self.inner.volume_up(),
+            Player::Mpd(mpd) => mpd.volume_up(),
+            Player::Mpv(mpv) => mpv.volume_up(),
+        }
     }
 
     pub fn volume_down(&self) {
         info!("MediaController: Volume Down");
-        self.inner.volume_down();
+        match &*self.player.lock().unwrap() {
+            Player::System => self.inner.volume_down(),
+            Player::Mpd(mpd) => mpd.volume_down(),
+            Player::Mpv(mpv) => mpv.volume_down(),
+        }
     }
 
     pub fn stop(&self) {
         info!("MediaController: Stop");
-        self.inner.stop();
+        match &*self.player.lock().unwrap() {
+            Player::System => self.inner.stop(),
+            Player::Mpd(mpd) => mpd.stop(),
+            Player::Mpv(mpv) => mpv.stop(),
+        }
+    }
+
+    /// Set the player volume in percent
+    pub fn set_volume(&self, percent: u32) {
+        info!("MediaController: Set Volume {}%", percent);
+        match &*self.player.lock().unwrap() {
+            Player::System => self.inner.set_volume(percent),
+            Player::Mpd(mpd) => mpd.set_volume(percent),
+            Player::Mpv(mpv) => mpv.set_volume(percent),
+        }
+    }
+
+    /// Query the player volume in percent
+    pub fn volume(&self) -> Option<u32> {
+        match &*self.player.lock().unwrap() {
+            Player::System => self.inner.volume(),
+            Player::Mpd(mpd) => mpd.volume(),
+            Player::Mpv(mpv) => mpv.volume(),
+        }
+    }
+
+    /// Query the track that is currently loaded in the player
+    pub fn now_playing(&self) -> Option<NowPlaying> {
+        match &*self.player.lock().unwrap() {
+            Player::System => self.inner.now_playing(),
+            Player::Mpd(mpd) => mpd.now_playing(),
+            Player::Mpv(mpv) => mpv.now_playing(),
+        }
+    }
+
+    /// Run an action that only the mpv backend supports
+    fn mpv_only(&self, name: &str, f: impl FnOnce(&mpv::MpvController)) {
+        info!("MediaController: {}", name);
+        match &*self.player.lock().unwrap() {
+            Player::Mpv(mpv) => f(mpv),
+            _ => warn!("{} is only supported by the mpv backend", name),
+        }
+    }
+
+    pub fn app_volume(&self, app: &str, step: i32) {
+        info!("MediaController: App Volume {} {:+}%", app, step);
+        self.inner.app_volume(app, step);
+    }
+
+    pub fn app_mute(&self, app: &str) {
+        info!("MediaController: App Mute {}", app);
+        self.inner.app_mute(app);
+    }
+
+    pub fn cycle_output_device(&self, settings: &OutputDeviceSettings) {
+        info!("MediaController: Cycle Output Device");
+        self.inner.cycle_output_device(&settings.cycle, settings.move_streams);
+    }
+
+    pub fn set_output_device(&self, name: &str, settings: &OutputDeviceSettings) {
+        info!("MediaController: Set Output Device {}", name);
+        self.inner.set_output_device(name, settings.move_streams);
+    }
+
+    pub fn seek(&self, seconds: f64) {
+        self.mpv_only("Seek", |mpv| mpv.seek(seconds));
+    }
+
+    pub fn speed_up(&self) {
+        self.mpv_only("Speed Up", |mpv| mpv.speed_up());
+    }
+
+    pub fn speed_down(&self) {
+        self.mpv_only("Speed Down", |mpv| mpv.speed_down());
+    }
+
+    pub fn chapter_next(&self) {
+        self.mpv_only("Next Chapter", |mpv| mpv.chapter_next());
+    }
+
+    pub fn chapter_previous(&self) {
+        self.mpv_only("Previous Chapter", |mpv| mpv.chapter_previous());
+    }
+
+    pub fn toggle_subtitles(&self) {
+        self.mpv_only("Toggle Subtitles", |mpv| mpv.toggle_subtitles());
+    }
+}
+
+/// List available output devices
+pub fn output_devices() -> Vec<OutputDevice> {
+    #[cfg(target_os = "windows")]
+    {
+        windows::output_devices()
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        pulseaudio::output_devices()
+    }
+}
+
+/// List applications that currently play audio
+pub fn app_streams() -> Vec<AppStream> {
+    #[cfg(target_os = "windows")]
+    {
+        windows::app_streams()
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        pulseaudio::app_streams()
+    }
+}
+
+/// Why application volumes and output devices can't be controlled, if they can't
+pub fn audio_unavailable() -> Option<String> {
+    #[cfg(target_os = "windows")]
+    {
+        None
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        pulseaudio::unavailable()
     }
 }
 
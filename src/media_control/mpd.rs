@@ -0,0 +1,523 @@
+use crate::config::MpdSettings;
+use log::{error, info};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+
+use super::NowPlaying;
+
+/// Volume change per VolumeUp/VolumeDown, in percent
+const VOLUME_STEP: i32 = 5;
+
+/// How long to wait for MPD before giving up on a command
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Response of MPD to a command: `key: value` pairs
+type Response = Vec<(String, String)>;
+
+/// Connect to the first address of `host` that answers within the timeout,
+/// a host that drops the connection attempt doesn't block for minutes
+fn connect_tcp(host: &str, port: u16) -> io::Result<TcpStream> {
+    let mut last_error = None;
+    for address in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, format!("{} has no address", host))
+    }))
+}
+
+/// A connection to MPD, over TCP or a Unix socket
+struct Connection {
+    reader: BufReader<Box<dyn Read + Send>>,
+    writer: Box<dyn Write + Send>,
+}
+
+impl Connection {
+    fn open(settings: &MpdSettings) -> io::Result<Self> {
+        let (reader, writer): (Box<dyn Read + Send>, Box<dyn Write + Send>) =
+            match &settings.socket {
+                #[cfg(unix)]
+                Some(path) => {
+                    let stream = UnixStream::connect(path)?;
+                    stream.set_read_timeout(Some(TIMEOUT))?;
+                    stream.set_write_timeout(Some(TIMEOUT))?;
+                    (Box::new(stream.try_clone()?), Box::new(stream))
+                }
+                #[cfg(not(unix))]
+                Some(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "Unix sockets are not supported on this platform",
+                    ));
+                }
+                None => {
+                    let stream = connect_tcp(&settings.host, settings.port)?;
+                    stream.set_read_timeout(Some(TIMEOUT))?;
+                    stream.set_write_timeout(Some(TIMEOUT))?;
+                    (Box::new(stream.try_clone()?), Box::new(stream))
+                }
+            };
+
+        let mut connection = Connection {
+            reader: BufReader::new(reader),
+            writer,
+        };
+
+        // MPD greets with "OK MPD <version>"
+        let greeting = connection.read_line()?;
+        if !greeting.starts_with("OK MPD") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unexpected MPD greeting: {}", greeting),
+            ));
+        }
+
+        if let Some(password) = &settings.password {
+            connection.command(&format!("password {}", quote(password)))?;
+        }
+
+        Ok(connection)
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "MPD closed the connection",
+            ));
+        }
+        Ok(line.trim_end_matches('\n').to_string())
+    }
+
+    /// Send a command and read its response up to "OK" or "ACK"
+    fn command(&mut self, command: &str) -> io::Result<Response> {
+        self.writer.write_all(command.as_bytes())?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+
+        let mut response = Vec::new();
+        loop {
+            let line = self.read_line()?;
+            if line == "OK" {
+                return Ok(response);
+            }
+            if line.starts_with("ACK") {
+                return Err(io::Error::other(format!("MPD error: {}", line)));
+            }
+            if let Some((key, value)) = line.split_once(": ") {
+                response.push((key.to_string(), value.to_string()));
+            }
+        }
+    }
+}
+
+/// Quote a command argument
+fn quote(argument: &str) -> String {
+    format!("\"{}\"", argument.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Find a value in a response
+fn field<'a>(response: &'a Response, key: &str) -> Option<&'a str> {
+    response
+        .iter()
+        .find(|(name, _)| name == key)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Media controller for the Music Player Daemon
+pub struct MpdController {
+    settings: MpdSettings,
+}
+
+impl MpdController {
+    pub fn new(settings: MpdSettings) -> Self {
+        match &settings.socket {
+            Some(path) => info!("MPD media controller initialized ({})", path.display()),
+            None => info!(
+                "MPD media controller initialized ({}:{})",
+                settings.host, settings.port
+            ),
+        }
+        MpdController { settings }
+    }
+
+    pub fn settings(&self) -> &MpdSettings {
+        &self.settings
+    }
+
+    /// Run commands on a fresh connection, MPD drops idle clients anyway
+    fn run<T>(&self, f: impl FnOnce(&mut Connection) -> io::Result<T>) -> io::Result<T> {
+        let mut connection = Connection::open(&self.settings)?;
+        f(&mut connection)
+    }
+
+    /// Log the outcome of a command
+    fn report(&self, name: &str, result: io::Result<()>) {
+        match result {
+            Ok(()) => info!("Successfully executed MPD {}", name),
+            Err(e) => error!("MPD {} failed: {}", name, e),
+        }
+    }
+
+    fn try_play_pause(&self) -> io::Result<()> {
+        self.run(|connection| {
+            let status = connection.command("status")?;
+            if field(&status, "state") == Some("play") {
+                connection.command("pause 1")?;
+            } else {
+                connection.command("play")?;
+            }
+            Ok(())
+        })
+    }
+
+    fn try_change_volume(&self, step: i32) -> io::Result<()> {
+        self.run(|connection| {
+            let status = connection.command("status")?;
+            let volume = field(&status, "volume")
+                .and_then(|volume| volume.parse::<i32>().ok())
+                .filter(|volume| *volume >= 0)
+                .ok_or_else(|| io::Error::other("MPD has no mixer, volume is not available"))?;
+            connection.command(&format!("setvol {}", (volume + step).clamp(0, 100)))?;
+            Ok(())
+        })
+    }
+
+    fn try_volume(&self) -> io::Result<Option<u32>> {
+        self.run(|connection| {
+            let status = connection.command("status")?;
+            // -1 means MPD has no mixer
+            Ok(field(&status, "volume").and_then(|volume| volume.parse().ok()))
+        })
+    }
+
+    fn try_simple(&self, command: &str) -> io::Result<()> {
+        self.run(|connection| connection.command(command).map(|_| ()))
+    }
+
+    fn try_now_playing(&self) -> io::Result<Option<NowPlaying>> {
+        self.run(|connection| {
+            let status = connection.command("status")?;
+            let song = connection.command("currentsong")?;
+
+            if song.is_empty() {
+                return Ok(None);
+            }
+
+            let title = field(&song, "Title")
+                .or_else(|| field(&song, "file"))
+                .unwrap_or_default();
+
+            Ok(Some(NowPlaying {
+                title: title.to_string(),
+                artist: field(&song, "Artist").unwrap_or_default().to_string(),
+                album: field(&song, "Album").unwrap_or_default().to_string(),
+                playing: field(&status, "state") == Some("play"),
+            }))
+        })
+    }
+
+    pub fn play_pause(&self) {
+        info!("MPD: Sending Play/Pause");
+        self.report("play/pause", self.try_play_pause());
+    }
+
+    pub fn next(&self) {
+        info!("MPD: Sending Next Track");
+        self.report("next", self.try_simple("next"));
+    }
+
+    pub fn previous(&self) {
+        info!("MPD: Sending Previous Track");
+        self.report("previous", self.try_simple("previous"));
+    }
+
+    pub fn volume_up(&self) {
+        info!("MPD: Sending Volume Up");
+        self.report("volume up", self.try_change_volume(VOLUME_STEP));
+    }
+
+    pub fn volume_down(&self) {
+        info!("MPD: Sending Volume Down");
+        self.report("volume down", self.try_change_volume(-VOLUME_STEP));
+    }
+
+    pub fn stop(&self) {
+        info!("MPD: Sending Stop (using pause instead)");
+        // Pause like the other backends do, so Play/Pause resumes the track
+        self.report("stop", self.try_simple("pause 1"));
+    }
+
+    pub fn set_volume(&self, percent: u32) {
+        info!("MPD: Setting volume to {}%", percent);
+        self.report("set volume", self.try_simple(&format!("setvol {}", percent.min(100))));
+    }
+
+    pub fn volume(&self) -> Option<u32> {
+        match self.try_volume() {
+            Ok(volume) => volume,
+            Err(e) => {
+                error!("MPD volume query failed: {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn now_playing(&self) -> Option<NowPlaying> {
+        match self.try_now_playing() {
+            Ok(now_playing) => now_playing,
+            Err(e) => {
+                error!("MPD now playing query failed: {}", e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    /// A tiny fake MPD server that records received commands
+    struct FakeMpd {
+        port: u16,
+        commands: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl FakeMpd {
+        fn start(password: Option<&'static str>, state: &'static str, volume: i32) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let commands = Arc::new(Mutex::new(Vec::new()));
+            let recorded = commands.clone();
+
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let Ok(stream) = stream else { break };
+                    serve(stream, &recorded, password, state, volume);
+                }
+            });
+
+            FakeMpd { port, commands }
+        }
+
+        fn settings(&self, password: Option<&str>) -> MpdSettings {
+            MpdSettings {
+                host: "127.0.0.1".to_string(),
+                port: self.port,
+                socket: None,
+                password: password.map(str::to_string),
+            }
+        }
+
+        fn commands(&self) -> Vec<String> {
+            self.commands.lock().unwrap().clone()
+        }
+    }
+
+    fn serve<S: Read + Write + TryClone>(
+        stream: S,
+        commands: &Mutex<Vec<String>>,
+        password: Option<&str>,
+        state: &str,
+        volume: i32,
+    ) {
+        let mut writer = stream.try_clone_stream();
+        let mut reader = BufReader::new(stream);
+        let mut authorized = password.is_none();
+        writer.write_all(b"OK MPD 0.23.5\n").unwrap();
+
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap_or(0) > 0 {
+            let command = line.trim_end().to_string();
+            line.clear();
+            commands.lock().unwrap().push(command.clone());
+
+            let reply = if let Some(given) = command.strip_prefix("password ") {
+                if Some(given) == password.map(quote).as_deref() {
+                    authorized = true;
+                    "OK\n".to_string()
+                } else {
+                    "ACK [3@0] {password} incorrect password\n".to_string()
+                }
+            } else if !authorized {
+                format!("ACK [4@0] {{{}}} you don't have permission\n", command)
+            } else if command == "status" {
+                format!("volume: {}\nstate: {}\nOK\n", volume, state)
+            } else if command == "currentsong" {
+                "file: music/song.flac\nArtist: Artist\nTitle: Song\nAlbum: Album\nOK\n"
+                    .to_string()
+            } else {
+                "OK\n".to_string()
+            };
+            writer.write_all(reply.as_bytes()).unwrap();
+        }
+    }
+
+    /// Stream types that can be split into a reader and a writer
+    trait TryClone: Sized {
+        fn try_clone_stream(&self) -> Self;
+    }
+
+    impl TryClone for TcpStream {
+        fn try_clone_stream(&self) -> Self {
+            self.try_clone().unwrap()
+        }
+    }
+
+    #[cfg(unix)]
+    impl TryClone for UnixStream {
+        fn try_clone_stream(&self) -> Self {
+            self.try_clone().unwrap()
+        }
+    }
+
+    #[test]
+    fn play_pause_pauses_when_playing() {
+        let server = FakeMpd::start(None, "play", 50);
+        let controller = MpdController::new(server.settings(None));
+
+        controller.try_play_pause().unwrap();
+
+        assert_eq!(server.commands(), vec!["status", "pause 1"]);
+    }
+
+    #[test]
+    fn play_pause_plays_when_paused() {
+        let server = FakeMpd::start(None, "pause", 50);
+        let controller = MpdController::new(server.settings(None));
+
+        controller.try_play_pause().unwrap();
+
+        assert_eq!(server.commands(), vec!["status", "play"]);
+    }
+
+    #[test]
+    fn simple_commands() {
+        let server = FakeMpd::start(None, "play", 50);
+        let controller = MpdController::new(server.settings(None));
+
+        controller.try_simple("next").unwrap();
+        controller.try_simple("previous").unwrap();
+
+        assert_eq!(server.commands(), vec!["next", "previous"]);
+    }
+
+    #[test]
+    fn volume_is_clamped() {
+        let server = FakeMpd::start(None, "play", 98);
+        let controller = MpdController::new(server.settings(None));
+
+        controller.try_change_volume(VOLUME_STEP).unwrap();
+        controller.try_change_volume(-VOLUME_STEP).unwrap();
+
+        assert_eq!(
+            server.commands(),
+            vec!["status", "setvol 100", "status", "setvol 93"]
+        );
+    }
+
+    #[test]
+    fn volume_without_mixer_fails() {
+        let server = FakeMpd::start(None, "play", -1);
+        let controller = MpdController::new(server.settings(None));
+
+        assert!(controller.try_change_volume(VOLUME_STEP).is_err());
+        assert_eq!(server.commands(), vec!["status"]);
+    }
+
+    #[test]
+    fn password_is_sent_first() {
+        let server = FakeMpd::start(Some("se\"cret"), "play", 50);
+        let controller = MpdController::new(server.settings(Some("se\"cret")));
+
+        controller.try_simple("next").unwrap();
+
+        assert_eq!(server.commands(), vec!["password \"se\\\"cret\"", "next"]);
+    }
+
+    #[test]
+    fn wrong_password_is_an_error() {
+        let server = FakeMpd::start(Some("secret"), "play", 50);
+        let controller = MpdController::new(server.settings(Some("wrong")));
+
+        let error = controller.try_simple("next").err().unwrap();
+
+        assert!(error.to_string().contains("incorrect password"));
+        assert_eq!(server.commands(), vec!["password \"wrong\""]);
+    }
+
+    #[test]
+    fn now_playing_is_parsed() {
+        let server = FakeMpd::start(None, "play", 50);
+        let controller = MpdController::new(server.settings(None));
+
+        let now_playing = controller.try_now_playing().unwrap().unwrap();
+
+        assert_eq!(
+            now_playing,
+            NowPlaying {
+                title: "Song".to_string(),
+                artist: "Artist".to_string(),
+                album: "Album".to_string(),
+                playing: true,
+            }
+        );
+    }
+
+    #[test]
+    fn unreachable_server_is_an_error() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let controller = MpdController::new(MpdSettings {
+            host: "127.0.0.1".to_string(),
+            port,
+            socket: None,
+            password: None,
+        });
+
+        assert!(controller.try_simple("next").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_socket() {
+        use std::os::unix::net::UnixListener;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mpd.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        let commands = Arc::new(Mutex::new(Vec::new()));
+        let recorded = commands.clone();
+
+        thread::spawn(move || {
+            if let Ok((stream, _)) = listener.accept() {
+                serve(stream, &recorded, None, "pause", 50);
+            }
+        });
+
+        let controller = MpdController::new(MpdSettings {
+            host: String::new(),
+            port: 0,
+            socket: Some(path.clone()),
+            password: None,
+        });
+        controller.try_play_pause().unwrap();
+
+        assert_eq!(*commands.lock().unwrap(), vec!["status", "play"]);
+    }
+}
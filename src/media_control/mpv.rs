@@ -0,0 +1,515 @@
+use crate::config::MpvSettings;
+use log::{error, info, warn};
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+
+/// mpv listens on a Unix socket, and on a named pipe on Windows
+#[cfg(unix)]
+type Stream = UnixStream;
+#[cfg(not(unix))]
+type Stream = std::fs::File;
+
+use super::NowPlaying;
+
+/// Volume change per VolumeUp/VolumeDown, in percent
+const VOLUME_STEP: i64 = 5;
+
+/// Playback speed factor per SpeedUp/SpeedDown, same as mpv's `[` and `]` keys
+const SPEED_FACTOR: f64 = 1.1;
+
+/// How long to wait for mpv before giving up on a command
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A connection to mpv's JSON IPC socket (`--input-ipc-server`)
+struct Connection {
+    replies: Replies,
+    writer: Stream,
+    next_request_id: u64,
+}
+
+#[cfg(unix)]
+fn connect_stream(path: &Path) -> io::Result<Stream> {
+    let stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    Ok(stream)
+}
+
+/// Named pipes are opened like files, e.g. `\\.\pipe\mpvsocket`
+#[cfg(not(unix))]
+fn connect_stream(path: &Path) -> io::Result<Stream> {
+    std::fs::OpenOptions::new().read(true).write(true).open(path)
+}
+
+/// Lines sent by mpv, read with the socket's timeout
+#[cfg(unix)]
+struct Replies(BufReader<Stream>);
+
+#[cfg(unix)]
+impl Replies {
+    fn new(stream: &Stream) -> io::Result<Self> {
+        Ok(Replies(BufReader::new(stream.try_clone()?)))
+    }
+
+    /// The next line, empty when mpv has closed the connection
+    fn next_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        self.0.read_line(&mut line)?;
+        Ok(line)
+    }
+}
+
+/// Lines sent by mpv. Named pipes have no timeouts, so they are read on a
+/// thread and waited for with one.
+#[cfg(not(unix))]
+struct Replies {
+    lines: crossbeam_channel::Receiver<io::Result<String>>,
+    pipe: Stream,
+}
+
+#[cfg(not(unix))]
+impl Replies {
+    fn new(stream: &Stream) -> io::Result<Self> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let (sender, lines) = crossbeam_channel::unbounded();
+        std::thread::spawn(move || loop {
+            let mut line = String::new();
+            let result = reader.read_line(&mut line).map(|_| line);
+            let ended = !matches!(&result, Ok(line) if !line.is_empty());
+            if sender.send(result).is_err() || ended {
+                return;
+            }
+        });
+
+        Ok(Replies {
+            lines,
+            pipe: stream.try_clone()?,
+        })
+    }
+
+    /// The next line, empty when mpv has closed the connection
+    fn next_line(&mut self) -> io::Result<String> {
+        match self.lines.recv_timeout(TIMEOUT) {
+            Ok(line) => line,
+            Err(crossbeam_channel::RecvTimeoutError::Timeout) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "mpv didn't reply in time",
+            )),
+            Err(crossbeam_channel::RecvTimeoutError::Disconnected) => Ok(String::new()),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+impl Drop for Replies {
+    /// Stop the thread still waiting for a line
+    fn drop(&mut self) {
+        use std::os::windows::io::AsRawHandle;
+        use windows::Win32::Foundation::HANDLE;
+        use windows::Win32::System::IO::CancelIoEx;
+
+        unsafe {
+            let _ = CancelIoEx(HANDLE(self.pipe.as_raw_handle() as isize), None);
+        }
+    }
+}
+
+impl Connection {
+    fn open(path: &Path) -> io::Result<Self> {
+        let stream = connect_stream(path)?;
+
+        Ok(Connection {
+            replies: Replies::new(&stream)?,
+            writer: stream,
+            next_request_id: 1,
+        })
+    }
+
+    /// Send a command and wait for its reply, skipping event notifications
+    fn command(&mut self, command: Value) -> io::Result<Value> {
+        let request_id = self.next_request_id;
+        self.next_request_id += 1;
+
+        let request = json!({ "command": command, "request_id": request_id });
+        self.writer.write_all(request.to_string().as_bytes())?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+
+        loop {
+            let line = self.replies.next_line()?;
+            if line.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "mpv closed the connection",
+                ));
+            }
+
+            let reply: Value = serde_json::from_str(&line)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+            if reply.get("request_id").and_then(Value::as_u64) != Some(request_id) {
+                // Asynchronous event, not our reply
+                continue;
+            }
+
+            return match reply.get("error").and_then(Value::as_str) {
+                Some("success") => Ok(reply.get("data").cloned().unwrap_or(Value::Null)),
+                Some(e) => Err(io::Error::other(format!("mpv error: {}", e))),
+                None => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "mpv reply without error status",
+                )),
+            };
+        }
+    }
+}
+
+/// Sockets in a directory, most recently created first
+#[cfg(unix)]
+fn sockets_in(dir: &Path) -> Vec<PathBuf> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Failed to read mpv socket directory {}: {}", dir.display(), e);
+            return Vec::new();
+        }
+    };
+
+    let mut sockets: Vec<_> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            if !metadata.file_type().is_socket() {
+                return None;
+            }
+            Some((metadata.modified().ok(), entry.path()))
+        })
+        .collect();
+
+    sockets.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    sockets.into_iter().map(|(_, path)| path).collect()
+}
+
+/// Only Unix sockets are discovered
+#[cfg(not(unix))]
+fn sockets_in(dir: &Path) -> Vec<PathBuf> {
+    warn!(
+        "mpv socket directories are only searched on Unix, ignoring {}",
+        dir.display()
+    );
+    Vec::new()
+}
+
+/// Media controller for mpv
+pub struct MpvController {
+    settings: MpvSettings,
+}
+
+impl MpvController {
+    pub fn new(settings: MpvSettings) -> Self {
+        info!("mpv media controller initialized");
+        MpvController { settings }
+    }
+
+    pub fn settings(&self) -> &MpvSettings {
+        &self.settings
+    }
+
+    /// Connect to the configured socket, or discover a running mpv.
+    /// Sockets left behind by mpv instances that have exited are skipped.
+    fn connect(&self) -> io::Result<Connection> {
+        let mut candidates = Vec::new();
+        if let Some(socket) = &self.settings.socket {
+            candidates.push(socket.clone());
+        }
+        if let Some(dir) = &self.settings.socket_dir {
+            candidates.extend(sockets_in(dir));
+        }
+
+        for path in &candidates {
+            match Connection::open(path) {
+                Ok(connection) => return Ok(connection),
+                Err(e) => info!("mpv socket {} is not usable: {}", path.display(), e),
+            }
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "No running mpv found, start it with --input-ipc-server",
+        ))
+    }
+
+    /// Send a single command on a fresh connection
+    fn send(&self, command: Value) -> io::Result<Value> {
+        self.connect()?.command(command)
+    }
+
+    /// Send a command and log the outcome
+    fn execute(&self, name: &str, command: Value) {
+        match self.send(command) {
+            Ok(_) => info!("Successfully executed mpv {}", name),
+            Err(e) => error!("mpv {} failed: {}", name, e),
+        }
+    }
+
+    fn try_now_playing(&self) -> io::Result<Option<NowPlaying>> {
+        let mut connection = self.connect()?;
+
+        // Properties are unavailable while nothing is loaded
+        let Ok(title) = connection.command(json!(["get_property", "media-title"])) else {
+            return Ok(None);
+        };
+        let metadata = connection
+            .command(json!(["get_property", "metadata"]))
+            .unwrap_or(Value::Null);
+        let paused = connection.command(json!(["get_property", "pause"]))?;
+
+        let tag = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| metadata.get(*name).and_then(Value::as_str))
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        Ok(Some(NowPlaying {
+            title: title.as_str().unwrap_or_default().to_string(),
+            artist: tag(&["artist", "ARTIST", "Artist"]),
+            album: tag(&["album", "ALBUM", "Album"]),
+            playing: paused == Value::Bool(false),
+        }))
+    }
+
+    pub fn play_pause(&self) {
+        info!("mpv: Sending Play/Pause");
+        self.execute("play/pause", json!(["cycle", "pause"]));
+    }
+
+    pub fn next(&self) {
+        info!("mpv: Sending Next Track");
+        self.execute("next", json!(["playlist-next"]));
+    }
+
+    pub fn previous(&self) {
+        info!("mpv: Sending Previous Track");
+        self.execute("previous", json!(["playlist-prev"]));
+    }
+
+    pub fn volume_up(&self) {
+        info!("mpv: Sending Volume Up");
+        self.execute("volume up", json!(["add", "volume", VOLUME_STEP]));
+    }
+
+    pub fn volume_down(&self) {
+        info!("mpv: Sending Volume Down");
+        self.execute("volume down", json!(["add", "volume", -VOLUME_STEP]));
+    }
+
+    pub fn stop(&self) {
+        info!("mpv: Sending Stop (using pause instead)");
+        // Pause like the other backends do, mpv quits on "stop" with an empty playlist
+        self.execute("stop", json!(["set_property", "pause", true]));
+    }
+
+    pub fn seek(&self, seconds: f64) {
+        info!("mpv: Seeking {:+} seconds", seconds);
+        self.execute("seek", json!(["seek", seconds, "relative"]));
+    }
+
+    pub fn speed_up(&self) {
+        info!("mpv: Speeding up");
+        self.execute("speed up", json!(["multiply", "speed", SPEED_FACTOR]));
+    }
+
+    pub fn speed_down(&self) {
+        info!("mpv: Slowing down");
+        self.execute("speed down", json!(["multiply", "speed", 1.0 / SPEED_FACTOR]));
+    }
+
+    pub fn chapter_next(&self) {
+        info!("mpv: Next Chapter");
+        self.execute("next chapter", json!(["add", "chapter", 1]));
+    }
+
+    pub fn chapter_previous(&self) {
+        info!("mpv: Previous Chapter");
+        self.execute("previous chapter", json!(["add", "chapter", -1]));
+    }
+
+    pub fn toggle_subtitles(&self) {
+        info!("mpv: Toggling subtitles");
+        self.execute("toggle subtitles", json!(["cycle", "sub-visibility"]));
+    }
+
+    pub fn set_volume(&self, percent: u32) {
+        info!("mpv: Setting volume to {}%", percent);
+        self.execute("set volume", json!(["set_property", "volume", percent]));
+    }
+
+    pub fn volume(&self) -> Option<u32> {
+        match self.send(json!(["get_property", "volume"])) {
+            Ok(volume) => volume.as_f64().map(|volume| volume.round() as u32),
+            Err(e) => {
+                error!("mpv volume query failed: {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn now_playing(&self) -> Option<NowPlaying> {
+        match self.try_now_playing() {
+            Ok(now_playing) => now_playing,
+            Err(e) => {
+                error!("mpv now playing query failed: {}", e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    /// A fake mpv IPC server that records received commands
+    struct FakeMpv {
+        commands: Arc<Mutex<Vec<Value>>>,
+    }
+
+    impl FakeMpv {
+        fn start(path: &Path, paused: bool) -> Self {
+            let listener = UnixListener::bind(path).unwrap();
+            let commands = Arc::new(Mutex::new(Vec::new()));
+            let recorded = commands.clone();
+
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let Ok(stream) = stream else { break };
+                    serve(stream, &recorded, paused);
+                }
+            });
+
+            FakeMpv { commands }
+        }
+
+        fn commands(&self) -> Vec<Value> {
+            self.commands.lock().unwrap().clone()
+        }
+    }
+
+    fn serve(stream: UnixStream, commands: &Mutex<Vec<Value>>, paused: bool) {
+        let mut writer = stream.try_clone().unwrap();
+        let mut reader = BufReader::new(stream);
+
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap_or(0) > 0 {
+            let request: Value = serde_json::from_str(&line).unwrap();
+            line.clear();
+            let command = request["command"].clone();
+            commands.lock().unwrap().push(command.clone());
+
+            let data = match command[1].as_str() {
+                Some("media-title") => json!("Song"),
+                Some("metadata") => json!({ "ARTIST": "Artist", "album": "Album" }),
+                Some("pause") => json!(paused),
+                _ => Value::Null,
+            };
+
+            // Interleave an event like mpv does, it must be skipped
+            let event = json!({ "event": "property-change" });
+            let reply = json!({ "error": "success", "data": data, "request_id": request["request_id"] });
+            writeln!(writer, "{}\n{}", event, reply).unwrap();
+        }
+    }
+
+    fn settings(socket: Option<PathBuf>, socket_dir: Option<PathBuf>) -> MpvSettings {
+        MpvSettings { socket, socket_dir }
+    }
+
+    #[test]
+    fn commands_are_sent() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mpv.sock");
+        let server = FakeMpv::start(&path, false);
+        let controller = MpvController::new(settings(Some(path), None));
+
+        controller.send(json!(["cycle", "pause"])).unwrap();
+        controller.send(json!(["seek", -10.0, "relative"])).unwrap();
+
+        assert_eq!(
+            server.commands(),
+            vec![json!(["cycle", "pause"]), json!(["seek", -10.0, "relative"])]
+        );
+    }
+
+    #[test]
+    fn now_playing_is_parsed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mpv.sock");
+        let _server = FakeMpv::start(&path, false);
+        let controller = MpvController::new(settings(Some(path), None));
+
+        let now_playing = controller.try_now_playing().unwrap().unwrap();
+
+        assert_eq!(
+            now_playing,
+            NowPlaying {
+                title: "Song".to_string(),
+                artist: "Artist".to_string(),
+                album: "Album".to_string(),
+                playing: true,
+            }
+        );
+    }
+
+    #[test]
+    fn discovery_skips_stale_sockets() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // A socket file without a server behind it
+        drop(UnixListener::bind(dir.path().join("stale.sock")).unwrap());
+        let server = FakeMpv::start(&dir.path().join("live.sock"), true);
+
+        let controller = MpvController::new(settings(None, Some(dir.path().to_path_buf())));
+        controller.send(json!(["playlist-next"])).unwrap();
+
+        assert_eq!(server.commands(), vec![json!(["playlist-next"])]);
+    }
+
+    #[test]
+    fn explicit_socket_is_preferred() {
+        let dir = tempfile::tempdir().unwrap();
+        let discovered = FakeMpv::start(&dir.path().join("discovered.sock"), true);
+        let other_dir = tempfile::tempdir().unwrap();
+        let explicit_path = other_dir.path().join("explicit.sock");
+        let explicit = FakeMpv::start(&explicit_path, true);
+
+        let controller = MpvController::new(settings(Some(explicit_path), Some(dir.path().to_path_buf())));
+        controller.send(json!(["playlist-prev"])).unwrap();
+
+        assert_eq!(explicit.commands(), vec![json!(["playlist-prev"])]);
+        assert!(discovered.commands().is_empty());
+    }
+
+    #[test]
+    fn no_running_mpv_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing.sock");
+        let controller =
+            MpvController::new(settings(Some(missing), Some(dir.path().to_path_buf())));
+
+        let error = controller.send(json!(["cycle", "pause"])).err().unwrap();
+
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,458 @@
+use log::{error, info, warn};
+use std::io;
+use std::process::Command;
+
+use super::{AppStream, OutputDevice};
+
+/// A playback stream ("sink input") as reported by the PulseAudio server
+#[derive(Debug, Clone)]
+pub struct SinkInput {
+    pub index: u32,
+    pub app_name: String,
+    pub binary: String,
+    pub media_name: String,
+    pub muted: bool,
+}
+
+impl SinkInput {
+    /// Check whether this stream belongs to the given application
+    fn matches(&self, app: &str) -> bool {
+        self.app_name.eq_ignore_ascii_case(app) || self.binary.eq_ignore_ascii_case(app)
+    }
+}
+
+/// Shown when pactl is not installed
+const MISSING: &str = "pactl is not installed, install it with: sudo apt install pulseaudio-utils";
+
+/// Why pactl can't be executed, if it can't
+pub fn unavailable() -> Option<String> {
+    match Command::new("pactl").arg("--version").output() {
+        Ok(_) => None,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Some(MISSING.to_string()),
+        Err(e) => Some(format!("pactl can't be executed: {}", e)),
+    }
+}
+
+/// Execute pactl command and return its stdout on success
+///
+/// pactl talks to the server over the native PulseAudio protocol, so this
+/// works the same with PipeWire's pulse compatibility layer.
+fn execute_pactl(args: &[&str]) -> Option<String> {
+    // Force the C locale, the list output is parsed below
+    match Command::new("pactl").env("LC_ALL", "C").args(args).output() {
+        Ok(output) => {
+            if output.status.success() {
+                Some(String::from_utf8_lossy(&output.stdout).into_owned())
+            } else {
+                error!(
+                    "pactl {} failed: {}",
+                    args.join(" "),
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+                None
+            }
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            error!("{}", MISSING);
+            None
+        }
+        Err(e) => {
+            error!("Failed to execute pactl: {}", e);
+            None
+        }
+    }
+}
+
+/// Parse the output of `pactl list sink-inputs`
+fn parse_sink_inputs(output: &str) -> Vec<SinkInput> {
+    let mut inputs = Vec::new();
+    let mut current: Option<SinkInput> = None;
+
+    for line in output.lines() {
+        let trimmed = line.trim();
+
+        if let Some(index) = trimmed.strip_prefix("Sink Input #") {
+            if let Some(input) = current.take() {
+                inputs.push(input);
+            }
+            current = index.parse().ok().map(|index| SinkInput {
+                index,
+                app_name: String::new(),
+                binary: String::new(),
+                media_name: String::new(),
+                muted: false,
+            });
+            continue;
+        }
+
+        let Some(input) = current.as_mut() else {
+            continue;
+        };
+
+        if let Some(mute) = trimmed.strip_prefix("Mute:") {
+            input.muted = mute.trim() == "yes";
+        } else if let Some((name, value)) = trimmed.split_once(" = ") {
+            let value = value.trim_matches('"').to_string();
+            match name {
+                "application.name" => input.app_name = value,
+                "application.process.binary" => input.binary = value,
+                "media.name" => input.media_name = value,
+                _ => {}
+            }
+        }
+    }
+
+    if let Some(input) = current {
+        inputs.push(input);
+    }
+
+    inputs
+}
+
+/// List all playback streams
+pub fn list_sink_inputs() -> Vec<SinkInput> {
+    execute_pactl(&["list", "sink-inputs"])
+        .map(|output| parse_sink_inputs(&output))
+        .unwrap_or_default()
+}
+
+/// Find the streams that belong to the given application
+fn find_app_streams(app: &str) -> Vec<SinkInput> {
+    let streams: Vec<_> = list_sink_inputs()
+        .into_iter()
+        .filter(|input| input.matches(app))
+        .collect();
+
+    if streams.is_empty() {
+        info!("No playing streams found for application '{}'", app);
+    }
+
+    streams
+}
+
+/// Change the volume of all streams of an application by `step` percent
+pub fn change_app_volume(app: &str, step: i32) {
+    let volume = format!("{:+}%", step);
+
+    for input in find_app_streams(app) {
+        let index = input.index.to_string();
+        if execute_pactl(&["set-sink-input-volume", &index, &volume]).is_some() {
+            info!("Changed volume of '{}' (#{}) by {}", app, input.index, volume);
+        }
+    }
+}
+
+/// Toggle mute of all streams of an application
+pub fn toggle_app_mute(app: &str) {
+    let streams = find_app_streams(app);
+
+    // Mute everything unless all streams are already muted, so that
+    // several streams of one application don't end up out of sync
+    let mute = if streams.iter().all(|input| input.muted) { "0" } else { "1" };
+
+    for input in streams {
+        let index = input.index.to_string();
+        if execute_pactl(&["set-sink-input-mute", &index, mute]).is_some() {
+            info!("Set mute of '{}' (#{}) to {}", app, input.index, mute);
+        }
+    }
+}
+
+/// Streams that are currently playing, for the UI picker
+pub fn app_streams() -> Vec<AppStream> {
+    let mut streams: Vec<AppStream> = Vec::new();
+
+    for input in list_sink_inputs() {
+        let name = if input.app_name.is_empty() {
+            input.binary.clone()
+        } else {
+            input.app_name.clone()
+        };
+
+        if name.is_empty() {
+            warn!("Skipping sink input #{} without application name", input.index);
+            continue;
+        }
+
+        if !streams.iter().any(|stream| stream.name == name) {
+            streams.push(AppStream {
+                name,
+                binary: input.binary,
+                media_name: input.media_name,
+            });
+        }
+    }
+
+    streams
+}
+
+/// Parse the output of `pactl list sinks`
+fn parse_sinks(output: &str) -> Vec<OutputDevice> {
+    let mut sinks = Vec::new();
+    let mut current: Option<OutputDevice> = None;
+
+    for line in output.lines() {
+        let trimmed = line.trim();
+
+        if trimmed.starts_with("Sink #") {
+            if let Some(sink) = current.take() {
+                sinks.push(sink);
+            }
+            current = Some(OutputDevice {
+                name: String::new(),
+                description: String::new(),
+            });
+            continue;
+        }
+
+        let Some(sink) = current.as_mut() else {
+            continue;
+        };
+
+        if let Some(name) = trimmed.strip_prefix("Name:") {
+            sink.name = name.trim().to_string();
+        } else if let Some(description) = trimmed.strip_prefix("Description:") {
+            sink.description = description.trim().to_string();
+        }
+    }
+
+    if let Some(sink) = current {
+        sinks.push(sink);
+    }
+
+    sinks.retain(|sink| !sink.name.is_empty());
+    sinks
+}
+
+/// List all output devices
+pub fn output_devices() -> Vec<OutputDevice> {
+    execute_pactl(&["list", "sinks"])
+        .map(|output| parse_sinks(&output))
+        .unwrap_or_default()
+}
+
+/// Default output device in the output of `pactl info`
+fn parse_default_sink(output: &str) -> Option<String> {
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix("Default Sink:"))
+        .map(|name| name.trim().to_string())
+}
+
+/// Name of the current default output device
+pub fn default_sink() -> Option<String> {
+    parse_default_sink(&execute_pactl(&["info"])?)
+}
+
+/// Make the device the default output, optionally moving playing streams to it
+pub fn set_default_sink(name: &str, move_streams: bool) {
+    if execute_pactl(&["set-default-sink", name]).is_none() {
+        return;
+    }
+    info!("Default output device set to '{}'", name);
+
+    if move_streams {
+        for input in list_sink_inputs() {
+            let index = input.index.to_string();
+            if execute_pactl(&["move-sink-input", &index, name]).is_some() {
+                info!("Moved stream #{} to '{}'", input.index, name);
+            }
+        }
+    }
+}
+
+/// The device of `available` that follows `current` in `order`.
+/// An empty order cycles through all available devices.
+fn next_sink<'a>(
+    available: &'a [String],
+    order: &'a [String],
+    current: Option<&str>,
+) -> Option<&'a String> {
+    // Devices from the configured order that are currently connected
+    let candidates: Vec<&String> = if order.is_empty() {
+        available.iter().collect()
+    } else {
+        order.iter().filter(|name| available.contains(name)).collect()
+    };
+
+    let next = match candidates
+        .iter()
+        .position(|name| Some(name.as_str()) == current)
+    {
+        Some(position) => candidates[(position + 1) % candidates.len()],
+        None => candidates.first()?,
+    };
+    Some(next)
+}
+
+/// Switch to the device that follows the current default in `order`.
+/// An empty order cycles through all available devices.
+pub fn cycle_default_sink(order: &[String], move_streams: bool) {
+    let available: Vec<String> = output_devices().into_iter().map(|sink| sink.name).collect();
+
+    match next_sink(&available, order, default_sink().as_deref()) {
+        Some(next) => set_default_sink(next, move_streams),
+        None => warn!("No output devices available to cycle through"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `LC_ALL=C pactl list sink-inputs` with PipeWire's pulse server
+    const SINK_INPUTS: &str = r#"Sink Input #71
+	Driver: PipeWire
+	Owner Module: n/a
+	Client: 70
+	Sink: 56
+	Sample Specification: float32le 2ch 48000Hz
+	Channel Map: front-left,front-right
+	Format: pcm, format.sample_format = "\"float32le\""  format.rate = "48000"  format.channels = "2"  format.channel_map = "\"front-left,front-right\""
+	Corked: no
+	Mute: no
+	Volume: front-left: 65536 / 100% / 0.00 dB,   front-right: 65536 / 100% / 0.00 dB
+	        balance 0.00
+	Buffer Latency: 0 usec
+	Sink Latency: 0 usec
+	Resample method: PipeWire
+	Properties:
+		client.api = "pipewire-pulse"
+		application.name = "Firefox"
+		application.process.binary = "firefox"
+		media.name = "Never Gonna Give You Up - YouTube"
+		node.rate = "1/48000"
+
+Sink Input #84
+	Driver: PipeWire
+	Owner Module: n/a
+	Client: 83
+	Sink: 56
+	Corked: no
+	Mute: yes
+	Volume: mono: 42597 /  65% / -11.23 dB
+	        balance 0.00
+	Properties:
+		application.name = "mpv Media Player"
+		application.process.binary = "mpv"
+		media.name = "Song = Title.flac"
+"#;
+
+    #[test]
+    fn parses_sink_inputs() {
+        let inputs = parse_sink_inputs(SINK_INPUTS);
+        assert_eq!(inputs.len(), 2);
+
+        assert_eq!(inputs[0].index, 71);
+        assert_eq!(inputs[0].app_name, "Firefox");
+        assert_eq!(inputs[0].binary, "firefox");
+        assert_eq!(inputs[0].media_name, "Never Gonna Give You Up - YouTube");
+        assert!(!inputs[0].muted);
+
+        assert_eq!(inputs[1].index, 84);
+        assert_eq!(inputs[1].media_name, "Song = Title.flac");
+        assert!(inputs[1].muted);
+        assert!(inputs[1].matches("MPV"));
+        assert!(inputs[1].matches("mpv media player"));
+        assert!(!inputs[1].matches("firefox"));
+    }
+
+    /// `LC_ALL=C pactl list sinks`, shortened
+    const SINKS: &str = r#"Sink #56
+	State: RUNNING
+	Name: alsa_output.pci-0000_00_1f.3.analog-stereo
+	Description: Built-in Audio Analog Stereo
+	Driver: PipeWire
+	Sample Specification: s32le 2ch 48000Hz
+	Properties:
+		alsa.name = "ALC257 Analog"
+		device.description = "Built-in Audio Analog Stereo"
+	Ports:
+		analog-output-speaker: Speakers (type: Speaker, priority: 10000, availability unknown)
+	Active Port: analog-output-speaker
+
+Sink #91
+	State: SUSPENDED
+	Name: bluez_output.00_1B_66_A1_2C_3D.1
+	Description: WH-1000XM4
+	Driver: PipeWire
+	Properties:
+		device.description = "WH-1000XM4"
+"#;
+
+    /// `LC_ALL=C pactl info`
+    const INFO: &str = "\
+Server String: /run/user/1000/pulse/native
+Library Protocol Version: 35
+Server Protocol Version: 35
+Is Local: yes
+Client Index: 112
+Tile Size: 65472
+User Name: alice
+Host Name: laptop
+Server Name: PulseAudio (on PipeWire 1.0.5)
+Server Version: 15.0.0
+Default Sample Specification: float32le 2ch 48000Hz
+Default Channel Map: front-left,front-right
+Default Sink: bluez_output.00_1B_66_A1_2C_3D.1
+Default Source: alsa_input.pci-0000_00_1f.3.analog-stereo
+Cookie: 8d3a:5b1e
+";
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_sinks_and_the_default() {
+        let sinks = parse_sinks(SINKS);
+        let parsed: Vec<_> = sinks
+            .iter()
+            .map(|sink| (sink.name.as_str(), sink.description.as_str()))
+            .collect();
+        assert_eq!(
+            parsed,
+            [
+                (
+                    "alsa_output.pci-0000_00_1f.3.analog-stereo",
+                    "Built-in Audio Analog Stereo"
+                ),
+                ("bluez_output.00_1B_66_A1_2C_3D.1", "WH-1000XM4"),
+            ]
+        );
+
+        assert_eq!(
+            parse_default_sink(INFO).as_deref(),
+            Some("bluez_output.00_1B_66_A1_2C_3D.1")
+        );
+        assert_eq!(parse_default_sink("Server Name: pulseaudio\n"), None);
+    }
+
+    #[test]
+    fn cycles_through_connected_devices() {
+        let available = names(&["speakers", "headphones", "hdmi"]);
+
+        // All devices when no order is configured
+        let next = |current| next_sink(&available, &[], current).map(String::as_str);
+        assert_eq!(next(Some("speakers")), Some("headphones"));
+        assert_eq!(next(Some("hdmi")), Some("speakers"));
+        assert_eq!(next(None), Some("speakers"));
+
+        // The configured order, skipping devices that aren't connected
+        let order = names(&["hdmi", "usb-dac", "speakers"]);
+        let next = |current| next_sink(&available, &order, current).map(String::as_str);
+        assert_eq!(next(Some("hdmi")), Some("speakers"));
+        assert_eq!(next(Some("speakers")), Some("hdmi"));
+        // The current device isn't in the order
+        assert_eq!(next(Some("headphones")), Some("hdmi"));
+
+        assert_eq!(next_sink(&[], &order, Some("hdmi")), None);
+    }
+
+    #[test]
+    fn ignores_output_without_streams() {
+        assert!(parse_sink_inputs("").is_empty());
+        // Lines before the first stream, e.g. a warning of pactl
+        assert!(parse_sink_inputs("Mute: yes\n\tapplication.name = \"x\"\n").is_empty());
+    }
+}
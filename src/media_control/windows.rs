@@ -1,4 +1,5 @@
-use log::{info, error};
+use log::{info, warn};
+use super::{AppStream, NowPlaying, OutputDevice};
 use windows::Win32::UI::Input::KeyboardAndMouse::{
     keybd_event, KEYBD_EVENT_FLAGS, KEYEVENTF_EXTENDEDKEY, KEYEVENTF_KEYUP,
 };
@@ -74,4 +75,44 @@ impl WindowsMediaController {
         // Many players (especially browser-based) don't respond to Play after Stop
         self.send_media_key(VK_MEDIA_PLAY_PAUSE);
     }
+
+    pub fn set_volume(&self, percent: u32) {
+        warn!("Windows: Setting the volume to {}% is not supported yet", percent);
+    }
+
+    /// Media keys give no feedback about the player state
+    pub fn volume(&self) -> Option<u32> {
+        None
+    }
+
+    /// Media keys give no feedback about the player state
+    pub fn now_playing(&self) -> Option<NowPlaying> {
+        None
+    }
+
+    pub fn app_volume(&self, app: &str, _step: i32) {
+        warn!("Windows: Per-application volume is not supported yet (app '{}')", app);
+    }
+
+    pub fn app_mute(&self, app: &str) {
+        warn!("Windows: Per-application mute is not supported yet (app '{}')", app);
+    }
+
+    pub fn cycle_output_device(&self, _order: &[String], _move_streams: bool) {
+        warn!("Windows: Output device switching is not supported yet");
+    }
+
+    pub fn set_output_device(&self, name: &str, _move_streams: bool) {
+        warn!("Windows: Output device switching is not supported yet (device '{}')", name);
+    }
+}
+
+/// Output devices are not enumerated on Windows yet
+pub fn output_devices() -> Vec<OutputDevice> {
+    Vec::new()
+}
+
+/// Per-application streams are not enumerated on Windows yet
+pub fn app_streams() -> Vec<AppStream> {
+    Vec::new()
 }
@@ -0,0 +1,255 @@
+use crate::config::{Schedule, Weekday};
+use jiff::civil::DateTime;
+
+/// Parsed cron expression, one bit per allowed value of each field
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronExpr {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    /// 0 = Sunday ... 6 = Saturday
+    weekdays: u64,
+    /// Whether day of month / day of week were `*`
+    any_day: bool,
+    any_weekday: bool,
+}
+
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+const WEEKDAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+impl CronExpr {
+    /// Parse a five-field expression: minute hour day-of-month month day-of-week.
+    /// Fields accept `*`, numbers, names (`jan`, `mon`), ranges, lists and steps.
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!(
+                "expected 5 fields (minute hour day month weekday), got {}",
+                fields.len()
+            ));
+        };
+
+        let mut weekdays =
+            parse_field(weekday, 0, 7, &WEEKDAY_NAMES, 0).map_err(|e| format!("weekday: {}", e))?;
+        // Both 0 and 7 mean Sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+
+        Ok(CronExpr {
+            minutes: parse_field(minute, 0, 59, &[], 0).map_err(|e| format!("minute: {}", e))?,
+            hours: parse_field(hour, 0, 23, &[], 0).map_err(|e| format!("hour: {}", e))?,
+            days: parse_field(day, 1, 31, &[], 0).map_err(|e| format!("day: {}", e))?,
+            months: parse_field(month, 1, 12, &MONTH_NAMES, 1)
+                .map_err(|e| format!("month: {}", e))?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    /// Every day at `time` ("HH:MM"), limited to `days` unless it is empty
+    pub fn daily(time: &str, days: &[Weekday]) -> Result<Self, String> {
+        let (hour, minute) = time
+            .split_once(':')
+            .and_then(|(hour, minute)| {
+                Some((
+                    hour.trim().parse::<u32>().ok()?,
+                    minute.trim().parse::<u32>().ok()?,
+                ))
+            })
+            .filter(|&(hour, minute)| hour < 24 && minute < 60)
+            .ok_or_else(|| format!("invalid time '{}', expected HH:MM", time))?;
+
+        let weekdays = if days.is_empty() {
+            0b111_1111
+        } else {
+            days.iter()
+                .map(|day| 1 << ((Weekday::ALL.iter().position(|d| d == day).unwrap() + 1) % 7))
+                .fold(0, |mask, bit| mask | bit)
+        };
+
+        Ok(CronExpr {
+            minutes: 1 << minute,
+            hours: 1 << hour,
+            days: all(1, 31),
+            months: all(1, 12),
+            weekdays,
+            any_day: true,
+            any_weekday: days.is_empty(),
+        })
+    }
+
+    /// Whether the expression fires in the minute of `time`
+    pub fn matches(&self, time: DateTime) -> bool {
+        let bit = |mask: u64, value: i64| mask & (1 << value) != 0;
+
+        let day = bit(self.days, time.day().into());
+        let weekday = bit(self.weekdays, time.weekday().to_sunday_zero_offset().into());
+
+        // Like cron: if both day fields are restricted, either one may match
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+
+        day_matches
+            && bit(self.minutes, time.minute().into())
+            && bit(self.hours, time.hour().into())
+            && bit(self.months, time.month().into())
+    }
+}
+
+impl TryFrom<&Schedule> for CronExpr {
+    type Error = String;
+
+    fn try_from(schedule: &Schedule) -> Result<Self, String> {
+        match schedule {
+            Schedule::Daily { time, days } => CronExpr::daily(time, days),
+            Schedule::Cron(expression) => CronExpr::parse(expression),
+        }
+    }
+}
+
+fn all(min: u32, max: u32) -> u64 {
+    (min..=max).fold(0, |mask, value| mask | 1 << value)
+}
+
+/// Parse one field into a bit mask. `names[i]` stands for `first_name + i`.
+fn parse_field(
+    field: &str,
+    min: u32,
+    max: u32,
+    names: &[&str],
+    first_name: u32,
+) -> Result<u64, String> {
+    let value = |text: &str| -> Result<u32, String> {
+        let value = match names
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(text))
+        {
+            Some(index) => index as u32 + first_name,
+            None => text
+                .parse()
+                .map_err(|_| format!("invalid value '{}'", text))?,
+        };
+        if value < min || value > max {
+            return Err(format!("{} is out of range {}-{}", value, min, max));
+        }
+        Ok(value)
+    };
+
+    let mut mask = 0;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .map_err(|_| format!("invalid step '{}'", step))?;
+                if step == 0 {
+                    return Err("step must not be 0".to_string());
+                }
+                (range, step)
+            }
+            None => (item, 1),
+        };
+
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                // `5/15` means from 5 to the end
+                None if step > 1 => (value(range)?, max),
+                None => {
+                    let value = value(range)?;
+                    (value, value)
+                }
+            },
+        };
+        if start > end {
+            return Err(format!("invalid range '{}'", range));
+        }
+
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+
+    Ok(mask)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jiff::civil::date;
+
+    #[test]
+    fn parses_fields() {
+        let cron = CronExpr::parse("*/15 9-17 * * mon-fri").unwrap();
+        // Monday 2024-01-01
+        assert!(cron.matches(date(2024, 1, 1).at(9, 45, 0, 0)));
+        assert!(!cron.matches(date(2024, 1, 1).at(9, 46, 0, 0)));
+        assert!(!cron.matches(date(2024, 1, 1).at(18, 0, 0, 0)));
+        // Saturday
+        assert!(!cron.matches(date(2024, 1, 6).at(9, 45, 0, 0)));
+    }
+
+    #[test]
+    fn sunday_is_0_and_7() {
+        let sunday = date(2024, 1, 7).at(8, 0, 0, 0);
+        assert!(CronExpr::parse("0 8 * * 0").unwrap().matches(sunday));
+        assert!(CronExpr::parse("0 8 * * 7").unwrap().matches(sunday));
+        assert!(CronExpr::parse("0 8 * * SUN").unwrap().matches(sunday));
+    }
+
+    #[test]
+    fn restricted_day_fields_match_either() {
+        // The 1st of the month or any Friday
+        let cron = CronExpr::parse("0 12 1 * fri").unwrap();
+        assert!(cron.matches(date(2024, 2, 1).at(12, 0, 0, 0)));
+        assert!(cron.matches(date(2024, 2, 2).at(12, 0, 0, 0)));
+        assert!(!cron.matches(date(2024, 2, 3).at(12, 0, 0, 0)));
+    }
+
+    #[test]
+    fn lists_and_months() {
+        let cron = CronExpr::parse("0,30 7 * dec,1 *").unwrap();
+        assert!(cron.matches(date(2024, 12, 24).at(7, 30, 0, 0)));
+        assert!(cron.matches(date(2024, 1, 2).at(7, 0, 0, 0)));
+        assert!(!cron.matches(date(2024, 6, 2).at(7, 0, 0, 0)));
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        assert!(CronExpr::parse("0 23 * *").is_err());
+        assert!(CronExpr::parse("60 23 * * *").is_err());
+        assert!(CronExpr::parse("0 23 * * 8").is_err());
+        assert!(CronExpr::parse("*/0 * * * *").is_err());
+        assert!(CronExpr::parse("0 5-2 * * *").is_err());
+        assert!(CronExpr::parse("0 x * * *").is_err());
+    }
+
+    #[test]
+    fn daily_times() {
+        let weekdays = [
+            Weekday::Mon,
+            Weekday::Tue,
+            Weekday::Wed,
+            Weekday::Thu,
+            Weekday::Fri,
+        ];
+        let cron = CronExpr::daily("23:00", &weekdays).unwrap();
+        assert!(cron.matches(date(2024, 1, 5).at(23, 0, 0, 0)));
+        assert!(!cron.matches(date(2024, 1, 6).at(23, 0, 0, 0)));
+
+        let every_day = CronExpr::daily("7:05", &[]).unwrap();
+        assert!(every_day.matches(date(2024, 1, 6).at(7, 5, 0, 0)));
+
+        assert!(CronExpr::daily("24:00", &[]).is_err());
+        assert!(CronExpr::daily("noon", &[]).is_err());
+    }
+}
@@ -0,0 +1,394 @@
+mod cron;
+
+use crate::actions::{ActionRunner, Trigger};
+use crate::config::{Config, MediaAction, ScheduledAction};
+use jiff::{RoundMode, ToSpan, Unit, Zoned, ZonedRound};
+use log::{info, warn};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub use cron::CronExpr;
+
+/// A scheduled minute that is at most this old still runs
+/// (e.g. the thread woke up late), older ones are reported as missed
+const GRACE_MINUTES: i64 = 1;
+
+/// Gaps longer than this (a long suspend) are only checked for the last week
+const MAX_CATCH_UP_MINUTES: i64 = 7 * 24 * 60;
+
+/// Source of the current time, replaced by a fake clock in tests
+pub trait Clock {
+    fn now(&self) -> Zoned;
+    fn sleep(&self, duration: Duration);
+}
+
+/// The system's local time
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Zoned {
+        Zoned::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// Result of checking the schedule
+#[derive(Debug, Default, PartialEq)]
+pub struct Due {
+    /// Entries to run now, by index in `Config::schedule`
+    pub run: Vec<(usize, MediaAction)>,
+    /// Entries whose time has passed while the system was asleep
+    pub missed: Vec<(usize, Zoned)>,
+}
+
+/// Fires the actions of `Config::schedule` at their times
+pub struct Scheduler<C: Clock> {
+    clock: C,
+    config: Arc<Mutex<Config>>,
+    /// The last minute that has been checked
+    checked: Zoned,
+    /// `Config::schedule` as it was last parsed
+    parsed_from: Vec<ScheduledAction>,
+    /// The valid entries of `parsed_from`, by index
+    parsed: Vec<(usize, CronExpr, MediaAction)>,
+}
+
+/// Start of the minute of `time`
+fn minute_of(time: &Zoned) -> Zoned {
+    time.round(
+        ZonedRound::new()
+            .smallest(Unit::Minute)
+            .mode(RoundMode::Trunc),
+    )
+    .unwrap_or_else(|_| time.clone())
+}
+
+impl<C: Clock> Scheduler<C> {
+    pub fn new(clock: C, config: Arc<Mutex<Config>>) -> Self {
+        // Entries of the minute the application starts in still run
+        let checked = minute_of(&clock.now()).saturating_sub(1.minute());
+        Scheduler {
+            clock,
+            config,
+            checked,
+            parsed_from: Vec::new(),
+            parsed: Vec::new(),
+        }
+    }
+
+    /// Parse `schedule` if it has changed since the last time,
+    /// so invalid entries are reported once. Whether it was parsed.
+    fn parse(&mut self, schedule: &[ScheduledAction]) -> bool {
+        if self.parsed_from == schedule {
+            return false;
+        }
+
+        self.parsed = schedule
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entry)| match CronExpr::try_from(&entry.when) {
+                Ok(cron) => Some((index, cron, entry.action.clone())),
+                Err(e) => {
+                    warn!("Scheduled action #{} is invalid: {}", index + 1, e);
+                    None
+                }
+            })
+            .collect();
+        self.parsed_from = schedule.to_vec();
+        true
+    }
+
+    /// Find the entries due since the last check
+    pub fn check(&mut self) -> Due {
+        let now = minute_of(&self.clock.now());
+        let mut due = Due::default();
+
+        // The clock went backwards, nothing to catch up on
+        if now <= self.checked {
+            self.checked = now;
+            return due;
+        }
+
+        let config = Arc::clone(&self.config);
+        self.parse(&config.lock().unwrap().schedule);
+
+        let mut minute = self.checked.saturating_add(1.minute());
+        let oldest = now.saturating_sub(MAX_CATCH_UP_MINUTES.minutes());
+        if minute < oldest {
+            warn!(
+                "Scheduler was not running since {}, only checking the last week",
+                self.checked.strftime("%Y-%m-%d %H:%M")
+            );
+            minute = oldest;
+        }
+        let grace = now.saturating_sub(GRACE_MINUTES.minutes());
+
+        while minute <= now {
+            for (index, cron, action) in &self.parsed {
+                if !cron.matches(minute.datetime()) {
+                    continue;
+                }
+                if minute >= grace {
+                    due.run.push((*index, action.clone()));
+                } else {
+                    due.missed.push((*index, minute.clone()));
+                }
+            }
+            minute = minute.saturating_add(1.minute());
+        }
+
+        self.checked = now;
+        due
+    }
+
+    /// Time until the start of the next minute
+    fn until_next_minute(&self) -> Duration {
+        let now = self.clock.now();
+        let next = minute_of(&now).saturating_add(1.minute());
+        let nanos = next.timestamp().as_nanosecond() - now.timestamp().as_nanosecond();
+        Duration::from_nanos(nanos.clamp(0, 60_000_000_000) as u64)
+    }
+
+    /// Check the schedule every minute and run the due actions
+    pub fn run(mut self, action_runner: ActionRunner) {
+        info!("Scheduler started");
+
+        loop {
+            let due = self.check();
+
+            for (index, time) in &due.missed {
+                warn!(
+                    "Missed scheduled action #{} at {} (system was asleep?)",
+                    index + 1,
+                    time.strftime("%Y-%m-%d %H:%M")
+                );
+            }
+
+            if !due.run.is_empty() {
+                let config = self.config.lock().unwrap().clone();
+                for (index, action) in &due.run {
+                    info!("Running scheduled action #{}: {}", index + 1, action.name());
+                    action_runner.run(Trigger::Schedule(*index), action, &config);
+                }
+            }
+
+            self.clock.sleep(self.until_next_minute());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Schedule, ScheduledAction, Weekday};
+    use jiff::civil::date;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Clock that only moves when told to
+    #[derive(Clone)]
+    struct FakeClock(Rc<RefCell<Zoned>>);
+
+    impl FakeClock {
+        fn at(year: i16, month: i8, day: i8, hour: i8, minute: i8) -> Self {
+            let time = date(year, month, day)
+                .at(hour, minute, 0, 0)
+                .to_zoned(jiff::tz::TimeZone::UTC)
+                .unwrap();
+            FakeClock(Rc::new(RefCell::new(time)))
+        }
+
+        fn advance(&self, span: jiff::Span) {
+            let mut time = self.0.borrow_mut();
+            *time = time.checked_add(span).unwrap();
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Zoned {
+            self.0.borrow().clone()
+        }
+
+        fn sleep(&self, duration: Duration) {
+            self.advance(jiff::Span::try_from(duration).unwrap());
+        }
+    }
+
+    fn scheduler(clock: &FakeClock, schedule: Vec<ScheduledAction>) -> Scheduler<FakeClock> {
+        let config = Config {
+            schedule,
+            ..Config::default()
+        };
+        Scheduler::new(clock.clone(), Arc::new(Mutex::new(config)))
+    }
+
+    fn entry(when: Schedule, action: MediaAction) -> ScheduledAction {
+        ScheduledAction { when, action }
+    }
+
+    fn indexes(due: &Due) -> Vec<usize> {
+        due.run.iter().map(|(index, _)| *index).collect()
+    }
+
+    #[test]
+    fn runs_daily_action_on_its_minute() {
+        // Friday 2024-01-05, 22:58
+        let clock = FakeClock::at(2024, 1, 5, 22, 58);
+        let weekdays = vec![
+            Weekday::Mon,
+            Weekday::Tue,
+            Weekday::Wed,
+            Weekday::Thu,
+            Weekday::Fri,
+        ];
+        let mut scheduler = scheduler(
+            &clock,
+            vec![entry(
+                Schedule::Daily {
+                    time: "23:00".to_string(),
+                    days: weekdays,
+                },
+                MediaAction::Stop,
+            )],
+        );
+
+        assert_eq!(scheduler.check(), Due::default());
+
+        clock.sleep(scheduler.until_next_minute());
+        assert_eq!(scheduler.check(), Due::default());
+
+        clock.sleep(scheduler.until_next_minute());
+        assert_eq!(scheduler.check().run, vec![(0, MediaAction::Stop)]);
+
+        // Checking again in the same minute doesn't run it twice
+        clock.advance(30.seconds());
+        assert_eq!(scheduler.check(), Due::default());
+
+        // Saturday is not a weekday
+        clock.advance(1.day());
+        assert!(scheduler.check().run.is_empty());
+    }
+
+    #[test]
+    fn late_wakeup_still_runs() {
+        let clock = FakeClock::at(2024, 1, 1, 7, 59);
+        let mut scheduler = scheduler(
+            &clock,
+            vec![entry(
+                Schedule::Cron("0 8 * * *".to_string()),
+                MediaAction::PlayPause,
+            )],
+        );
+        scheduler.check();
+
+        // Woke up at 08:01:30 instead of 08:00
+        clock.advance(2.minutes().seconds(30));
+        let due = scheduler.check();
+        assert_eq!(indexes(&due), vec![0]);
+        assert!(due.missed.is_empty());
+    }
+
+    #[test]
+    fn reports_missed_runs_after_suspend() {
+        let clock = FakeClock::at(2024, 1, 1, 22, 0);
+        let mut scheduler = scheduler(
+            &clock,
+            vec![
+                entry(Schedule::Cron("0 23 * * *".to_string()), MediaAction::Stop),
+                entry(Schedule::Cron("30 * * * *".to_string()), MediaAction::Next),
+                entry(
+                    Schedule::Cron("0 6 * * *".to_string()),
+                    MediaAction::PlayPause,
+                ),
+            ],
+        );
+        scheduler.check();
+
+        // Suspended until 06:00 the next morning
+        clock.advance(8.hours());
+        let due = scheduler.check();
+
+        assert_eq!(indexes(&due), vec![2]);
+        let missed: Vec<_> = due
+            .missed
+            .iter()
+            .map(|(index, time)| (*index, time.hour(), time.minute()))
+            .collect();
+        assert_eq!(
+            missed,
+            vec![
+                (1, 22, 30),
+                (0, 23, 0),
+                (1, 23, 30),
+                (1, 0, 30),
+                (1, 1, 30),
+                (1, 2, 30),
+                (1, 3, 30),
+                (1, 4, 30),
+                (1, 5, 30),
+            ]
+        );
+    }
+
+    #[test]
+    fn clock_going_backwards_runs_nothing() {
+        let clock = FakeClock::at(2024, 1, 1, 12, 0);
+        let mut scheduler = scheduler(
+            &clock,
+            vec![entry(
+                Schedule::Cron("* * * * *".to_string()),
+                MediaAction::Next,
+            )],
+        );
+        assert_eq!(indexes(&scheduler.check()), vec![0]);
+
+        clock.advance((-10).minutes());
+        assert_eq!(scheduler.check(), Due::default());
+
+        clock.advance(1.minute());
+        assert_eq!(indexes(&scheduler.check()), vec![0]);
+    }
+
+    #[test]
+    fn skips_invalid_entries() {
+        let clock = FakeClock::at(2024, 1, 1, 12, 0);
+        let mut scheduler = scheduler(
+            &clock,
+            vec![
+                entry(
+                    Schedule::Cron("every minute".to_string()),
+                    MediaAction::Stop,
+                ),
+                entry(Schedule::Cron("0 12 * * *".to_string()), MediaAction::Next),
+            ],
+        );
+        assert_eq!(indexes(&scheduler.check()), vec![1]);
+
+        // Reported once, not on every check
+        let schedule = scheduler.config.lock().unwrap().schedule.clone();
+        assert!(!scheduler.parse(&schedule));
+        clock.advance(1.minute());
+        assert_eq!(scheduler.check(), Due::default());
+        assert!(!scheduler.parse(&schedule));
+    }
+
+    #[test]
+    fn follows_config_changes() {
+        let clock = FakeClock::at(2024, 1, 1, 12, 0);
+        let mut scheduler = scheduler(&clock, Vec::new());
+        assert_eq!(scheduler.check(), Due::default());
+
+        scheduler.config.lock().unwrap().schedule.push(entry(
+            Schedule::Daily {
+                time: "12:01".to_string(),
+                days: Vec::new(),
+            },
+            MediaAction::VolumeDown,
+        ));
+        clock.advance(1.minute());
+        assert_eq!(scheduler.check().run, vec![(0, MediaAction::VolumeDown)]);
+    }
+}
@@ -0,0 +1,282 @@
+use crate::config::{BindingSet, Config, Profile, Resolution};
+use eframe::egui;
+use rdev::Key;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use super::action_name;
+
+/// Exports bindings of the active profile to a file, or imports them from one
+pub enum BindingsDialog {
+    Export {
+        path: String,
+        /// Bindings of the active profile and whether they are exported
+        selected: Vec<(Key, bool)>,
+        message: Option<Result<String, String>>,
+    },
+    Import {
+        path: String,
+        set: Option<BindingSet>,
+        /// Chosen for each conflicting key, skipped if not chosen
+        resolutions: HashMap<Key, Resolution>,
+        message: Option<Result<String, String>>,
+    },
+}
+
+/// Where the file goes unless the user picks another path
+fn default_path(profile: &str) -> String {
+    let dir = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+    dir.join(format!("{}-bindings.json", profile))
+        .display()
+        .to_string()
+}
+
+impl BindingsDialog {
+    pub fn export(config: &Config) -> Self {
+        let set = BindingSet::export(&config.active_profile, config.active(), None);
+        BindingsDialog::Export {
+            path: default_path(&config.active_profile),
+            selected: set.keys().into_iter().map(|key| (key, true)).collect(),
+            message: None,
+        }
+    }
+
+    pub fn import(config: &Config) -> Self {
+        BindingsDialog::Import {
+            path: default_path(&config.active_profile),
+            set: None,
+            resolutions: HashMap::new(),
+            message: None,
+        }
+    }
+
+    /// Show the dialog, returns false once it is closed
+    pub fn show(&mut self, ctx: &egui::Context, config: &mut Config) -> bool {
+        match self {
+            BindingsDialog::Export {
+                path,
+                selected,
+                message,
+            } => show_export(ctx, config, path, selected, message),
+            BindingsDialog::Import {
+                path,
+                set,
+                resolutions,
+                message,
+            } => show_import(ctx, config, path, set, resolutions, message),
+        }
+    }
+}
+
+fn show_export(
+    ctx: &egui::Context,
+    config: &Config,
+    path: &mut String,
+    selected: &mut [(Key, bool)],
+    message: &mut Option<Result<String, String>>,
+) -> bool {
+    let mut open = true;
+
+    egui::Window::new("📤 Export Bindings")
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.label(format!(
+                "Bindings of '{}' to export:",
+                config.active_profile
+            ));
+            let profile = config.active();
+            for (key, export) in selected.iter_mut() {
+                let action = profile.hotkeys.get(key).map(action_name).unwrap_or("");
+                ui.checkbox(export, format!("{:?}: {}", key, action));
+            }
+
+            ui.add_space(5.0);
+            ui.horizontal(|ui| {
+                ui.label("File:");
+                ui.text_edit_singleline(path);
+            });
+            ui.label("The format is taken from the extension: .json, .toml or .yaml");
+            if PathBuf::from(path.as_str()).exists() {
+                ui.colored_label(
+                    egui::Color32::from_rgb(220, 160, 60),
+                    "The file exists and will be replaced",
+                );
+            }
+
+            show_message(ui, message);
+
+            ui.add_space(5.0);
+            ui.horizontal(|ui| {
+                let keys: Vec<Key> = selected
+                    .iter()
+                    .filter(|(_, export)| *export)
+                    .map(|(key, _)| *key)
+                    .collect();
+                if ui
+                    .add_enabled(!keys.is_empty(), egui::Button::new("📤 Export"))
+                    .clicked()
+                {
+                    let set = BindingSet::export(&config.active_profile, profile, Some(&keys));
+                    *message = Some(
+                        set.write(&PathBuf::from(path.as_str()))
+                            .map(|()| format!("Exported {} binding(s)", keys.len())),
+                    );
+                }
+                if ui.button("Close").clicked() {
+                    open = false;
+                }
+            });
+        });
+
+    open
+}
+
+fn show_message(ui: &mut egui::Ui, message: &Option<Result<String, String>>) {
+    match message {
+        Some(Ok(text)) => {
+            ui.colored_label(egui::Color32::GREEN, text.as_str());
+        }
+        Some(Err(text)) => {
+            ui.colored_label(egui::Color32::RED, text.as_str());
+        }
+        None => {}
+    }
+}
+
+fn show_import(
+    ctx: &egui::Context,
+    config: &mut Config,
+    path: &mut String,
+    set: &mut Option<BindingSet>,
+    resolutions: &mut HashMap<Key, Resolution>,
+    message: &mut Option<Result<String, String>>,
+) -> bool {
+    let mut open = true;
+
+    egui::Window::new("📥 Import Bindings")
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("File:");
+                ui.text_edit_singleline(path);
+                if ui.button("Open").clicked() {
+                    match BindingSet::read(&PathBuf::from(path.as_str())) {
+                        Ok(opened) => {
+                            *set = Some(opened);
+                            *message = None;
+                        }
+                        Err(e) => {
+                            *set = None;
+                            *message = Some(Err(e));
+                        }
+                    }
+                    resolutions.clear();
+                }
+            });
+
+            if let Some(set) = set {
+                ui.add_space(5.0);
+                ui.label(format!(
+                    "Into '{}', from '{}':",
+                    config.active_profile, set.profile
+                ));
+                conflicts_grid(ui, config.active(), set, resolutions);
+            }
+            show_message(ui, message);
+
+            ui.add_space(5.0);
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(set.is_some(), egui::Button::new("📥 Import"))
+                    .clicked()
+                {
+                    if let Some(imported) = set.take() {
+                        let summary = config.active_mut().import(&imported, |key| {
+                            resolutions.get(&key).copied().unwrap_or(Resolution::Skip)
+                        });
+                        *message = Some(Ok(format!("{}. Save to keep them.", summary)));
+                    }
+                }
+                if ui.button("Close").clicked() {
+                    open = false;
+                }
+            });
+        });
+
+    open
+}
+
+/// The bindings of `set`, with a choice for each key that is bound already
+fn conflicts_grid(
+    ui: &mut egui::Ui,
+    profile: &Profile,
+    set: &BindingSet,
+    resolutions: &mut HashMap<Key, Resolution>,
+) {
+    let conflicts = profile.conflicts(set);
+    let free = profile.free_keys(set);
+
+    egui::Grid::new("import_bindings")
+        .num_columns(3)
+        .spacing([10.0, 4.0])
+        .striped(true)
+        .show(ui, |ui| {
+            for key in set.keys() {
+                ui.label(format!("{:?}", key));
+                ui.label(action_name(&set.hotkeys[&key]));
+
+                if !conflicts.contains(&key) {
+                    let same = profile.hotkeys.get(&key) == Some(&set.hotkeys[&key]);
+                    ui.label(if same { "already bound" } else { "new" });
+                    ui.end_row();
+                    continue;
+                }
+
+                let existing = action_name(&profile.hotkeys[&key]);
+                let resolution = resolutions.entry(key).or_insert(Resolution::Skip);
+                let text = match resolution {
+                    Resolution::Skip => format!("Skip, keep {}", existing),
+                    Resolution::Replace => format!("Replace {}", existing),
+                    Resolution::Rename(to) => format!("Bind to {:?}", to),
+                };
+                egui::ComboBox::from_id_salt(("import_resolution", format!("{:?}", key)))
+                    .selected_text(text)
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(
+                            resolution,
+                            Resolution::Skip,
+                            format!("Skip, keep {}", existing),
+                        );
+                        ui.selectable_value(
+                            resolution,
+                            Resolution::Replace,
+                            format!("Replace {}", existing),
+                        );
+                        for to in &free {
+                            ui.selectable_value(
+                                resolution,
+                                Resolution::Rename(*to),
+                                format!("Bind to {:?}", to),
+                            );
+                        }
+                    });
+                ui.end_row();
+            }
+        });
+
+    // Two bindings can't be moved to the same key
+    let mut taken = Vec::new();
+    for key in &conflicts {
+        if let Some(Resolution::Rename(to)) = resolutions.get(key) {
+            if taken.contains(to) {
+                ui.colored_label(
+                    egui::Color32::from_rgb(220, 160, 60),
+                    format!("{:?} is chosen twice, only the first binding gets it", to),
+                );
+            }
+            taken.push(*to);
+        }
+    }
+}
@@ -0,0 +1,233 @@
+//! Undo and redo of the edits made in the window, and what is not saved yet
+
+use crate::config::{Config, Format};
+use similar::{ChangeTag, TextDiff};
+
+/// Typing closer together than this is undone at once
+const MERGE_SECONDS: f64 = 1.0;
+
+/// How many edits can be undone
+const LIMIT: usize = 100;
+
+/// Edits of the configuration since the window was opened
+pub struct History {
+    /// The configuration as it was last saved or applied
+    saved: Config,
+    /// The configuration before each edit, oldest first
+    undo: Vec<Config>,
+    redo: Vec<Config>,
+    /// The configuration after the last recorded edit
+    current: Config,
+    /// When the last edit was recorded, if it was typing
+    typed_at: Option<f64>,
+}
+
+/// Whether `a` and `b` differ in more than the active profile,
+/// which is applied right away and isn't an edit
+fn same_settings(a: &Config, b: &Config) -> bool {
+    let a = Config {
+        active_profile: b.active_profile.clone(),
+        ..a.clone()
+    };
+    a == *b
+}
+
+impl History {
+    pub fn new(config: &Config) -> Self {
+        History {
+            saved: config.clone(),
+            undo: Vec::new(),
+            redo: Vec::new(),
+            current: config.clone(),
+            typed_at: None,
+        }
+    }
+
+    /// Start over with a configuration that replaced the edited one,
+    /// e.g. reloaded from the file
+    pub fn reset(&mut self, config: &Config) {
+        *self = History::new(config);
+    }
+
+    /// `config` was saved, the edits can still be undone
+    pub fn saved(&mut self, config: &Config) {
+        self.saved = config.clone();
+        self.current = config.clone();
+    }
+
+    /// Note the edits made to `config` at `time`, `typing` if a text field has the focus
+    pub fn record(&mut self, config: &Config, time: f64, typing: bool) {
+        if same_settings(config, &self.current) {
+            self.current.active_profile = config.active_profile.clone();
+            return;
+        }
+
+        let merged = typing && self.typed_at.is_some_and(|at| time - at < MERGE_SECONDS);
+        if !merged {
+            self.undo.push(self.current.clone());
+            if self.undo.len() > LIMIT {
+                self.undo.remove(0);
+            }
+        }
+        self.redo.clear();
+        self.current = config.clone();
+        self.typed_at = typing.then_some(time);
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Take back the last edit of `config`
+    pub fn undo(&mut self, config: &mut Config) {
+        if let Some(previous) = self.undo.pop() {
+            self.redo.push(self.current.clone());
+            self.restore(config, previous);
+        }
+    }
+
+    /// Make the last undone edit of `config` again
+    pub fn redo(&mut self, config: &mut Config) {
+        if let Some(next) = self.redo.pop() {
+            self.undo.push(self.current.clone());
+            self.restore(config, next);
+        }
+    }
+
+    fn restore(&mut self, config: &mut Config, snapshot: Config) {
+        let active = std::mem::take(&mut config.active_profile);
+        *config = snapshot;
+        // Stay in the profile that is used now, unless the edit created it
+        if config.profiles.contains_key(&active) {
+            config.active_profile = active;
+        }
+        self.current = config.clone();
+        self.typed_at = None;
+    }
+
+    /// Whether `config` has edits that are not saved
+    pub fn is_dirty(&self, config: &Config) -> bool {
+        !same_settings(config, &self.saved)
+    }
+
+    /// Changed lines of the file between the saved configuration and `config`,
+    /// in groups with a few unchanged lines around them
+    pub fn diff(&self, config: &Config) -> Vec<Vec<(ChangeTag, String)>> {
+        let saved = Config {
+            active_profile: config.active_profile.clone(),
+            ..self.saved.clone()
+        };
+        let (Ok(old), Ok(new)) = (saved.to_text(Format::Json), config.to_text(Format::Json))
+        else {
+            return Vec::new();
+        };
+
+        let diff = TextDiff::from_lines(&old, &new);
+        diff.grouped_ops(2)
+            .iter()
+            .map(|group| {
+                group
+                    .iter()
+                    .flat_map(|op| diff.iter_changes(op))
+                    .map(|change| (change.tag(), change.value().trim_end().to_string()))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MediaAction;
+    use rdev::Key;
+
+    fn bind(config: &mut Config, key: Key, action: MediaAction) {
+        config.active_mut().hotkeys.insert(key, action);
+    }
+
+    #[test]
+    fn undoes_and_redoes_edits() {
+        let mut config = Config::default();
+        let mut history = History::new(&config);
+
+        bind(&mut config, Key::F1, MediaAction::Next);
+        history.record(&config, 1.0, false);
+        bind(&mut config, Key::F2, MediaAction::Stop);
+        history.record(&config, 1.1, false);
+        assert!(history.is_dirty(&config));
+
+        history.undo(&mut config);
+        assert!(config.active().hotkeys.contains_key(&Key::F1));
+        assert!(!config.active().hotkeys.contains_key(&Key::F2));
+        history.undo(&mut config);
+        assert!(!history.can_undo());
+        assert!(!history.is_dirty(&config));
+
+        history.redo(&mut config);
+        assert!(config.active().hotkeys.contains_key(&Key::F1));
+        assert!(history.can_redo());
+
+        // A new edit drops what was undone
+        bind(&mut config, Key::F3, MediaAction::PlayPause);
+        history.record(&config, 2.0, false);
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn typing_is_undone_at_once() {
+        let mut config = Config::default();
+        let mut history = History::new(&config);
+
+        for (time, app) in [(1.0, "m"), (1.3, "mp"), (1.6, "mpv")] {
+            let app = app.to_string();
+            bind(&mut config, Key::F1, MediaAction::AppMute { app });
+            history.record(&config, time, true);
+        }
+
+        history.undo(&mut config);
+        assert!(!config.active().hotkeys.contains_key(&Key::F1));
+    }
+
+    #[test]
+    fn switching_profiles_is_not_an_edit() {
+        let mut config = Config::default();
+        config.profiles.insert("Work".to_string(), Default::default());
+        let mut history = History::new(&config);
+
+        config.active_profile = "Work".to_string();
+        history.record(&config, 1.0, false);
+        assert!(!history.can_undo());
+        assert!(!history.is_dirty(&config));
+
+        // Undo keeps the profile in use
+        bind(&mut config, Key::F1, MediaAction::Next);
+        history.record(&config, 2.0, false);
+        history.undo(&mut config);
+        assert_eq!(config.active_profile, "Work");
+    }
+
+    #[test]
+    fn saving_keeps_the_edits_undoable() {
+        let mut config = Config::default();
+        let mut history = History::new(&config);
+
+        config.backups = 1;
+        history.record(&config, 1.0, false);
+        let diff = history.diff(&config);
+        assert_eq!(diff.len(), 1);
+        assert!(diff[0].contains(&(ChangeTag::Delete, "  \"backups\": 5,".to_string())));
+        assert!(diff[0].contains(&(ChangeTag::Insert, "  \"backups\": 1,".to_string())));
+
+        history.saved(&config);
+        assert!(!history.is_dirty(&config));
+        assert!(history.diff(&config).is_empty());
+        history.undo(&mut config);
+        assert_eq!(config.backups, 5);
+        assert!(history.is_dirty(&config));
+    }
+}
@@ -0,0 +1,208 @@
+use crate::config::{MediaAction, Step, SUPPORTED_KEYS};
+use eframe::egui;
+use rdev::Key;
+
+use super::action_name;
+
+/// What the user did with the macro editor
+pub enum EditorResult {
+    Open,
+    /// Bind the macro to the key
+    Save(Key, Vec<Step>),
+    Close,
+}
+
+/// Records triggered actions and edits the steps of a macro
+pub struct MacroEditor {
+    key: Key,
+    steps: Vec<Step>,
+    recording: bool,
+    last_recorded: Option<f64>,
+}
+
+impl MacroEditor {
+    pub fn new(key: Key, steps: Vec<Step>) -> Self {
+        MacroEditor {
+            key,
+            steps,
+            recording: false,
+            last_recorded: None,
+        }
+    }
+
+    /// Key the macro will be bound to
+    pub fn key(&self) -> Key {
+        self.key
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Record an action triggered at `time` (seconds), with the delay since the previous one
+    pub fn record(&mut self, action: &MediaAction, time: f64) {
+        if !self.recording {
+            return;
+        }
+
+        if let Some(last) = self.last_recorded {
+            let delay = ((time - last) * 1000.0).round() as u64;
+            if delay > 0 {
+                self.steps.push(Step::Delay(delay));
+            }
+        }
+
+        self.steps.push(Step::Action(action.clone()));
+        self.last_recorded = Some(time);
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context, bound: Option<&MediaAction>) -> EditorResult {
+        let mut result = EditorResult::Open;
+
+        egui::Window::new("⏺ Macro Editor")
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Key:");
+                    egui::ComboBox::from_id_salt("macro_key")
+                        .selected_text(format!("{:?}", self.key))
+                        .show_ui(ui, |ui| {
+                            for key in SUPPORTED_KEYS {
+                                ui.selectable_value(&mut self.key, key, format!("{:?}", key));
+                            }
+                        });
+
+                    // Warn before replacing another binding
+                    if let Some(action) = bound.filter(|action| !matches!(action, MediaAction::Macro(_))) {
+                        ui.colored_label(
+                            egui::Color32::from_rgb(220, 160, 60),
+                            format!("replaces {}", action_name(action)),
+                        );
+                    }
+                });
+
+                ui.add_space(5.0);
+
+                ui.horizontal(|ui| {
+                    if self.recording {
+                        if ui.button("⏹ Stop recording").clicked() {
+                            self.recording = false;
+                        }
+                        ui.colored_label(egui::Color32::RED, "● Recording");
+                    } else if ui.button("⏺ Record").clicked() {
+                        self.recording = true;
+                        self.last_recorded = None;
+                    }
+                });
+                ui.label("Press hotkeys or the ▶ buttons to record their actions.");
+
+                ui.add_space(5.0);
+                ui.separator();
+
+                self.steps_editor(ui);
+
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    if ui.button("➕ Delay").clicked() {
+                        self.steps.push(Step::Delay(500));
+                    }
+                    if ui.button("Remove delays").clicked() {
+                        self.steps.retain(|step| !matches!(step, Step::Delay(_)));
+                    }
+                    if ui.button("Clear").clicked() {
+                        self.steps.clear();
+                        self.last_recorded = None;
+                    }
+                });
+
+                ui.add_space(5.0);
+
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(!self.steps.is_empty(), egui::Button::new("💾 Save binding"))
+                        .clicked()
+                    {
+                        result = EditorResult::Save(self.key, self.steps.clone());
+                    }
+                    if ui.button("Cancel").clicked() {
+                        result = EditorResult::Close;
+                    }
+                });
+            });
+
+        result
+    }
+
+    /// List of steps with editable delays
+    fn steps_editor(&mut self, ui: &mut egui::Ui) {
+        if self.steps.is_empty() {
+            ui.label("No steps yet");
+            return;
+        }
+
+        let mut move_up = None;
+        let mut move_down = None;
+        let mut remove = None;
+        let count = self.steps.len();
+
+        egui::ScrollArea::vertical()
+            .max_height(200.0)
+            .show(ui, |ui| {
+                egui::Grid::new("macro_steps")
+                    .num_columns(3)
+                    .spacing([10.0, 4.0])
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for (index, step) in self.steps.iter_mut().enumerate() {
+                            ui.label(format!("{}.", index + 1));
+
+                            match step {
+                                Step::Action(action) => {
+                                    ui.label(action_name(action));
+                                }
+                                Step::Delay(ms) => {
+                                    ui.horizontal(|ui| {
+                                        ui.label("Wait");
+                                        ui.add(
+                                            egui::DragValue::new(ms)
+                                                .range(0..=600_000)
+                                                .speed(10)
+                                                .suffix(" ms"),
+                                        );
+                                    });
+                                }
+                            }
+
+                            ui.horizontal(|ui| {
+                                if ui.add_enabled(index > 0, egui::Button::new("⬆")).clicked() {
+                                    move_up = Some(index);
+                                }
+                                if ui
+                                    .add_enabled(index + 1 < count, egui::Button::new("⬇"))
+                                    .clicked()
+                                {
+                                    move_down = Some(index);
+                                }
+                                if ui.button("❌").clicked() {
+                                    remove = Some(index);
+                                }
+                            });
+
+                            ui.end_row();
+                        }
+                    });
+            });
+
+        if let Some(index) = move_up {
+            self.steps.swap(index, index - 1);
+        }
+        if let Some(index) = move_down {
+            self.steps.swap(index, index + 1);
+        }
+        if let Some(index) = remove {
+            self.steps.remove(index);
+        }
+    }
+}
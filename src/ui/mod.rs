@@ -0,0 +1,1594 @@
+mod bindings_dialog;
+mod history;
+mod macro_editor;
+
+use crate::actions::{format_duration, MacroProgress, Trigger};
+use crate::config::{
+    default_mpv_socket, Backend, Config, Finding, MediaAction, MpdSettings, MpvSettings,
+    OutputDeviceSettings, RuleTarget, Schedule, ScheduledAction, ScriptSource, Severity, Weekday,
+    WindowRule, SUPPORTED_KEYS,
+};
+use crate::hotkey_listener::{BindingStatus, BindingStatuses};
+use crate::media_control::{self, AppStream, NowPlaying, OutputDevice};
+use crate::scheduler::CronExpr;
+use crossbeam_channel::{Receiver, Sender};
+use eframe::egui;
+use log::info;
+use rdev::Key;
+use similar::ChangeTag;
+use std::time::Instant;
+
+use bindings_dialog::BindingsDialog;
+use history::History;
+use macro_editor::{EditorResult, MacroEditor};
+
+/// Messages from UI to main thread
+#[derive(Debug, Clone)]
+pub enum UiMessage {
+    /// Use this configuration from now on, it is not written to the file
+    ApplyConfig(Config),
+    /// Run an action once, as if its key was pressed
+    TestAction(Key, MediaAction),
+    /// Make the profile active right away
+    SwitchProfile(String),
+    /// Resume (true) or pause (false) the hotkeys
+    SetEnabled(bool),
+    CancelSleepTimer,
+    Exit,
+}
+
+/// Messages from main thread to UI
+#[derive(Debug, Clone)]
+pub enum AppMessage {
+    HotkeyPressed(Key, MediaAction),
+    NowPlaying(Option<NowPlaying>),
+    MacroProgress(Trigger, MacroProgress),
+    /// A script failed to compile or stopped with an error
+    ScriptError(Trigger, String),
+    /// The sleep timer was started or changed (deadline), or has ended
+    SleepTimer(Option<Instant>),
+    /// Another profile became active
+    ProfileChanged(String),
+    /// A window rule started or stopped applying (its description)
+    WindowRule(Option<String>),
+    /// The hotkeys were resumed (true) or paused (false)
+    Enabled(bool),
+    /// The configuration file was changed outside the app
+    ConfigUpdated(Config),
+    /// The configuration file could not be loaded, the previous one is still used
+    ConfigError(String),
+    /// How the keys of the active profile were registered
+    BindingStatus(BindingStatuses),
+}
+
+/// Playing applications and output devices shown in the pickers
+struct AudioLists {
+    app_streams: Vec<AppStream>,
+    output_devices: Vec<OutputDevice>,
+    /// Why the lists can't be loaded, e.g. a missing `pactl`
+    unavailable: Option<String>,
+}
+
+impl AudioLists {
+    fn load() -> Self {
+        AudioLists {
+            app_streams: media_control::app_streams(),
+            output_devices: media_control::output_devices(),
+            unavailable: media_control::audio_unavailable(),
+        }
+    }
+}
+
+/// Main application with UI
+pub struct HotkeyMapperApp {
+    config: Config,
+    last_pressed_info: Option<(Key, MediaAction, f64)>,
+    now_playing: Option<NowPlaying>,
+    macro_progress: Option<(Trigger, MacroProgress, f64)>,
+    macro_editor: Option<MacroEditor>,
+    bindings_dialog: Option<BindingsDialog>,
+    script_error: Option<(Trigger, String)>,
+    config_error: Option<String>,
+    /// File the configuration is loaded from and saved to
+    config_path: String,
+    /// Edits that can be undone, and the configuration as it was saved
+    history: History,
+    /// The changes are shown for review before saving
+    review_changes: bool,
+    /// Asking whether to save before exiting
+    confirm_exit: bool,
+    /// Result of the last "Check", until it is dismissed
+    check_findings: Option<Vec<Finding>>,
+    sleep_timer: Option<Instant>,
+    window_rule: Option<String>,
+    /// Reported by the listener for the bindings it handles
+    binding_statuses: BindingStatuses,
+    /// False while the hotkeys are paused
+    enabled: bool,
+    /// New name while a profile is being renamed
+    profile_rename: Option<String>,
+    audio: AudioLists,
+    ui_sender: Sender<UiMessage>,
+    app_receiver: Receiver<AppMessage>,
+}
+
+impl HotkeyMapperApp {
+    pub fn new(
+        config: Config,
+        ui_sender: Sender<UiMessage>,
+        app_receiver: Receiver<AppMessage>,
+    ) -> Self {
+        Self {
+            history: History::new(&config),
+            config,
+            last_pressed_info: None,
+            now_playing: None,
+            macro_progress: None,
+            macro_editor: None,
+            bindings_dialog: None,
+            script_error: None,
+            config_error: None,
+            config_path: Config::config_path().display().to_string(),
+            review_changes: false,
+            confirm_exit: false,
+            check_findings: None,
+            sleep_timer: None,
+            window_rule: None,
+            binding_statuses: BindingStatuses::new(),
+            enabled: true,
+            profile_rename: None,
+            audio: AudioLists::load(),
+            ui_sender,
+            app_receiver,
+        }
+    }
+
+    fn process_app_messages(&mut self, ctx: &egui::Context) {
+        while let Ok(msg) = self.app_receiver.try_recv() {
+            match msg {
+                AppMessage::HotkeyPressed(key, action) => {
+                    info!("UI received hotkey press: {:?} -> {:?}", key, action);
+                    let time = ctx.input(|i| i.time);
+                    if let Some(editor) = &mut self.macro_editor {
+                        editor.record(&action, time);
+                    }
+                    self.last_pressed_info = Some((key, action, time));
+                    ctx.request_repaint();
+                }
+                AppMessage::MacroProgress(trigger, progress) => {
+                    self.macro_progress = Some((trigger, progress, ctx.input(|i| i.time)));
+                    ctx.request_repaint();
+                }
+                AppMessage::ScriptError(trigger, error) => {
+                    self.script_error = Some((trigger, error));
+                    ctx.request_repaint();
+                }
+                AppMessage::SleepTimer(deadline) => {
+                    self.sleep_timer = deadline;
+                    ctx.request_repaint();
+                }
+                AppMessage::ProfileChanged(name) => {
+                    self.config.active_profile = name;
+                    self.profile_rename = None;
+                    ctx.request_repaint();
+                }
+                AppMessage::WindowRule(description) => {
+                    self.window_rule = description;
+                    ctx.request_repaint();
+                }
+                AppMessage::Enabled(enabled) => {
+                    self.enabled = enabled;
+                    ctx.request_repaint();
+                }
+                AppMessage::NowPlaying(now_playing) => {
+                    self.now_playing = now_playing;
+                    ctx.request_repaint();
+                }
+                AppMessage::ConfigUpdated(config) => {
+                    info!("Configuration file changed, reloading");
+                    self.history.reset(&config);
+                    self.config = config;
+                    self.config_error = None;
+                    self.profile_rename = None;
+                    ctx.request_repaint();
+                }
+                AppMessage::ConfigError(error) => {
+                    self.config_error = Some(error);
+                    ctx.request_repaint();
+                }
+                AppMessage::BindingStatus(statuses) => {
+                    self.binding_statuses = statuses;
+                    ctx.request_repaint();
+                }
+            }
+        }
+    }
+
+    /// Save the configuration and use it from now on
+    fn save(&mut self) -> bool {
+        self.review_changes = false;
+        if let Err(e) = self.config.save() {
+            log::error!("Failed to save config: {}", e);
+            self.config_error = Some(format!("Failed to save the configuration: {}", e));
+            return false;
+        }
+        info!("Configuration saved successfully");
+        self.history.saved(&self.config);
+        // Send updated configuration to main thread
+        let _ = self.ui_sender.send(UiMessage::ApplyConfig(self.config.clone()));
+        true
+    }
+
+    fn exit(&self) {
+        let _ = self.ui_sender.send(UiMessage::Exit);
+        std::process::exit(0);
+    }
+
+    /// Ctrl+Z, and Ctrl+Y or Ctrl+Shift+Z, unless a text field takes them
+    fn undo_shortcuts(&mut self, ctx: &egui::Context) {
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        let undo = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Z);
+        let redo = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Y);
+        let shift_redo = egui::KeyboardShortcut::new(
+            egui::Modifiers::COMMAND | egui::Modifiers::SHIFT,
+            egui::Key::Z,
+        );
+        if ctx.input_mut(|i| i.consume_shortcut(&shift_redo) || i.consume_shortcut(&redo)) {
+            self.history.redo(&mut self.config);
+        } else if ctx.input_mut(|i| i.consume_shortcut(&undo)) {
+            self.history.undo(&mut self.config);
+        }
+    }
+
+    /// What saving changes in the file
+    fn show_review(&mut self, ctx: &egui::Context) {
+        if !self.review_changes {
+            return;
+        }
+
+        let mut open = true;
+        let mut save = false;
+        egui::Window::new("💾 Review Changes")
+            .open(&mut open)
+            .collapsible(false)
+            .default_width(450.0)
+            .show(ctx, |ui| {
+                let hunks = self.history.diff(&self.config);
+                if hunks.is_empty() {
+                    ui.label("Nothing changed since the last save");
+                }
+                egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    for (index, hunk) in hunks.iter().enumerate() {
+                        if index > 0 {
+                            ui.label("⋯");
+                        }
+                        for (tag, line) in hunk {
+                            let (sign, color) = match tag {
+                                ChangeTag::Delete => ("-", egui::Color32::from_rgb(220, 90, 90)),
+                                ChangeTag::Insert => ("+", egui::Color32::from_rgb(90, 190, 90)),
+                                ChangeTag::Equal => (" ", ui.visuals().weak_text_color()),
+                            };
+                            ui.label(
+                                egui::RichText::new(format!("{} {}", sign, line))
+                                    .monospace()
+                                    .color(color),
+                            );
+                        }
+                    }
+                });
+
+                ui.add_space(5.0);
+                ui.horizontal(|ui| {
+                    save = ui.button("💾 Save").clicked();
+                    if ui.button("Cancel").clicked() {
+                        self.review_changes = false;
+                    }
+                });
+            });
+
+        if !open {
+            self.review_changes = false;
+        }
+        if save {
+            self.save();
+        }
+    }
+
+    /// Asks what to do with unsaved changes before exiting
+    fn show_exit_confirmation(&mut self, ctx: &egui::Context) {
+        if !self.confirm_exit {
+            return;
+        }
+
+        let mut choice = None;
+        egui::Window::new("Unsaved Changes")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label("Some changes are not saved yet.");
+                ui.add_space(5.0);
+                ui.horizontal(|ui| {
+                    if ui.button("💾 Save and Exit").clicked() {
+                        choice = Some(true);
+                    }
+                    if ui.button("Exit Without Saving").clicked() {
+                        choice = Some(false);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        self.confirm_exit = false;
+                    }
+                });
+            });
+
+        match choice {
+            Some(true) => {
+                // A failed save is shown in the window instead
+                self.confirm_exit = false;
+                if self.save() {
+                    self.exit();
+                }
+            }
+            Some(false) => self.exit(),
+            None => {}
+        }
+    }
+
+    /// Run an action from the UI, recording it if a macro is being recorded
+    fn test_action(&mut self, key: Key, action: MediaAction, time: f64) {
+        if let Some(editor) = &mut self.macro_editor {
+            editor.record(&action, time);
+        }
+        let _ = self.ui_sender.send(UiMessage::TestAction(key, action));
+    }
+
+    /// Make another profile active, in the UI and for the listener
+    fn switch_profile(&mut self, name: String) {
+        self.config.active_profile = name.clone();
+        self.profile_rename = None;
+        let _ = self.ui_sender.send(UiMessage::SwitchProfile(name));
+    }
+
+    /// Profile selection, creation, renaming and removal
+    fn profile_bar(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Profile:");
+
+            if let Some(new_name) = &mut self.profile_rename {
+                let response = ui.add(egui::TextEdit::singleline(new_name).desired_width(120.0));
+                let enter = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if ui.button("✔").clicked() || enter {
+                    let new_name = new_name.trim().to_string();
+                    self.profile_rename = None;
+                    rename_profile(&mut self.config, &new_name);
+                }
+                if ui.button("✖").clicked() {
+                    self.profile_rename = None;
+                }
+                return;
+            }
+
+            let mut selected = None;
+            egui::ComboBox::from_id_salt("profile")
+                .selected_text(&self.config.active_profile)
+                .show_ui(ui, |ui| {
+                    for name in self.config.profiles.keys() {
+                        if ui
+                            .selectable_label(*name == self.config.active_profile, name)
+                            .clicked()
+                        {
+                            selected = Some(name.clone());
+                        }
+                    }
+                });
+            if let Some(name) = selected.filter(|name| *name != self.config.active_profile) {
+                self.switch_profile(name);
+            }
+
+            if ui.button("➕").on_hover_text("New profile with the bindings of this one").clicked() {
+                let name = (2..)
+                    .map(|n| format!("Profile {}", n))
+                    .find(|name| !self.config.profiles.contains_key(name))
+                    .unwrap();
+                let profile = self.config.active().clone();
+                self.config.profiles.insert(name.clone(), profile);
+                // The listener knows the profile after saving
+                self.config.active_profile = name;
+            }
+
+            if ui.button("✏").on_hover_text("Rename profile").clicked() {
+                self.profile_rename = Some(self.config.active_profile.clone());
+            }
+
+            if ui
+                .add_enabled(self.config.profiles.len() > 1, egui::Button::new("🗑"))
+                .on_hover_text("Delete profile")
+                .clicked()
+            {
+                self.config.profiles.remove(&self.config.active_profile);
+                if let Some(name) = self.config.profiles.keys().next().cloned() {
+                    self.switch_profile(name);
+                }
+            }
+        });
+    }
+
+    fn show_macro_editor(&mut self, ctx: &egui::Context) {
+        let Some(editor) = &mut self.macro_editor else {
+            return;
+        };
+
+        // Keep the recording indicator and timing up to date
+        if editor.is_recording() {
+            ctx.request_repaint_after(std::time::Duration::from_millis(200));
+        }
+
+        let bound = self.config.active().hotkeys.get(&editor.key()).cloned();
+        match editor.show(ctx, bound.as_ref()) {
+            EditorResult::Open => {}
+            EditorResult::Save(key, steps) => {
+                info!("Binding recorded macro to {:?}", key);
+                self.config.active_mut().hotkeys.insert(key, MediaAction::Macro(steps));
+                self.macro_editor = None;
+            }
+            EditorResult::Close => self.macro_editor = None,
+        }
+    }
+}
+
+impl eframe::App for HotkeyMapperApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // Process messages from main thread
+        self.process_app_messages(ctx);
+        self.undo_shortcuts(ctx);
+
+        // Closing the window asks about unsaved changes first
+        if ctx.input(|i| i.viewport().close_requested()) && self.history.is_dirty(&self.config) {
+            ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            self.confirm_exit = true;
+        }
+        self.show_review(ctx);
+        self.show_exit_confirmation(ctx);
+
+        self.show_macro_editor(ctx);
+        if let Some(dialog) = &mut self.bindings_dialog {
+            if !dialog.show(ctx, &mut self.config) {
+                self.bindings_dialog = None;
+            }
+        }
+
+        // Main panel
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.heading("🎵 Music Hotkey Mapper");
+            ui.add_space(10.0);
+
+            // Status
+            ui.horizontal(|ui| {
+                ui.label("Status:");
+                if self.enabled {
+                    ui.colored_label(egui::Color32::GREEN, "● Active");
+                    if ui.small_button("⏸ Pause").clicked() {
+                        let _ = self.ui_sender.send(UiMessage::SetEnabled(false));
+                    }
+                } else {
+                    ui.colored_label(egui::Color32::GRAY, "⏸ Paused");
+                    if ui.small_button("▶ Resume").clicked() {
+                        let _ = self.ui_sender.send(UiMessage::SetEnabled(true));
+                    }
+                }
+            });
+
+            // Configuration file in use
+            ui.horizontal(|ui| {
+                ui.label("Config:");
+                ui.label(egui::RichText::new(&self.config_path).monospace());
+                if Config::is_portable() {
+                    ui.colored_label(egui::Color32::from_rgb(120, 180, 220), "portable")
+                        .on_hover_text("Kept next to the executable");
+                }
+                if self.history.is_dirty(&self.config) {
+                    ui.colored_label(egui::Color32::from_rgb(220, 160, 60), "● unsaved changes");
+                }
+            });
+
+            // Window rule that is applied right now
+            if let Some(description) = &self.window_rule {
+                ui.horizontal(|ui| {
+                    ui.label("Window rule:");
+                    ui.colored_label(egui::Color32::from_rgb(220, 180, 80), description);
+                });
+            }
+
+            // Sleep timer countdown
+            if let Some(deadline) = self.sleep_timer {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                ui.horizontal(|ui| {
+                    ui.label("Sleep timer:");
+                    ui.colored_label(
+                        egui::Color32::from_rgb(180, 140, 220),
+                        format!("{} left", format_duration(remaining)),
+                    );
+                    if ui.small_button("Cancel").clicked() {
+                        let _ = self.ui_sender.send(UiMessage::CancelSleepTimer);
+                    }
+                });
+                ctx.request_repaint_after(std::time::Duration::from_secs(1));
+            }
+
+            ui.add_space(5.0);
+
+            // Show last key press
+            if let Some((key, action, time)) = &self.last_pressed_info {
+                let current_time = ui.input(|i| i.time);
+                if current_time - *time < 3.0 {
+                    ui.horizontal(|ui| {
+                        ui.label("Last action:");
+                        ui.colored_label(
+                            egui::Color32::from_rgb(100, 200, 100),
+                            format!("{:?} → {:?}", key, action),
+                        );
+                    });
+                }
+            }
+
+            // Show the progress of the last macro
+            if let Some((trigger, progress, time)) = &self.macro_progress {
+                let current_time = ui.input(|i| i.time);
+                let text = match progress {
+                    MacroProgress::Step {
+                        index,
+                        total,
+                        description,
+                    } => Some(format!("{}: step {}/{} – {}", trigger, index + 1, total, description)),
+                    // Keep the outcome visible for a moment
+                    MacroProgress::Finished if current_time - *time < 3.0 => {
+                        Some(format!("{}: finished", trigger))
+                    }
+                    MacroProgress::Cancelled if current_time - *time < 3.0 => {
+                        Some(format!("{}: cancelled", trigger))
+                    }
+                    _ => None,
+                };
+                if let Some(text) = text {
+                    ui.horizontal(|ui| {
+                        ui.label("Macro:");
+                        ui.colored_label(egui::Color32::from_rgb(100, 150, 220), text);
+                    });
+                }
+            }
+
+            // Show the last script error until it is dismissed
+            let mut dismiss_script_error = false;
+            if let Some((trigger, error)) = &self.script_error {
+                ui.horizontal(|ui| {
+                    ui.colored_label(egui::Color32::RED, format!("Script {}: {}", trigger, error));
+                    dismiss_script_error = ui.small_button("✖").on_hover_text("Dismiss").clicked();
+                });
+            }
+            if dismiss_script_error {
+                self.script_error = None;
+            }
+
+            // Show why the configuration file was not applied until it is dismissed
+            let mut dismiss_config_error = false;
+            if let Some(error) = &self.config_error {
+                ui.horizontal(|ui| {
+                    ui.colored_label(egui::Color32::RED, error);
+                    dismiss_config_error = ui.small_button("✖").on_hover_text("Dismiss").clicked();
+                });
+            }
+            if dismiss_config_error {
+                self.config_error = None;
+            }
+
+            // Show the current track
+            if let Some(now_playing) = &self.now_playing {
+                ui.horizontal(|ui| {
+                    ui.label(if now_playing.playing { "Now playing:" } else { "Paused:" });
+                    if now_playing.artist.is_empty() {
+                        ui.label(&now_playing.title);
+                    } else {
+                        ui.label(format!("{} – {}", now_playing.artist, now_playing.title));
+                    }
+                });
+            }
+
+            ui.add_space(10.0);
+            ui.separator();
+            ui.add_space(10.0);
+
+            // Hotkey settings
+            ui.heading("Hotkey Settings");
+            ui.add_space(10.0);
+
+            self.profile_bar(ui);
+            ui.add_space(5.0);
+            let profiles: Vec<String> = self.config.profiles.keys().cloned().collect();
+
+            // Hotkeys table
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("hotkeys_grid")
+                    .num_columns(3)
+                    .spacing([10.0, 8.0])
+                    .striped(true)
+                    .show(ui, |ui| {
+                        // Headers
+                        ui.label(egui::RichText::new("Key").strong());
+                        ui.label(egui::RichText::new("Action").strong());
+                        ui.label(egui::RichText::new("Control").strong());
+                        ui.end_row();
+
+                        // List of hotkeys
+                        let mut keys_to_remove = Vec::new();
+                        let mut keys_to_update = Vec::new();
+                        let mut keys_to_test = Vec::new();
+                        let mut macro_to_edit = None;
+                        let mut refresh_audio = false;
+                        let mut hotkeys: Vec<_> = self.config.active().hotkeys.iter().collect();
+                        hotkeys.sort_by_key(|(k, _)| format!("{:?}", k));
+
+                        for (key, action) in hotkeys {
+                            ui.horizontal(|ui| {
+                                ui.label(format!("{:?}", key));
+                                binding_status(ui, self.binding_statuses.get(key));
+                            });
+
+                            // Action selection
+                            let mut current_action = action.clone();
+                            ui.horizontal(|ui| {
+                                if action_editor(ui, egui::Id::new(key), &mut current_action, &self.audio, &profiles) {
+                                    refresh_audio = true;
+                                }
+                            });
+
+                            // If action changed, remember for update
+                            if current_action != *action {
+                                keys_to_update.push((*key, current_action));
+                            }
+
+                            ui.horizontal(|ui| {
+                                // Test button
+                                if ui.button("▶").on_hover_text("Run now").clicked() {
+                                    keys_to_test.push((*key, action.clone()));
+                                }
+
+                                if let MediaAction::Macro(steps) = action {
+                                    if ui.button("✏").on_hover_text("Edit macro").clicked() {
+                                        macro_to_edit = Some(MacroEditor::new(*key, steps.clone()));
+                                    }
+                                }
+
+                                // Delete button
+                                if ui.button("❌").clicked() {
+                                    keys_to_remove.push(*key);
+                                }
+                            });
+
+                            ui.end_row();
+                        }
+
+                        let time = ui.input(|i| i.time);
+                        for (key, action) in keys_to_test {
+                            self.test_action(key, action, time);
+                        }
+
+                        if macro_to_edit.is_some() {
+                            self.macro_editor = macro_to_edit;
+                        }
+
+                        // Apply changes
+                        for (key, action) in keys_to_update {
+                            self.config.active_mut().hotkeys.insert(key, action);
+                        }
+
+                        // Remove hotkeys
+                        for key in keys_to_remove {
+                            self.config.active_mut().hotkeys.remove(&key);
+                        }
+
+                        if refresh_audio {
+                            self.audio = AudioLists::load();
+                        }
+                    });
+            });
+
+            ui.add_space(10.0);
+
+            // Add new hotkey button
+            if ui.button("➕ Add Hotkey").clicked() {
+                info!("Add hotkey button clicked");
+                // Could add dialog for adding new hotkey
+                // For now just add an example
+                self.config
+                    .active_mut()
+                    .hotkeys
+                    .entry(Key::F1)
+                    .or_insert(MediaAction::PlayPause);
+            }
+
+            if ui
+                .add_enabled(self.macro_editor.is_none(), egui::Button::new("⏺ Record Macro"))
+                .clicked()
+            {
+                // Suggest the first free key
+                let key = SUPPORTED_KEYS
+                    .into_iter()
+                    .find(|key| !self.config.active().hotkeys.contains_key(key))
+                    .unwrap_or(Key::F1);
+                self.macro_editor = Some(MacroEditor::new(key, Vec::new()));
+            }
+
+            ui.add_space(10.0);
+
+            egui::CollapsingHeader::new("🔊 Output Devices").show(ui, |ui| {
+                if output_devices_editor(ui, &mut self.config.output_devices, &self.audio) {
+                    self.audio = AudioLists::load();
+                }
+            });
+
+            egui::CollapsingHeader::new("🎛 Player Backend").show(ui, |ui| {
+                ui.label(format!("Used by the profile '{}'", self.config.active_profile));
+                backend_editor(ui, &mut self.config.active_mut().backend);
+            });
+
+            egui::CollapsingHeader::new("⏰ Schedule").show(ui, |ui| {
+                if schedule_editor(ui, &mut self.config.schedule, &self.audio, &profiles) {
+                    self.audio = AudioLists::load();
+                }
+            });
+
+            egui::CollapsingHeader::new("🪟 Window Rules").show(ui, |ui| {
+                window_rules_editor(ui, &mut self.config.window_rules, &profiles);
+            });
+
+            ui.add_space(10.0);
+            ui.separator();
+            ui.add_space(10.0);
+
+            // Control buttons
+            ui.horizontal(|ui| {
+                if ui.button("💾 Save").clicked() {
+                    info!("Save button clicked");
+                    if self.history.is_dirty(&self.config) {
+                        self.review_changes = true;
+                    } else {
+                        self.save();
+                    }
+                }
+
+                if ui
+                    .add_enabled(self.history.can_undo(), egui::Button::new("↶"))
+                    .on_hover_text("Undo (Ctrl+Z)")
+                    .clicked()
+                {
+                    self.history.undo(&mut self.config);
+                }
+                if ui
+                    .add_enabled(self.history.can_redo(), egui::Button::new("↷"))
+                    .on_hover_text("Redo (Ctrl+Y)")
+                    .clicked()
+                {
+                    self.history.redo(&mut self.config);
+                }
+
+                if ui.button("🔄 Reload").clicked() {
+                    info!("Reload button clicked");
+                    match Config::load() {
+                        Ok(mut config) => {
+                            config.keep_active_profile(&self.config.active_profile);
+                            self.history.reset(&config);
+                            self.config = config;
+                            self.config_error = None;
+                            info!("Configuration reloaded");
+                            // The listener uses the reloaded bindings too
+                            let _ = self.ui_sender.send(UiMessage::ApplyConfig(self.config.clone()));
+                        }
+                        Err(e) => {
+                            log::error!("Failed to reload config: {}", e);
+                            self.config_error = Some(format!("Failed to reload the configuration: {}", e));
+                        }
+                    }
+                }
+
+                ui.menu_button("⏪ Restore Backup", |ui| {
+                    let backups = Config::backups();
+                    if backups.is_empty() {
+                        ui.label("No backups yet, they are made when saving");
+                    }
+                    for backup in backups {
+                        let modified = backup
+                            .modified
+                            .and_then(|time| jiff::Timestamp::try_from(time).ok())
+                            .map(|time| time.to_zoned(jiff::tz::TimeZone::system()).strftime("%Y-%m-%d %H:%M:%S").to_string())
+                            .unwrap_or_default();
+                        if ui
+                            .button(format!("#{}  {}", backup.number, modified))
+                            .on_hover_text(backup.path.display().to_string())
+                            .clicked()
+                        {
+                            info!("Restoring {}", backup.path.display());
+                            match Config::restore(&backup) {
+                                Ok(config) => {
+                                    self.history.reset(&config);
+                                    self.config = config;
+                                    self.config_error = None;
+                                    let _ = self.ui_sender.send(UiMessage::ApplyConfig(self.config.clone()));
+                                }
+                                Err(e) => {
+                                    log::error!("Failed to restore {}: {}", backup.path.display(), e);
+                                    self.config_error = Some(format!("Failed to restore backup #{}: {}", backup.number, e));
+                                }
+                            }
+                            ui.close_menu();
+                        }
+                    }
+                });
+
+                if ui.button("📤 Export").on_hover_text("Share bindings of this profile").clicked() {
+                    self.bindings_dialog = Some(BindingsDialog::export(&self.config));
+                }
+                if ui.button("📥 Import").on_hover_text("Add shared bindings to this profile").clicked() {
+                    self.bindings_dialog = Some(BindingsDialog::import(&self.config));
+                }
+
+                if ui.button("🔍 Check").on_hover_text("Look for mistakes in the configuration").clicked() {
+                    self.check_findings = Some(self.config.check());
+                }
+
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if ui.button("❌ Exit").clicked() {
+                        info!("Exit button clicked");
+                        if self.history.is_dirty(&self.config) {
+                            self.confirm_exit = true;
+                        } else {
+                            self.exit();
+                        }
+                    }
+                });
+            });
+
+            // Findings of the last check
+            let mut dismiss_findings = false;
+            if let Some(findings) = &self.check_findings {
+                ui.add_space(10.0);
+                ui.group(|ui| {
+                    ui.horizontal(|ui| {
+                        ui.label(egui::RichText::new("🔍 Check").strong());
+                        dismiss_findings = ui.small_button("✖").on_hover_text("Dismiss").clicked();
+                    });
+                    if findings.is_empty() {
+                        ui.colored_label(egui::Color32::GREEN, "No problems found");
+                    }
+                    for finding in findings {
+                        let color = match finding.severity {
+                            Severity::Error => egui::Color32::RED,
+                            Severity::Warning => egui::Color32::YELLOW,
+                        };
+                        ui.colored_label(color, finding.to_string());
+                    }
+                });
+            }
+            if dismiss_findings {
+                self.check_findings = None;
+            }
+
+            ui.add_space(10.0);
+
+            // Instructions
+            ui.group(|ui| {
+                ui.label(egui::RichText::new("ℹ Instructions:").strong());
+                ui.label("• Configure the desired keys and actions");
+                ui.label("• Click 'Save' to review and apply changes, Ctrl+Z undoes an edit");
+                ui.label("• '⏺ Record Macro' records the actions of pressed hotkeys and ▶ buttons");
+                ui.label("• The application works in the background and intercepts key presses");
+            });
+        });
+
+        // Everything edited in this frame
+        let time = ctx.input(|i| i.time);
+        self.history.record(&self.config, time, ctx.wants_keyboard_input());
+    }
+}
+
+/// Warning icon for a binding whose key doesn't work as expected
+fn binding_status(ui: &mut egui::Ui, status: Option<&BindingStatus>) {
+    match status {
+        Some(BindingStatus::Failed(reason)) => {
+            ui.colored_label(egui::Color32::RED, "⚠")
+                .on_hover_text(format!("Not registered: {}", reason));
+        }
+        Some(BindingStatus::Shadowed(other)) => {
+            ui.colored_label(egui::Color32::YELLOW, "⚠")
+                .on_hover_text(format!("Also used by {}", other));
+        }
+        Some(BindingStatus::Registered) | None => {}
+    }
+}
+
+/// Short name of an action for the selection list
+fn action_name(action: &MediaAction) -> &'static str {
+    match action {
+        MediaAction::PlayPause => "Play/Pause",
+        MediaAction::Next => "Next",
+        MediaAction::Previous => "Previous",
+        MediaAction::Stop => "Stop",
+        MediaAction::VolumeUp => "Volume Up",
+        MediaAction::VolumeDown => "Volume Down",
+        MediaAction::AppVolume { .. } => "App Volume",
+        MediaAction::AppMute { .. } => "App Mute",
+        MediaAction::CycleOutputDevice => "Cycle Output Device",
+        MediaAction::SetOutputDevice(_) => "Set Output Device",
+        MediaAction::Seek(_) => "Seek",
+        MediaAction::SpeedUp => "Speed Up",
+        MediaAction::SpeedDown => "Speed Down",
+        MediaAction::ChapterNext => "Next Chapter",
+        MediaAction::ChapterPrevious => "Previous Chapter",
+        MediaAction::ToggleSubtitles => "Toggle Subtitles",
+        MediaAction::RunCommand { .. } => "Run Command",
+        MediaAction::Macro(_) => "Macro",
+        MediaAction::Script(_) => "Script",
+        MediaAction::SleepTimer(_) => "Sleep Timer",
+        MediaAction::CancelSleepTimer => "Cancel Sleep Timer",
+        MediaAction::SwitchProfile(_) => "Switch Profile",
+        MediaAction::CycleProfile => "Cycle Profile",
+        MediaAction::ToggleEnabled => "Pause/Resume Hotkeys",
+    }
+}
+
+/// Action selection and parameters of the selected action, `id` tells the rows apart.
+/// Returns true if the audio lists should be refreshed.
+fn action_editor(
+    ui: &mut egui::Ui,
+    id: egui::Id,
+    action: &mut MediaAction,
+    audio: &AudioLists,
+    profiles: &[String],
+) -> bool {
+    // Keep the application when switching between per-application actions
+    let current_app = match action {
+        MediaAction::AppVolume { app, .. } | MediaAction::AppMute { app } => app.clone(),
+        _ => String::new(),
+    };
+
+    egui::ComboBox::from_id_salt(id.with("action"))
+        .selected_text(action_name(action))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(action, MediaAction::PlayPause, "Play/Pause");
+            ui.selectable_value(action, MediaAction::Next, "Next");
+            ui.selectable_value(action, MediaAction::Previous, "Previous");
+            ui.selectable_value(action, MediaAction::Stop, "Stop");
+            ui.selectable_value(action, MediaAction::VolumeUp, "Volume Up");
+            ui.selectable_value(action, MediaAction::VolumeDown, "Volume Down");
+
+            let is_app_volume = matches!(action, MediaAction::AppVolume { .. });
+            if ui.selectable_label(is_app_volume, "App Volume").clicked() && !is_app_volume {
+                *action = MediaAction::AppVolume {
+                    app: current_app.clone(),
+                    step: 5,
+                };
+            }
+
+            let is_app_mute = matches!(action, MediaAction::AppMute { .. });
+            if ui.selectable_label(is_app_mute, "App Mute").clicked() && !is_app_mute {
+                *action = MediaAction::AppMute {
+                    app: current_app.clone(),
+                };
+            }
+
+            ui.selectable_value(action, MediaAction::CycleOutputDevice, "Cycle Output Device");
+
+            let is_set_output = matches!(action, MediaAction::SetOutputDevice(_));
+            if ui.selectable_label(is_set_output, "Set Output Device").clicked() && !is_set_output {
+                let first = audio.output_devices.first().map(|device| device.name.clone());
+                *action = MediaAction::SetOutputDevice(first.unwrap_or_default());
+            }
+
+            ui.separator();
+            ui.label("mpv only:");
+
+            let is_seek = matches!(action, MediaAction::Seek(_));
+            if ui.selectable_label(is_seek, "Seek").clicked() && !is_seek {
+                *action = MediaAction::Seek(10.0);
+            }
+            ui.selectable_value(action, MediaAction::SpeedUp, "Speed Up");
+            ui.selectable_value(action, MediaAction::SpeedDown, "Speed Down");
+            ui.selectable_value(action, MediaAction::ChapterNext, "Next Chapter");
+            ui.selectable_value(action, MediaAction::ChapterPrevious, "Previous Chapter");
+            ui.selectable_value(action, MediaAction::ToggleSubtitles, "Toggle Subtitles");
+
+            let is_sleep_timer = matches!(action, MediaAction::SleepTimer(_));
+            if ui.selectable_label(is_sleep_timer, "Sleep Timer").clicked() && !is_sleep_timer {
+                *action = MediaAction::SleepTimer(15);
+            }
+            ui.selectable_value(action, MediaAction::CancelSleepTimer, "Cancel Sleep Timer");
+
+            let is_switch_profile = matches!(action, MediaAction::SwitchProfile(_));
+            if ui.selectable_label(is_switch_profile, "Switch Profile").clicked() && !is_switch_profile {
+                *action = MediaAction::SwitchProfile(profiles.first().cloned().unwrap_or_default());
+            }
+            ui.selectable_value(action, MediaAction::CycleProfile, "Cycle Profile");
+            ui.selectable_value(action, MediaAction::ToggleEnabled, "Pause/Resume Hotkeys");
+
+            ui.separator();
+
+            let is_command = matches!(action, MediaAction::RunCommand { .. });
+            if ui.selectable_label(is_command, "Run Command").clicked() && !is_command {
+                *action = MediaAction::RunCommand {
+                    program: String::new(),
+                    args: Vec::new(),
+                    env: Default::default(),
+                    cwd: None,
+                    timeout: 30,
+                    shell: false,
+                };
+            }
+
+            let is_macro = matches!(action, MediaAction::Macro(_));
+            if ui.selectable_label(is_macro, "Macro").clicked() && !is_macro {
+                *action = MediaAction::Macro(Vec::new());
+            }
+
+            let is_script = matches!(action, MediaAction::Script(_));
+            if ui.selectable_label(is_script, "Script").clicked() && !is_script {
+                *action = MediaAction::Script(ScriptSource::File("script.rhai".into()));
+            }
+        });
+
+    let mut refresh_audio = false;
+
+    match action {
+        MediaAction::AppVolume { app, step } => {
+            refresh_audio = app_picker(ui, id, app, audio);
+            ui.add(egui::DragValue::new(step).range(-100..=100).suffix("%"));
+        }
+        MediaAction::AppMute { app } => {
+            refresh_audio = app_picker(ui, id, app, audio);
+        }
+        MediaAction::SetOutputDevice(name) => {
+            refresh_audio = device_picker(ui, id, name, audio);
+        }
+        MediaAction::Seek(seconds) => {
+            ui.add(egui::DragValue::new(seconds).range(-3600.0..=3600.0).suffix(" s"));
+        }
+        MediaAction::SwitchProfile(name) => {
+            egui::ComboBox::from_id_salt(id.with("profile"))
+                .selected_text(name.as_str())
+                .show_ui(ui, |ui| {
+                    for profile in profiles {
+                        ui.selectable_value(name, profile.clone(), profile);
+                    }
+                });
+        }
+        MediaAction::SleepTimer(minutes) => {
+            ui.add(egui::DragValue::new(minutes).range(1..=600).prefix("+").suffix(" min"));
+        }
+        MediaAction::Macro(steps) => {
+            ui.label(format!("{} steps", steps.len()));
+        }
+        MediaAction::Script(source) => {
+            let is_file = matches!(source, ScriptSource::File(_));
+            egui::ComboBox::from_id_salt(id.with("script"))
+                .selected_text(if is_file { "File" } else { "Inline" })
+                .width(60.0)
+                .show_ui(ui, |ui| {
+                    if ui.selectable_label(is_file, "File").clicked() && !is_file {
+                        *source = ScriptSource::File("script.rhai".into());
+                    }
+                    if ui.selectable_label(!is_file, "Inline").clicked() && is_file {
+                        *source = ScriptSource::Inline(String::new());
+                    }
+                });
+
+            match source {
+                ScriptSource::File(path) => {
+                    let mut text = path.display().to_string();
+                    if ui
+                        .add(egui::TextEdit::singleline(&mut text).desired_width(150.0))
+                        .on_hover_text("Relative paths start in the configuration directory")
+                        .changed()
+                    {
+                        *path = text.into();
+                    }
+                }
+                ScriptSource::Inline(code) => {
+                    ui.add(
+                        egui::TextEdit::singleline(code)
+                            .desired_width(150.0)
+                            .code_editor()
+                            .hint_text("if volume() < 50 { set_volume(50) }"),
+                    );
+                }
+            }
+        }
+        MediaAction::RunCommand { program, args, .. } => {
+            ui.add(egui::TextEdit::singleline(program).desired_width(90.0).hint_text("program"));
+
+            // Arguments are edited as one line, quotes keep spaces inside an argument.
+            // The line is kept while editing, re-joining would eat trailing spaces.
+            let id = id.with("command_args");
+            let mut line = ui
+                .data_mut(|data| data.get_temp::<String>(id))
+                .unwrap_or_else(|| join_args(args));
+            let response = ui
+                .add(egui::TextEdit::singleline(&mut line).desired_width(120.0).hint_text("{key} {title}"))
+                .on_hover_text("Placeholders: {key} {action} {title} {artist} {album} {status}");
+            if response.changed() {
+                *args = split_args(&line);
+            }
+            if response.has_focus() {
+                ui.data_mut(|data| data.insert_temp(id, line));
+            } else {
+                ui.data_mut(|data| data.remove::<String>(id));
+            }
+        }
+        _ => {}
+    }
+
+    refresh_audio
+}
+
+/// Application name input with a list of currently playing streams.
+/// Returns true if the list should be refreshed.
+fn app_picker(ui: &mut egui::Ui, id: egui::Id, app: &mut String, audio: &AudioLists) -> bool {
+    ui.add(egui::TextEdit::singleline(app).desired_width(90.0).hint_text("application"));
+
+    let mut refresh = false;
+    egui::ComboBox::from_id_salt(id.with("app"))
+        .selected_text("")
+        .width(20.0)
+        .show_ui(ui, |ui| {
+            if audio.app_streams.is_empty() {
+                empty_list(ui, "No playing applications", audio);
+            }
+            for stream in &audio.app_streams {
+                let label = if stream.media_name.is_empty() {
+                    stream.name.clone()
+                } else {
+                    format!("{} ({})", stream.name, stream.media_name)
+                };
+                if ui.selectable_label(*app == stream.name, label).clicked() {
+                    *app = stream.name.clone();
+                }
+            }
+            ui.separator();
+            if ui.button("🔄 Refresh").clicked() {
+                refresh = true;
+            }
+        });
+
+    refresh
+}
+
+/// Label of an empty audio list, or why it couldn't be loaded
+fn empty_list(ui: &mut egui::Ui, empty: &str, audio: &AudioLists) {
+    match &audio.unavailable {
+        Some(reason) => ui.colored_label(egui::Color32::RED, format!("⚠ {}", reason)),
+        None => ui.label(empty),
+    };
+}
+
+/// Output device selection. Returns true if the list should be refreshed.
+fn device_picker(ui: &mut egui::Ui, id: egui::Id, name: &mut String, audio: &AudioLists) -> bool {
+    let devices = &audio.output_devices;
+    let selected = devices
+        .iter()
+        .find(|device| device.name == *name)
+        .map(|device| device.description.clone())
+        .unwrap_or_else(|| name.clone());
+
+    let mut refresh = false;
+    egui::ComboBox::from_id_salt(id.with("device"))
+        .selected_text(selected)
+        .width(180.0)
+        .show_ui(ui, |ui| {
+            if devices.is_empty() {
+                empty_list(ui, "No output devices found", audio);
+            }
+            for device in devices {
+                ui.selectable_value(name, device.name.clone(), &device.description);
+            }
+            ui.separator();
+            if ui.button("🔄 Refresh").clicked() {
+                refresh = true;
+            }
+        });
+
+    refresh
+}
+
+/// Output devices with their cycle order for `CycleOutputDevice`.
+/// Returns true if the device list should be refreshed.
+fn output_devices_editor(
+    ui: &mut egui::Ui,
+    settings: &mut OutputDeviceSettings,
+    audio: &AudioLists,
+) -> bool {
+    let devices = &audio.output_devices;
+    let description = |name: &str| {
+        devices
+            .iter()
+            .find(|device| device.name == name)
+            .map(|device| device.description.clone())
+    };
+
+    ui.label("Devices checked below are switched through in this order (none checked = all devices):");
+
+    let mut move_up = None;
+    let mut move_down = None;
+    let mut remove = None;
+
+    // Devices in the cycle, including ones that are not connected right now
+    for (index, name) in settings.cycle.iter().enumerate() {
+        ui.horizontal(|ui| {
+            let mut checked = true;
+            let label = description(name).unwrap_or_else(|| format!("{} (not connected)", name));
+            if ui.checkbox(&mut checked, label).changed() {
+                remove = Some(index);
+            }
+            if ui.add_enabled(index > 0, egui::Button::new("⬆")).clicked() {
+                move_up = Some(index);
+            }
+            if ui
+                .add_enabled(index + 1 < settings.cycle.len(), egui::Button::new("⬇"))
+                .clicked()
+            {
+                move_down = Some(index);
+            }
+        });
+    }
+
+    if let Some(index) = move_up {
+        settings.cycle.swap(index, index - 1);
+    }
+    if let Some(index) = move_down {
+        settings.cycle.swap(index, index + 1);
+    }
+    if let Some(index) = remove {
+        settings.cycle.remove(index);
+    }
+
+    // Available devices that are not in the cycle
+    for device in devices {
+        if settings.cycle.contains(&device.name) {
+            continue;
+        }
+        let mut checked = false;
+        if ui.checkbox(&mut checked, &device.description).changed() {
+            settings.cycle.push(device.name.clone());
+        }
+    }
+
+    if devices.is_empty() {
+        empty_list(ui, "No output devices found", audio);
+    }
+
+    ui.checkbox(&mut settings.move_streams, "Move playing streams to the new device");
+
+    ui.button("🔄 Refresh devices").clicked()
+}
+
+/// Give the active profile a new name, updating the actions that switch to it
+fn rename_profile(config: &mut Config, new_name: &str) {
+    let old_name = config.active_profile.clone();
+    if new_name.is_empty() || new_name == old_name || config.profiles.contains_key(new_name) {
+        return;
+    }
+
+    if let Some(profile) = config.profiles.remove(&old_name) {
+        config.profiles.insert(new_name.to_string(), profile);
+    }
+    config.active_profile = new_name.to_string();
+
+    let renamed = |action: &mut MediaAction| {
+        if let MediaAction::SwitchProfile(name) = action {
+            if *name == old_name {
+                *name = new_name.to_string();
+            }
+        }
+    };
+    config
+        .profiles
+        .values_mut()
+        .flat_map(|profile| profile.hotkeys.values_mut())
+        .for_each(renamed);
+    config
+        .schedule
+        .iter_mut()
+        .for_each(|entry| renamed(&mut entry.action));
+
+    for rule in &mut config.window_rules {
+        if rule.target == RuleTarget::Profile(old_name.clone()) {
+            rule.target = RuleTarget::Profile(new_name.to_string());
+        }
+    }
+}
+
+/// Actions that run at set times.
+/// Returns true if the audio lists should be refreshed.
+fn schedule_editor(
+    ui: &mut egui::Ui,
+    schedule: &mut Vec<ScheduledAction>,
+    audio: &AudioLists,
+    profiles: &[String],
+) -> bool {
+    let mut refresh_audio = false;
+    let mut remove = None;
+
+    for (index, entry) in schedule.iter_mut().enumerate() {
+        let id = egui::Id::new(("schedule", index));
+        ui.horizontal(|ui| {
+            when_editor(ui, id, &mut entry.when);
+            ui.label("→");
+            if action_editor(ui, id, &mut entry.action, audio, profiles) {
+                refresh_audio = true;
+            }
+            if ui.button("❌").clicked() {
+                remove = Some(index);
+            }
+        });
+
+        if let Err(e) = CronExpr::try_from(&entry.when) {
+            ui.colored_label(egui::Color32::RED, e);
+        }
+    }
+
+    if let Some(index) = remove {
+        schedule.remove(index);
+    }
+
+    if ui.button("➕ Add Scheduled Action").clicked() {
+        schedule.push(ScheduledAction {
+            when: Schedule::Daily {
+                time: "23:00".to_string(),
+                days: Vec::new(),
+            },
+            action: MediaAction::Stop,
+        });
+    }
+
+    refresh_audio
+}
+
+/// Profiles or disabled hotkeys while certain windows are focused
+fn window_rules_editor(ui: &mut egui::Ui, rules: &mut Vec<WindowRule>, profiles: &[String]) {
+    ui.label("The first rule matching the focused window applies (X11 only)");
+    let mut remove = None;
+
+    for (index, rule) in rules.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.label("Class:");
+            ui.add(egui::TextEdit::singleline(&mut rule.class).desired_width(100.0).hint_text("any"))
+                .on_hover_text("WM_CLASS of the window, see `xprop WM_CLASS`");
+            ui.label("Title contains:");
+            ui.add(egui::TextEdit::singleline(&mut rule.title).desired_width(120.0).hint_text("any"));
+            ui.label("→");
+
+            let selected = match &rule.target {
+                RuleTarget::Profile(name) => name.as_str(),
+                RuleTarget::Disable => "Disable hotkeys",
+            };
+            egui::ComboBox::from_id_salt(("window_rule", index))
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    for profile in profiles {
+                        ui.selectable_value(&mut rule.target, RuleTarget::Profile(profile.clone()), profile);
+                    }
+                    ui.selectable_value(&mut rule.target, RuleTarget::Disable, "Disable hotkeys");
+                });
+
+            if ui.button("❌").clicked() {
+                remove = Some(index);
+            }
+        });
+
+        if rule.class.is_empty() && rule.title.is_empty() {
+            ui.colored_label(egui::Color32::RED, "Set a class or a title");
+        }
+    }
+
+    if let Some(index) = remove {
+        rules.remove(index);
+    }
+
+    if ui.button("➕ Add Window Rule").clicked() {
+        rules.push(WindowRule {
+            class: String::new(),
+            title: String::new(),
+            target: RuleTarget::Disable,
+        });
+    }
+}
+
+/// Daily time with its days, or a cron expression
+fn when_editor(ui: &mut egui::Ui, id: egui::Id, when: &mut Schedule) {
+    let is_daily = matches!(when, Schedule::Daily { .. });
+    egui::ComboBox::from_id_salt(id.with("when"))
+        .selected_text(if is_daily { "Daily" } else { "Cron" })
+        .width(60.0)
+        .show_ui(ui, |ui| {
+            if ui.selectable_label(is_daily, "Daily").clicked() && !is_daily {
+                *when = Schedule::Daily {
+                    time: "23:00".to_string(),
+                    days: Vec::new(),
+                };
+            }
+            if ui.selectable_label(!is_daily, "Cron").clicked() && is_daily {
+                *when = Schedule::Cron("0 23 * * 1-5".to_string());
+            }
+        });
+
+    match when {
+        Schedule::Daily { time, days } => {
+            ui.add(egui::TextEdit::singleline(time).desired_width(45.0).hint_text("HH:MM"));
+            // No days selected means every day
+            for day in Weekday::ALL {
+                let selected = days.contains(&day);
+                if ui.selectable_label(selected, format!("{:?}", day)).clicked() {
+                    if selected {
+                        days.retain(|d| *d != day);
+                    } else {
+                        days.push(day);
+                    }
+                }
+            }
+        }
+        Schedule::Cron(expression) => {
+            ui.add(egui::TextEdit::singleline(expression).desired_width(110.0).hint_text("0 23 * * 1-5"))
+                .on_hover_text("minute hour day month weekday");
+        }
+    }
+}
+
+/// Player backend selection with its connection settings
+fn backend_editor(ui: &mut egui::Ui, backend: &mut Backend) {
+    let is_mpd = matches!(backend, Backend::Mpd(_));
+    let is_mpv = matches!(backend, Backend::Mpv(_));
+    let selected = match backend {
+        Backend::System => "System",
+        Backend::Mpd(_) => "MPD",
+        Backend::Mpv(_) => "mpv",
+    };
+
+    ui.horizontal(|ui| {
+        ui.label("Backend:");
+        egui::ComboBox::from_id_salt("backend")
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(backend, Backend::System, "System");
+                if ui.selectable_label(is_mpd, "MPD").clicked() && !is_mpd {
+                    *backend = Backend::Mpd(MpdSettings::default());
+                }
+                if ui.selectable_label(is_mpv, "mpv").clicked() && !is_mpv {
+                    *backend = Backend::Mpv(MpvSettings::default());
+                }
+            });
+    });
+
+    if let Backend::Mpv(settings) = backend {
+        egui::Grid::new("mpv_settings")
+            .num_columns(2)
+            .spacing([10.0, 4.0])
+            .show(ui, |ui| {
+                ui.label("Socket:");
+                let hint = default_mpv_socket().display().to_string();
+                path_edit(ui, &mut settings.socket, &hint);
+                ui.end_row();
+
+                ui.label("Socket directory:");
+                path_edit(ui, &mut settings.socket_dir, "search for sockets of running mpv");
+                ui.end_row();
+            });
+    }
+
+    if let Backend::Mpd(settings) = backend {
+        egui::Grid::new("mpd_settings")
+            .num_columns(2)
+            .spacing([10.0, 4.0])
+            .show(ui, |ui| {
+                ui.label("Host:");
+                ui.text_edit_singleline(&mut settings.host);
+                ui.end_row();
+
+                ui.label("Port:");
+                ui.add(egui::DragValue::new(&mut settings.port));
+                ui.end_row();
+
+                ui.label("Socket:");
+                path_edit(ui, &mut settings.socket, "use host and port");
+                ui.end_row();
+
+                ui.label("Password:");
+                let mut password = settings.password.clone().unwrap_or_default();
+                if ui
+                    .add(egui::TextEdit::singleline(&mut password).password(true))
+                    .changed()
+                {
+                    settings.password = (!password.is_empty()).then_some(password);
+                }
+                ui.end_row();
+            });
+    }
+}
+
+/// Join command arguments into one line, quoting those with spaces
+fn join_args(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| {
+            if arg.is_empty() || arg.contains(char::is_whitespace) {
+                format!("\"{}\"", arg)
+            } else {
+                arg.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Split a line of arguments on whitespace outside of double quotes
+fn split_args(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut in_arg = false;
+
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_arg = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            c => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+
+    if in_arg {
+        args.push(current);
+    }
+
+    args
+}
+
+/// Text input for an optional path, empty means none
+fn path_edit(ui: &mut egui::Ui, path: &mut Option<std::path::PathBuf>, hint: &str) {
+    let mut text = path
+        .as_ref()
+        .map(|path| path.display().to_string())
+        .unwrap_or_default();
+    if ui
+        .add(egui::TextEdit::singleline(&mut text).hint_text(hint))
+        .changed()
+    {
+        *path = (!text.is_empty()).then(|| text.into());
+    }
+}
+
+/// Start UI
+pub fn run_ui(
+    config: Config,
+    ui_sender: Sender<UiMessage>,
+    app_receiver: Receiver<AppMessage>,
+) -> Result<(), eframe::Error> {
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default()
+            .with_inner_size([600.0, 500.0])
+            .with_min_inner_size([500.0, 400.0]),
+        ..Default::default()
+    };
+
+    eframe::run_native(
+        "Music Hotkey Mapper",
+        options,
+        Box::new(|_cc| Ok(Box::new(HotkeyMapperApp::new(config, ui_sender, app_receiver)))),
+    )
+}
@@ -0,0 +1,221 @@
+// Only X11 is watched so far, elsewhere the rules are never applied
+#![cfg_attr(target_os = "windows", allow(dead_code))]
+
+#[cfg(target_os = "linux")]
+mod x11;
+
+use crate::actions::ActionRunner;
+use crate::config::{Config, RuleTarget, WindowRule};
+use crate::hotkey_listener::ListenerControl;
+use crate::ui::AppMessage;
+use crossbeam_channel::Sender;
+use log::info;
+use std::sync::{Arc, Mutex};
+
+/// What is known about the focused window
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowInfo {
+    /// First part of WM_CLASS, usually the executable name
+    pub instance: String,
+    /// Second part of WM_CLASS, the application name
+    pub class: String,
+    pub title: String,
+}
+
+/// The first rule matching `window`
+fn find_rule<'a>(rules: &'a [WindowRule], window: &WindowInfo) -> Option<&'a WindowRule> {
+    rules.iter().find(|rule| {
+        // A rule without anything to match would catch every window
+        if rule.class.is_empty() && rule.title.is_empty() {
+            return false;
+        }
+        let class_matches = rule.class.is_empty()
+            || rule.class.eq_ignore_ascii_case(&window.instance)
+            || rule.class.eq_ignore_ascii_case(&window.class);
+        let title_matches = rule.title.is_empty()
+            || window
+                .title
+                .to_lowercase()
+                .contains(&rule.title.to_lowercase());
+        class_matches && title_matches
+    })
+}
+
+/// Applies `Config::window_rules` whenever the focused window changes
+pub struct WindowWatcher {
+    config: Arc<Mutex<Config>>,
+    action_runner: ActionRunner,
+    listener: ListenerControl,
+    app_sender: Sender<AppMessage>,
+    /// Target of the rule that is applied right now
+    current: Option<RuleTarget>,
+    /// Profile to go back to once no rule matches
+    restore: Option<String>,
+}
+
+impl WindowWatcher {
+    pub fn new(
+        config: Arc<Mutex<Config>>,
+        action_runner: ActionRunner,
+        listener: ListenerControl,
+        app_sender: Sender<AppMessage>,
+    ) -> Self {
+        WindowWatcher {
+            config,
+            action_runner,
+            listener,
+            app_sender,
+            current: None,
+            restore: None,
+        }
+    }
+
+    /// Watch the focused window (blocking call)
+    #[cfg(target_os = "linux")]
+    pub fn start(mut self) {
+        use log::warn;
+        use std::time::Duration;
+
+        loop {
+            let mut monitor = match x11::ActiveWindowMonitor::connect() {
+                Ok(monitor) => monitor,
+                Err(e) => {
+                    // Wayland sessions don't expose the focused window
+                    warn!("{}, window rules are disabled", e);
+                    return;
+                }
+            };
+
+            let result = loop {
+                match monitor.active_window() {
+                    Ok(window) => self.apply(window.as_ref()),
+                    Err(e) => break e,
+                }
+                if let Err(e) = monitor.wait_for_change() {
+                    break e;
+                }
+            };
+
+            warn!("Lost the X11 connection: {}, reconnecting", result);
+            self.apply(None);
+            std::thread::sleep(Duration::from_secs(5));
+        }
+    }
+
+    /// Watch the focused window (blocking call)
+    #[cfg(target_os = "windows")]
+    pub fn start(self) {
+        log::warn!("Window rules are only supported on X11");
+    }
+
+    /// Switch to the target of the rule matching `window`, or back
+    fn apply(&mut self, window: Option<&WindowInfo>) {
+        let rule = {
+            let config = self.config.lock().unwrap();
+            window
+                .and_then(|window| find_rule(&config.window_rules, window))
+                .cloned()
+        };
+        let target = rule.as_ref().map(|rule| rule.target.clone());
+        if target == self.current {
+            return;
+        }
+
+        match &target {
+            Some(RuleTarget::Profile(name)) => {
+                info!("Window rule: switching to profile '{}'", name);
+                if self.restore.is_none() {
+                    self.restore = Some(self.config.lock().unwrap().active_profile.clone());
+                }
+                self.listener.set_suspended(false);
+                self.action_runner.switch_profile(Some(name));
+            }
+            Some(RuleTarget::Disable) => {
+                info!("Window rule: disabling hotkeys");
+                self.listener.set_suspended(true);
+            }
+            None => {
+                info!("No window rule matches anymore");
+                self.listener.set_suspended(false);
+                if let Some(name) = self.restore.take() {
+                    self.action_runner.switch_profile(Some(&name));
+                }
+            }
+        }
+
+        let description = rule.map(|rule| describe(&rule));
+        let _ = self.app_sender.send(AppMessage::WindowRule(description));
+        self.current = target;
+    }
+}
+
+/// Short text for the status line, e.g. "Steam → hotkeys disabled"
+fn describe(rule: &WindowRule) -> String {
+    let window = match (rule.class.is_empty(), rule.title.is_empty()) {
+        (false, false) => format!("{} \"{}\"", rule.class, rule.title),
+        (false, true) => rule.class.clone(),
+        _ => format!("\"{}\"", rule.title),
+    };
+    match &rule.target {
+        RuleTarget::Profile(name) => format!("{} → profile '{}'", window, name),
+        RuleTarget::Disable => format!("{} → hotkeys disabled", window),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(instance: &str, class: &str, title: &str) -> WindowInfo {
+        WindowInfo {
+            instance: instance.to_string(),
+            class: class.to_string(),
+            title: title.to_string(),
+        }
+    }
+
+    fn rule(class: &str, title: &str, target: RuleTarget) -> WindowRule {
+        WindowRule {
+            class: class.to_string(),
+            title: title.to_string(),
+            target,
+        }
+    }
+
+    #[test]
+    fn matches_either_part_of_the_class() {
+        let rules = [rule("code", "", RuleTarget::Profile("Coding".to_string()))];
+        assert!(find_rule(&rules, &window("code", "Code", "main.rs")).is_some());
+        assert!(find_rule(&rules, &window("code-oss", "CODE", "main.rs")).is_some());
+        assert!(find_rule(&rules, &window("codex", "Codex", "main.rs")).is_none());
+    }
+
+    #[test]
+    fn title_is_a_substring_ignoring_case() {
+        let rules = [rule("", "youtube", RuleTarget::Disable)];
+        assert!(find_rule(
+            &rules,
+            &window("firefox", "firefox", "Music - YouTube — Mozilla Firefox")
+        )
+        .is_some());
+        assert!(find_rule(&rules, &window("firefox", "firefox", "Mozilla Firefox")).is_none());
+    }
+
+    #[test]
+    fn class_and_title_must_both_match() {
+        let rules = [rule("firefox", "YouTube", RuleTarget::Disable)];
+        assert!(find_rule(&rules, &window("firefox", "firefox", "YouTube")).is_some());
+        assert!(find_rule(&rules, &window("chromium", "Chromium", "YouTube")).is_none());
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rules = [
+            rule("", "", RuleTarget::Disable),
+            rule("steam_app_42", "", RuleTarget::Disable),
+            rule("steam_app_42", "", RuleTarget::Profile("Games".to_string())),
+        ];
+        let found = find_rule(&rules, &window("steam_app_42", "steam_app_42", "Some Game"));
+        assert_eq!(found, Some(&rules[1]));
+    }
+}
@@ -0,0 +1,329 @@
+use log::{info, warn};
+use x11rb::connection::Connection;
+use x11rb::errors::ReplyError;
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt, EventMask, Window,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+
+use super::WindowInfo;
+
+/// Watches `_NET_ACTIVE_WINDOW` on the root window, which is
+/// maintained by EWMH compliant window managers
+pub struct ActiveWindowMonitor {
+    connection: RustConnection,
+    root: Window,
+    net_active_window: Atom,
+    net_wm_name: Atom,
+    utf8_string: Atom,
+    /// Focused window, watched for title changes
+    active: Option<Window>,
+}
+
+impl ActiveWindowMonitor {
+    /// Connect to the display in `$DISPLAY`
+    pub fn connect() -> Result<Self, String> {
+        let (connection, screen) =
+            x11rb::connect(None).map_err(|e| format!("Failed to connect to X11: {}", e))?;
+        let root = connection.setup().roots[screen].root;
+
+        let atom = |name: &[u8]| -> Result<Atom, String> {
+            Ok(connection
+                .intern_atom(false, name)
+                .map_err(|e| e.to_string())?
+                .reply()
+                .map_err(|e| e.to_string())?
+                .atom)
+        };
+        let net_active_window = atom(b"_NET_ACTIVE_WINDOW")?;
+        let net_wm_name = atom(b"_NET_WM_NAME")?;
+        let utf8_string = atom(b"UTF8_STRING")?;
+
+        // Get told when _NET_ACTIVE_WINDOW changes
+        connection
+            .change_window_attributes(
+                root,
+                &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+            )
+            .map_err(|e| e.to_string())?
+            .check()
+            .map_err(|e| e.to_string())?;
+
+        info!("Watching the active X11 window");
+
+        Ok(ActiveWindowMonitor {
+            connection,
+            root,
+            net_active_window,
+            net_wm_name,
+            utf8_string,
+            active: None,
+        })
+    }
+
+    /// Class and title of the focused window, `None` if no window has the focus
+    pub fn active_window(&mut self) -> Result<Option<WindowInfo>, String> {
+        let window = self.property_window(self.root, self.net_active_window)?;
+
+        if window != self.active {
+            // Title changes of the new window are reported too
+            if let Some(window) = window {
+                let watch = ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE);
+                if let Ok(cookie) = self.connection.change_window_attributes(window, &watch) {
+                    // The window may be gone already, it is checked below
+                    let _ = cookie.check();
+                }
+            }
+            self.active = window;
+        }
+
+        let Some(window) = window else {
+            return Ok(None);
+        };
+
+        match self.window_info(window) {
+            Ok(info) => Ok(Some(info)),
+            // Closed between the event and the query
+            Err(ReplyError::X11Error(_)) => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Block until the focused window or its title changes
+    pub fn wait_for_change(&mut self) -> Result<(), String> {
+        loop {
+            let event = self
+                .connection
+                .wait_for_event()
+                .map_err(|e| e.to_string())?;
+
+            if let Event::PropertyNotify(event) = event {
+                let active_changed =
+                    event.window == self.root && event.atom == self.net_active_window;
+                let title_changed = Some(event.window) == self.active
+                    && (event.atom == self.net_wm_name
+                        || event.atom == u32::from(AtomEnum::WM_NAME));
+                if active_changed || title_changed {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Window stored in a WINDOW property, `None` if it is unset or 0
+    fn property_window(&self, window: Window, property: Atom) -> Result<Option<Window>, String> {
+        let reply = self
+            .connection
+            .get_property(false, window, property, AtomEnum::WINDOW, 0, 1)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| e.to_string())?;
+
+        Ok(reply
+            .value32()
+            .and_then(|mut values| values.next())
+            .filter(|window| *window != 0))
+    }
+
+    fn window_info(&self, window: Window) -> Result<WindowInfo, ReplyError> {
+        // WM_CLASS is "instance\0class\0"
+        let class =
+            self.property_bytes(window, AtomEnum::WM_CLASS.into(), AtomEnum::STRING.into())?;
+        let mut parts = class
+            .split(|byte| *byte == 0)
+            .map(|part| String::from_utf8_lossy(part).into_owned());
+        let instance = parts.next().unwrap_or_default();
+        let class = parts.next().unwrap_or_default();
+
+        let mut title = self.property_bytes(window, self.net_wm_name, self.utf8_string)?;
+        if title.is_empty() {
+            title =
+                self.property_bytes(window, AtomEnum::WM_NAME.into(), AtomEnum::STRING.into())?;
+        }
+
+        Ok(WindowInfo {
+            instance,
+            class,
+            title: String::from_utf8_lossy(&title).into_owned(),
+        })
+    }
+
+    fn property_bytes(
+        &self,
+        window: Window,
+        property: Atom,
+        kind: Atom,
+    ) -> Result<Vec<u8>, ReplyError> {
+        let reply = self
+            .connection
+            .get_property(false, window, property, kind, 0, 1024)?
+            .reply()?;
+        if reply.format != 8 {
+            if reply.format != 0 {
+                warn!(
+                    "Unexpected format {} of window property {}",
+                    reply.format, property
+                );
+            }
+            return Ok(Vec::new());
+        }
+        Ok(reply.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! These tests need an X server, e.g. `xvfb-run cargo test`.
+    //! Without `$DISPLAY` they pass without checking anything.
+
+    use super::*;
+    use std::sync::Mutex;
+    use x11rb::protocol::xproto::{CreateWindowAux, PropMode, WindowClass};
+    use x11rb::wrapper::ConnectionExt as _;
+
+    /// Tests share the root window's _NET_ACTIVE_WINDOW, so they take turns
+    static X_SERVER: Mutex<()> = Mutex::new(());
+
+    /// A second client that creates windows and plays the window manager
+    struct DummyWindows {
+        connection: RustConnection,
+        root: Window,
+        net_active_window: Atom,
+        net_wm_name: Atom,
+        utf8_string: Atom,
+    }
+
+    impl DummyWindows {
+        fn connect() -> Option<Self> {
+            std::env::var_os("DISPLAY")?;
+            let (connection, screen) = x11rb::connect(None).ok()?;
+            let root = connection.setup().roots[screen].root;
+            let atom = |name: &[u8]| {
+                connection
+                    .intern_atom(false, name)
+                    .unwrap()
+                    .reply()
+                    .unwrap()
+                    .atom
+            };
+            Some(DummyWindows {
+                net_active_window: atom(b"_NET_ACTIVE_WINDOW"),
+                net_wm_name: atom(b"_NET_WM_NAME"),
+                utf8_string: atom(b"UTF8_STRING"),
+                connection,
+                root,
+            })
+        }
+
+        fn create(&self, instance: &str, class: &str, title: &str) -> Window {
+            let window = self.connection.generate_id().unwrap();
+            self.connection
+                .create_window(
+                    0,
+                    window,
+                    self.root,
+                    0,
+                    0,
+                    100,
+                    100,
+                    0,
+                    WindowClass::INPUT_OUTPUT,
+                    0,
+                    &CreateWindowAux::new(),
+                )
+                .unwrap();
+            let wm_class = format!("{}\0{}\0", instance, class);
+            self.connection
+                .change_property8(
+                    PropMode::REPLACE,
+                    window,
+                    AtomEnum::WM_CLASS,
+                    AtomEnum::STRING,
+                    wm_class.as_bytes(),
+                )
+                .unwrap();
+            self.set_title(window, title);
+            window
+        }
+
+        fn set_title(&self, window: Window, title: &str) {
+            self.connection
+                .change_property8(
+                    PropMode::REPLACE,
+                    window,
+                    self.net_wm_name,
+                    self.utf8_string,
+                    title.as_bytes(),
+                )
+                .unwrap();
+            self.connection.flush().unwrap();
+        }
+
+        fn activate(&self, window: Window) {
+            self.connection
+                .change_property32(
+                    PropMode::REPLACE,
+                    self.root,
+                    self.net_active_window,
+                    AtomEnum::WINDOW,
+                    &[window],
+                )
+                .unwrap();
+            self.connection.flush().unwrap();
+        }
+    }
+
+    #[test]
+    fn reports_active_window_and_title_changes() {
+        let _turn = X_SERVER.lock().unwrap();
+        let Some(dummy) = DummyWindows::connect() else {
+            eprintln!("No X server, skipping");
+            return;
+        };
+        let editor = dummy.create("code", "Code", "main.rs - Visual Studio Code");
+        let game = dummy.create("steam_app_42", "steam_app_42", "Some Game");
+
+        dummy.activate(editor);
+        let mut monitor = ActiveWindowMonitor::connect().unwrap();
+        let info = monitor.active_window().unwrap().unwrap();
+        assert_eq!(info.instance, "code");
+        assert_eq!(info.class, "Code");
+        assert_eq!(info.title, "main.rs - Visual Studio Code");
+
+        dummy.activate(game);
+        monitor.wait_for_change().unwrap();
+        assert_eq!(
+            monitor.active_window().unwrap().unwrap().class,
+            "steam_app_42"
+        );
+
+        dummy.set_title(game, "Some Game - Paused");
+        monitor.wait_for_change().unwrap();
+        assert_eq!(
+            monitor.active_window().unwrap().unwrap().title,
+            "Some Game - Paused"
+        );
+
+        dummy.activate(0);
+        monitor.wait_for_change().unwrap();
+        assert_eq!(monitor.active_window().unwrap(), None);
+    }
+
+    #[test]
+    fn closed_window_counts_as_none() {
+        let _turn = X_SERVER.lock().unwrap();
+        let Some(dummy) = DummyWindows::connect() else {
+            eprintln!("No X server, skipping");
+            return;
+        };
+        let window = dummy.create("gone", "Gone", "Gone");
+        dummy.activate(window);
+        dummy.connection.destroy_window(window).unwrap();
+        // Round trip, so the window is gone before the monitor looks
+        dummy.connection.get_input_focus().unwrap().reply().unwrap();
+
+        let mut monitor = ActiveWindowMonitor::connect().unwrap();
+        assert_eq!(monitor.active_window().unwrap(), None);
+    }
+}